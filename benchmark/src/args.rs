@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 use chrono::Utc;
-use clap::Parser;
+use clap::{ArgMatches, Parser};
 use tokio::fs;
 
 use crate::ins::InstructionStack;
@@ -20,10 +20,10 @@ pub struct Args {
 }
 
 impl Args {
-    pub(super) async fn execute(self) -> Result<()> {
+    pub(super) async fn execute(self, matches: &ArgMatches) -> Result<()> {
         let mut stack = InstructionStack::try_new(self.common).await?;
 
-        let prog = self.command.to_instructions().await?;
+        let prog = self.command.to_instructions(matches).await?;
         let result = stack.run(prog).await;
 
         let value = stack.cleanup().await?;
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use cdl_catalog::DatasetCatalog;
+use clap::{ArgMatches, Parser};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::ins::{self, Instruction};
+
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct IngestArgs {
+    #[command(flatten)]
+    pub catalog: DatasetCatalog,
+
+    #[arg(long, default_value_t = 4096)]
+    pub file_size: usize,
+
+    #[arg(long, default_value_t = 10_000)]
+    pub num_files: usize,
+
+    /// The numbers of concurrent file readers to compare.
+    #[arg(long, value_delimiter = ',', default_value = "1,8")]
+    pub read_threads: Vec<usize>,
+
+    #[arg(long, default_value = "./ingest")]
+    pub work_dir: PathBuf,
+}
+
+impl IngestArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn to_instructions(
+        mut self,
+        matches: &ArgMatches,
+    ) -> Result<Vec<Box<dyn Instruction>>> {
+        self.catalog.load_profile(matches)?;
+        let Self {
+            catalog,
+            file_size,
+            num_files,
+            read_threads,
+            work_dir,
+        } = self;
+
+        let src = work_dir.join("src");
+        let mut prog: Vec<Box<dyn Instruction>> = vec![
+            Box::new(ins::static_metric::Instruction {
+                key: "kind",
+                value: Value::String("ingest_files".into()),
+            }),
+            Box::new(ins::static_metric::Instruction {
+                key: "file_size",
+                value: Value::Number(file_size.into()),
+            }),
+            Box::new(ins::static_metric::Instruction {
+                key: "num_files",
+                value: Value::Number(num_files.into()),
+            }),
+            Box::new(ins::generate_files::Instruction {
+                dir: src.clone(),
+                file_size,
+                num_files,
+            }),
+        ];
+        for max_read_threads in read_threads {
+            prog.push(Box::new(ins::copy_files::Instruction {
+                catalog: DatasetCatalog {
+                    max_read_threads,
+                    ..catalog.clone()
+                },
+                dst: work_dir.join(format!("dst-{max_read_threads}")),
+                label: format!("ingest_files_{max_read_threads}"),
+                src: src.clone(),
+            }));
+        }
+        Ok(prog)
+    }
+}
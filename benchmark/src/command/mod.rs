@@ -1,21 +1,30 @@
 pub mod create;
+pub mod ingest;
 pub mod sync;
 
 use anyhow::Result;
-use clap::Subcommand;
+use clap::{ArgMatches, Subcommand};
 
 use crate::ins::Instruction;
 
 #[derive(Clone, Debug, PartialEq, Subcommand)]
 pub enum Command {
     Create(self::create::CreateArgs),
+    Ingest(self::ingest::IngestArgs),
     Sync(self::sync::SyncArgs),
 }
 
 impl Command {
-    pub(super) async fn to_instructions(self) -> Result<Vec<Box<dyn Instruction>>> {
+    pub(super) async fn to_instructions(
+        self,
+        matches: &ArgMatches,
+    ) -> Result<Vec<Box<dyn Instruction>>> {
+        // The catalog args are flattened into each subcommand
+        let matches = matches.subcommand().map_or(matches, |(_, matches)| matches);
+
         match self {
             Self::Create(args) => args.to_instructions().await,
+            Self::Ingest(args) => args.to_instructions(matches).await,
             Self::Sync(args) => args.to_instructions().await,
         }
     }
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::GlobalPath;
+use chrono::Utc;
+use tokio::fs;
+use tracing::{info, instrument, Level};
+
+use super::InstructionStack;
+
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub catalog: DatasetCatalog,
+    pub dst: PathBuf,
+    pub label: String,
+    pub src: PathBuf,
+}
+
+#[async_trait]
+impl super::Instruction for Instruction {
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    async fn apply(&self, stack: &mut InstructionStack) -> Result<()> {
+        let Self {
+            catalog,
+            dst,
+            label,
+            src,
+        } = self;
+        let InstructionStack { metrics, .. } = stack;
+        info!("copy_files: {label}: {src:?} -> {dst:?}");
+
+        let fs = GlobalPath::from_local(src.clone())
+            .open(catalog.clone())
+            .await?;
+        let dst = GlobalPath::from_local(dst.clone());
+
+        metrics
+            .write(
+                format!("{label}_timestamp_begin"),
+                Utc::now().timestamp_micros(),
+            )
+            .await;
+        fs.copy_to(&dst).await?;
+        metrics
+            .write(
+                format!("{label}_timestamp_end"),
+                Utc::now().timestamp_micros(),
+            )
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    async fn delete(&self, _stack: &mut InstructionStack) -> Result<()> {
+        let Self { dst, label, .. } = self;
+        info!("copy_files: {label}: delete {dst:?}");
+
+        match fs::remove_dir_all(dst).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == ::std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
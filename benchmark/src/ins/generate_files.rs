@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use tokio::fs;
+use tracing::{info, instrument, Level};
+
+use super::InstructionStack;
+
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub dir: PathBuf,
+    pub file_size: usize,
+    pub num_files: usize,
+}
+
+#[async_trait]
+impl super::Instruction for Instruction {
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    async fn apply(&self, stack: &mut InstructionStack) -> Result<()> {
+        let Self {
+            dir,
+            file_size,
+            num_files,
+        } = self;
+        let InstructionStack { args, .. } = stack;
+        info!("generate_files: create {num_files} files into {dir:?}");
+
+        stream::iter(0..*num_files)
+            .map(|index| async move {
+                let dir = dir.join(format!("{:04}", index / FILES_PER_DIR));
+                fs::create_dir_all(&dir).await?;
+
+                let data = vec![(index % 251) as u8; *file_size];
+                fs::write(dir.join(format!("{index:09}.bin")), data).await
+            })
+            .buffer_unordered(args.num_threads)
+            .try_collect::<()>()
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    async fn delete(&self, _stack: &mut InstructionStack) -> Result<()> {
+        let Self { dir, .. } = self;
+        info!("generate_files: delete {dir:?}");
+
+        fs::remove_dir_all(dir).await.map_err(Into::into)
+    }
+}
+
+const FILES_PER_DIR: usize = 1000;
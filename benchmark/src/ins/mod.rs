@@ -1,9 +1,11 @@
 // pub mod branch;
 pub mod checkout_context;
+pub mod copy_files;
 pub mod create_datasets;
 pub mod create_ponds;
 pub mod create_syncs;
 pub mod elapsed_time;
+pub mod generate_files;
 pub mod static_metric;
 
 use std::{mem, sync::Arc};
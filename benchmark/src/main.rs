@@ -3,23 +3,24 @@ mod command;
 mod ins;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
 use tracing::{debug, error, info};
 
 #[::tokio::main]
 async fn main() {
-    let args = self::args::Args::parse();
+    let matches = self::args::Args::command().get_matches();
+    let args = self::args::Args::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
 
     ::cdl_k8s_core::otel::init_once();
     info!("Welcome to Connected Data Lake Benchmark!");
 
-    match try_main(args).await {
+    match try_main(args, matches).await {
         Ok(()) => info!("Done"),
         Err(error) => error!("{error}"),
     }
 }
 
-async fn try_main(args: self::args::Args) -> Result<()> {
+async fn try_main(args: self::args::Args, matches: ArgMatches) -> Result<()> {
     debug!("Starting Connected Data Lake CLI Benchmark");
-    args.execute().await
+    args.execute(&matches).await
 }
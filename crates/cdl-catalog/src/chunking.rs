@@ -0,0 +1,28 @@
+use std::fmt;
+
+use clap::ValueEnum;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ChunkingMode {
+    /// Split the files at every `max_chunk_size` bytes.
+    #[default]
+    Fixed,
+    /// Split the files at the content-defined boundaries (FastCDC),
+    /// and store the identical chunks only once in the chunk store.
+    /// The chunks are up to `max_chunk_size` (4 MiB if 0),
+    /// and about a quarter of it on average.
+    ContentDefined,
+}
+
+impl fmt::Display for ChunkingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => value.get_name().fmt(f),
+            None => Ok(()),
+        }
+    }
+}
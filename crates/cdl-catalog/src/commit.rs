@@ -0,0 +1,111 @@
+use std::{fmt, sync::Arc};
+
+use clap::ValueEnum;
+use lance_table::io::commit::{CommitHandler, ConditionalPutCommitHandler, UnsafeCommitHandler};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum CommitMode {
+    /// Write the manifests with conditional PUT requests,
+    /// so that the concurrent writers fail and retry on conflicts.
+    /// The object storage should support `If-None-Match` or `If-Match` headers.
+    #[default]
+    ConditionalPut,
+    /// Overwrite the manifests without any locks.
+    /// It is only safe for the single writer.
+    Unsafe,
+}
+
+impl fmt::Display for CommitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => value.get_name().fmt(f),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CommitMode {
+    pub(crate) fn commit_handler(&self) -> Arc<dyn CommitHandler> {
+        match self {
+            Self::ConditionalPut => Arc::new(ConditionalPutCommitHandler),
+            Self::Unsafe => Arc::new(UnsafeCommitHandler),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{RecordBatch, RecordBatchIterator, UInt64Array},
+        datatypes::{DataType, Field, Schema},
+    };
+    use lance::{
+        dataset::{WriteMode, WriteParams},
+        Dataset,
+    };
+    use tempfile::TempDir;
+
+    use crate::DatasetCatalog;
+
+    use super::*;
+
+    const NUM_ROWS: u64 = 16;
+    const NUM_WRITERS: u64 = 8;
+
+    async fn append(catalog: &DatasetCatalog, uri: &str, writer: u64, mode: WriteMode) {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "value",
+            DataType::UInt64,
+            false,
+        )]));
+        let values = (0..NUM_ROWS).map(|index| writer * NUM_ROWS + index);
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt64Array::from_iter_values(values))],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+
+        let params = WriteParams {
+            commit_handler: Some(catalog.commit_handler()),
+            mode,
+            ..Default::default()
+        };
+        Dataset::write(reader, uri, Some(params))
+            .await
+            .expect("failed to write the dataset");
+    }
+
+    #[::tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_appends_with_conditional_put() {
+        let temp_dir = TempDir::new().unwrap();
+        let uri = temp_dir.path().to_string_lossy().to_string();
+
+        let catalog = DatasetCatalog {
+            commit_mode: CommitMode::ConditionalPut,
+            ..Default::default()
+        };
+        append(&catalog, &uri, 0, WriteMode::Create).await;
+
+        let writers = (1..=NUM_WRITERS).map(|writer| {
+            let catalog = catalog.clone();
+            let uri = uri.clone();
+            ::tokio::spawn(async move { append(&catalog, &uri, writer, WriteMode::Append).await })
+        });
+        for writer in writers.collect::<Vec<_>>() {
+            writer.await.expect("failed to join the writer");
+        }
+
+        // No commits should be lost
+        let dataset = Dataset::open(&uri).await.unwrap();
+        assert_eq!(
+            dataset.count_rows(None).await.unwrap() as u64,
+            (NUM_WRITERS + 1) * NUM_ROWS,
+        );
+        assert_eq!(dataset.version().version, NUM_WRITERS + 1);
+    }
+}
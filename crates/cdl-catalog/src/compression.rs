@@ -0,0 +1,27 @@
+use std::fmt;
+
+use clap::ValueEnum;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Compression {
+    /// Store the file contents as-is.
+    #[default]
+    None,
+    /// Compress the file contents with LZ4, trading the ratio for the speed.
+    Lz4,
+    /// Compress the file contents with Zstandard.
+    Zstd,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => value.get_name().fmt(f),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Error, Result};
+
+/// Named catalog profiles loaded from a config file.
+///
+/// ```toml
+/// default_profile = "local"
+///
+/// [profiles.local]
+/// s3_endpoint = "http://object-storage"
+///
+/// [profiles.pond-b]
+/// s3_endpoint = "http://minio.pond-b:9000"
+/// s3_access_key = "..."
+/// s3_secret_key = "..."
+/// max_cache_size = 1073741824
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CatalogConfig {
+    pub default_profile: Option<String>,
+    pub profiles: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl FromStr for CatalogConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let table: ::toml::Table = s.parse().context("Invalid config file")?;
+
+        let mut config = Self::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "default_profile" => match value {
+                    ::toml::Value::String(name) => config.default_profile = Some(name),
+                    _ => bail!("Invalid config value: {key:?} should be a string"),
+                },
+                "profiles" => match value {
+                    ::toml::Value::Table(profiles) => {
+                        for (name, profile) in profiles {
+                            let profile = parse_profile(&name, profile)?;
+                            config.profiles.insert(name, profile);
+                        }
+                    }
+                    _ => bail!("Invalid config value: {key:?} should be a table"),
+                },
+                _ => bail!("Invalid config key: {key:?}"),
+            }
+        }
+
+        if let Some(name) = config.default_profile.as_ref() {
+            if !config.profiles.contains_key(name) {
+                bail!("No such default catalog profile: {name:?}")
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl CatalogConfig {
+    pub const KEY_CONFIG: &'static str = "CDL_CONFIG";
+
+    /// Return `$XDG_CONFIG_HOME/cdl/config.toml`, or `~/.config/cdl/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base_dir.join("cdl").join("config.toml"))
+    }
+
+    /// Load the given config file, or the default one if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Option<Self>> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(None),
+            },
+        };
+
+        fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read the config file: {path:?}"))?
+            .parse()
+            .with_context(|| format!("Failed to parse the config file: {path:?}"))
+            .map(Some)
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&[(String, String)]> {
+        self.profiles.get(name).map(Vec::as_slice)
+    }
+}
+
+fn parse_profile(name: &str, profile: ::toml::Value) -> Result<Vec<(String, String)>> {
+    let profile = match profile {
+        ::toml::Value::Table(profile) => profile,
+        _ => bail!("Invalid catalog profile: {name:?} should be a table"),
+    };
+
+    profile
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                ::toml::Value::String(value) => value,
+                ::toml::Value::Integer(value) => value.to_string(),
+                ::toml::Value::Float(value) => value.to_string(),
+                ::toml::Value::Boolean(value) => value.to_string(),
+                _ => bail!("Invalid value type of catalog profile {name:?}: {key:?}"),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches};
+    use tempfile::NamedTempFile;
+
+    use crate::DatasetCatalog;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+default_profile = "local"
+
+[profiles.local]
+s3_endpoint = "http://localhost:9000"
+s3_region = "local-region"
+
+[profiles.pond-b]
+s3_endpoint = "http://minio.pond-b:9000"
+s3_access_key = "pond-b-access-key"
+max_cache_size = 1024
+"#;
+
+    fn write_config() -> NamedTempFile {
+        let file = NamedTempFile::new().expect("failed to create the config file");
+        fs::write(file.path(), CONFIG).expect("failed to write the config file");
+        file
+    }
+
+    fn parse_catalog(args: &[&str]) -> DatasetCatalog {
+        parse_catalog_with_vars(args, &[])
+    }
+
+    /// Parse the catalog with the given profile env vars instead of the process ones,
+    /// which are shared by the concurrent tests.
+    fn parse_catalog_with_vars(args: &[&str], vars: &[(&str, &str)]) -> DatasetCatalog {
+        let matches = DatasetCatalog::command()
+            .try_get_matches_from(::std::iter::once("cdl").chain(args.iter().copied()))
+            .expect("failed to parse args");
+        let mut catalog =
+            DatasetCatalog::from_arg_matches(&matches).expect("failed to parse catalog");
+        catalog
+            .load_profile_with_vars(
+                &matches,
+                vars.iter()
+                    .map(|&(key, value)| (key.to_string(), value.to_string())),
+            )
+            .expect("failed to load the profile");
+        catalog
+    }
+
+    #[test]
+    fn parse_config_file() {
+        let config: CatalogConfig = CONFIG.parse().expect("failed to parse config");
+
+        assert_eq!(config.default_profile.as_deref(), Some("local"));
+        assert_eq!(
+            config.profile("pond-b"),
+            Some(
+                &[
+                    ("max_cache_size".into(), "1024".into()),
+                    ("s3_access_key".into(), "pond-b-access-key".into()),
+                    ("s3_endpoint".into(), "http://minio.pond-b:9000".into()),
+                ][..]
+            ),
+        );
+        assert_eq!(config.profile("unknown"), None);
+    }
+
+    #[test]
+    fn reject_invalid_config_file() {
+        assert!("unknown = 1".parse::<CatalogConfig>().is_err());
+        assert!("default_profile = \"unknown\""
+            .parse::<CatalogConfig>()
+            .is_err());
+        assert!("[profiles]\nfoo = 1".parse::<CatalogConfig>().is_err());
+        assert!("[profiles.foo]\nbar = [1, 2]"
+            .parse::<CatalogConfig>()
+            .is_err());
+    }
+
+    #[test]
+    fn merge_profiles_with_flags() {
+        let file = write_config();
+        let config = file.path().to_str().unwrap();
+
+        // the default profile is applied
+        let catalog = parse_catalog(&["--config", config]);
+        assert_eq!(catalog.s3_endpoint.as_str(), "http://localhost:9000/");
+        assert_eq!(catalog.s3_region, "local-region");
+
+        // the selected profile is applied
+        let catalog = parse_catalog(&["--config", config, "--profile", "pond-b"]);
+        assert_eq!(catalog.s3_endpoint.as_str(), "http://minio.pond-b:9000/");
+        assert_eq!(catalog.s3_access_key.as_deref(), Some("pond-b-access-key"));
+        assert_eq!(catalog.max_cache_size, 1024);
+
+        // the flags take precedence over the profile
+        let catalog = parse_catalog(&[
+            "--config",
+            config,
+            "--profile",
+            "pond-b",
+            "--max-cache-size",
+            "2048",
+        ]);
+        assert_eq!(catalog.s3_endpoint.as_str(), "http://minio.pond-b:9000/");
+        assert_eq!(catalog.max_cache_size, 2048);
+
+        // the named profiles are applied on top of the current catalog
+        let catalog = catalog.with_profile("local").unwrap();
+        assert_eq!(catalog.s3_endpoint.as_str(), "http://localhost:9000/");
+        assert_eq!(catalog.s3_region, "local-region");
+        assert_eq!(catalog.max_cache_size, 2048);
+        assert!(catalog.with_profile("unknown").is_err());
+    }
+
+    #[test]
+    fn drop_credentials_of_other_endpoints() {
+        let file = write_config();
+        let config = file.path().to_str().unwrap();
+
+        let catalog = parse_catalog(&[
+            "--config",
+            config,
+            "--s3-access-key",
+            "base-key",
+            "--s3-secret-key",
+            "base-secret",
+            "--s3-role-arn",
+            "base-role",
+        ]);
+        assert_eq!(catalog.s3_endpoint.as_str(), "http://localhost:9000/");
+
+        // the credentials are kept on the same endpoint
+        let local = catalog.with_profile("local").unwrap();
+        assert_eq!(local.s3_access_key.as_deref(), Some("base-key"));
+        assert_eq!(local.s3_secret_key.as_deref(), Some("base-secret"));
+
+        // the credentials are dropped on another endpoint, except the profile's own ones
+        let pond_b = catalog.with_profile("pond-b").unwrap();
+        assert_eq!(pond_b.s3_endpoint.as_str(), "http://minio.pond-b:9000/");
+        assert_eq!(pond_b.s3_access_key.as_deref(), Some("pond-b-access-key"));
+        assert_eq!(pond_b.s3_secret_key, None);
+        assert_eq!(pond_b.s3_role_arn, None);
+        assert!(pond_b.s3_credential_provider().is_err());
+
+        // the credentials of the profile are not leaked back to the base endpoint
+        let local = pond_b.with_profile("local").unwrap();
+        assert_eq!(local.s3_access_key, None);
+    }
+
+    #[test]
+    fn merge_profiles_with_env() {
+        let file = write_config();
+        let config = file.path().to_str().unwrap();
+
+        let catalog = parse_catalog_with_vars(
+            &["--config", config, "--profile", "pond-b"],
+            &[
+                ("CDL_PROFILE_POND_B_MAX_CACHE_SIZE", "4096"),
+                ("CDL_PROFILE_POND_B_S3_REGION", "pond-b-region"),
+                ("CDL_PROFILE_LOCAL_S3_REGION", "local-region-2"),
+            ],
+        );
+
+        // the profile env vars are added to the profile, taking precedence over the file
+        assert_eq!(catalog.max_cache_size, 4096);
+        assert_eq!(catalog.s3_region, "pond-b-region");
+        assert_eq!(catalog.s3_endpoint.as_str(), "http://minio.pond-b:9000/");
+        assert_eq!(catalog.s3_access_key.as_deref(), Some("pond-b-access-key"));
+
+        // the profile env vars alone define a profile
+        let catalog = parse_catalog_with_vars(
+            &["--config", config, "--profile", "pond-c"],
+            &[("CDL_PROFILE_POND_C_S3_REGION", "pond-c-region")],
+        );
+        assert_eq!(catalog.s3_region, "pond-c-region");
+        assert_eq!(catalog.s3_endpoint.as_str(), "http://object-storage/");
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use object_store::{aws::AwsCredential, CredentialProvider};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum S3CredentialSource {
+    /// Use the `s3_access_key` and `s3_secret_key`.
+    #[default]
+    Static,
+    /// Read a profile of the AWS shared credentials file.
+    Profile,
+    /// Exchange a web identity token file (e.g. k8s service account tokens)
+    /// with the STS `AssumeRoleWithWebIdentity` API.
+    WebIdentity,
+    /// Re-read a mounted k8s secret directory,
+    /// which has the `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` files.
+    Secret,
+}
+
+impl fmt::Display for S3CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => value.get_name().fmt(f),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The catalog values which select the credentials and where they are sent.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct CredentialKey {
+    pub(crate) access_key: Option<String>,
+    pub(crate) endpoint: ::url::Url,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) profile: String,
+    pub(crate) refresh_interval: u64,
+    pub(crate) role_arn: Option<String>,
+    pub(crate) secret_key: Option<String>,
+    pub(crate) source: S3CredentialSource,
+}
+
+/// Load the credentials once, or whenever they are going to be expired.
+#[async_trait]
+pub(crate) trait CredentialLoader: fmt::Debug + Send + Sync {
+    async fn load(&self) -> Result<(AwsCredential, Option<DateTime<Utc>>)>;
+}
+
+#[derive(Debug)]
+pub(crate) struct RefreshingCredentialProvider<L> {
+    cache: Mutex<Option<(Instant, Arc<AwsCredential>)>>,
+    interval: Duration,
+    loader: L,
+}
+
+impl<L> RefreshingCredentialProvider<L> {
+    pub(crate) fn new(loader: L, interval: Duration) -> Self {
+        Self {
+            cache: Mutex::default(),
+            interval,
+            loader,
+        }
+    }
+}
+
+#[async_trait]
+impl<L> CredentialProvider for RefreshingCredentialProvider<L>
+where
+    L: CredentialLoader,
+{
+    type Credential = AwsCredential;
+
+    async fn get_credential(&self) -> ::object_store::Result<Arc<AwsCredential>> {
+        if let Some((expires_at, credential)) = self.cache.lock().unwrap().as_ref() {
+            if Instant::now() < *expires_at {
+                return Ok(credential.clone());
+            }
+        }
+
+        let (credential, expiration) =
+            self.loader
+                .load()
+                .await
+                .map_err(|error| ::object_store::Error::Generic {
+                    store: "S3",
+                    source: error.into(),
+                })?;
+        let credential = Arc::new(credential);
+
+        // Refresh the credentials a bit earlier than their expiration
+        let ttl = expiration
+            .and_then(|expiration| (expiration - Utc::now()).to_std().ok())
+            .map(|ttl| ttl.saturating_sub(EXPIRATION_MARGIN))
+            .map_or(self.interval, |ttl| ttl.min(self.interval));
+        *self.cache.lock().unwrap() = Some((Instant::now() + ttl, credential.clone()));
+        Ok(credential)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ProfileLoader {
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) profile: String,
+}
+
+impl ProfileLoader {
+    pub(crate) fn load_sync(&self) -> Result<AwsCredential> {
+        let path = match self.path.clone() {
+            Some(path) => path,
+            None => default_shared_credentials_file()?,
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read the AWS credentials file: {path:?}"))?;
+        parse_shared_credentials(&content, &self.profile)
+            .with_context(|| format!("Failed to parse the AWS credentials file: {path:?}"))
+    }
+}
+
+#[async_trait]
+impl CredentialLoader for ProfileLoader {
+    async fn load(&self) -> Result<(AwsCredential, Option<DateTime<Utc>>)> {
+        self.load_sync().map(|credential| (credential, None))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SecretLoader {
+    pub(crate) path: PathBuf,
+}
+
+impl SecretLoader {
+    pub(crate) fn load_sync(&self) -> Result<AwsCredential> {
+        let read = |name: &str| -> Result<Option<String>> {
+            let path = self.path.join(name);
+            match fs::read_to_string(&path) {
+                Ok(value) => Ok(Some(value.trim().to_string())),
+                Err(error) if error.kind() == ::std::io::ErrorKind::NotFound => Ok(None),
+                Err(error) => {
+                    Err(error).with_context(|| format!("Failed to read the secret file: {path:?}"))
+                }
+            }
+        };
+
+        Ok(AwsCredential {
+            key_id: read("AWS_ACCESS_KEY_ID")?
+                .with_context(|| format!("Missing AWS_ACCESS_KEY_ID in {:?}", &self.path))?,
+            secret_key: read("AWS_SECRET_ACCESS_KEY")?
+                .with_context(|| format!("Missing AWS_SECRET_ACCESS_KEY in {:?}", &self.path))?,
+            token: read("AWS_SESSION_TOKEN")?,
+        })
+    }
+}
+
+#[async_trait]
+impl CredentialLoader for SecretLoader {
+    async fn load(&self) -> Result<(AwsCredential, Option<DateTime<Utc>>)> {
+        self.load_sync().map(|credential| (credential, None))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct WebIdentityLoader {
+    pub(crate) client: ::reqwest::Client,
+    pub(crate) endpoint: ::url::Url,
+    pub(crate) role_arn: Option<String>,
+    pub(crate) token_file: PathBuf,
+}
+
+#[async_trait]
+impl CredentialLoader for WebIdentityLoader {
+    async fn load(&self) -> Result<(AwsCredential, Option<DateTime<Utc>>)> {
+        // The token is rotated by the kubelet, so read it every time
+        let token = fs::read_to_string(&self.token_file).with_context(|| {
+            format!(
+                "Failed to read the web identity token: {:?}",
+                &self.token_file
+            )
+        })?;
+
+        let mut params = vec![
+            ("Action", "AssumeRoleWithWebIdentity"),
+            ("DurationSeconds", "3600"),
+            ("RoleSessionName", "connected-data-lake"),
+            ("Version", "2011-06-15"),
+            ("WebIdentityToken", token.trim()),
+        ];
+        if let Some(role_arn) = self.role_arn.as_deref() {
+            params.push(("RoleArn", role_arn));
+        }
+
+        let response = self
+            .client
+            .post(self.endpoint.clone())
+            .form(&params)
+            .send()
+            .await
+            .context("Failed to request the STS credentials")?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            bail!("Failed to assume role with web identity: {status}: {body}")
+        }
+
+        let get = |tag: &str| {
+            find_xml_tag(&body, tag)
+                .with_context(|| format!("Missing {tag} in the STS response"))
+                .map(ToString::to_string)
+        };
+        let credential = AwsCredential {
+            key_id: get("AccessKeyId")?,
+            secret_key: get("SecretAccessKey")?,
+            token: get("SessionToken").ok(),
+        };
+        let expiration =
+            find_xml_tag(&body, "Expiration").and_then(|expiration| expiration.parse().ok());
+        Ok((credential, expiration))
+    }
+}
+
+fn default_shared_credentials_file() -> Result<PathBuf> {
+    if let Some(path) = env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+        return Ok(path.into());
+    }
+    env::var_os("HOME")
+        .map(|home| Path::new(&home).join(".aws").join("credentials"))
+        .context("Cannot find the AWS credentials file: no home directory")
+}
+
+fn parse_shared_credentials(content: &str, profile: &str) -> Result<AwsCredential> {
+    let mut key_id = None;
+    let mut secret_key = None;
+    let mut token = None;
+
+    let mut is_target = false;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let section = section.trim();
+            is_target = section == profile || section.strip_prefix("profile ") == Some(profile);
+            continue;
+        }
+        if !is_target {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = Some(value.trim().to_string());
+            match key.trim() {
+                "aws_access_key_id" => key_id = value,
+                "aws_secret_access_key" => secret_key = value,
+                "aws_session_token" => token = value,
+                _ => continue,
+            }
+        }
+    }
+
+    Ok(AwsCredential {
+        key_id: key_id.with_context(|| format!("Missing aws_access_key_id in {profile:?}"))?,
+        secret_key: secret_key
+            .with_context(|| format!("Missing aws_secret_access_key in {profile:?}"))?,
+        token,
+    })
+}
+
+fn find_xml_tag<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = start + body[start..].find(&format!("</{tag}>"))?;
+    Some(body[start..end].trim())
+}
+
+const EXPIRATION_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+#[cfg(test)]
+mod tests {
+    use crate::DatasetCatalog;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn share_credential_providers() {
+        let catalog = DatasetCatalog {
+            s3_access_key: Some("shared-key".into()),
+            s3_secret_key: Some("shared-secret".into()),
+            ..Default::default()
+        };
+        let provider = catalog.s3_credential_provider().unwrap();
+        assert!(Arc::ptr_eq(
+            &provider,
+            &catalog.clone().s3_credential_provider().unwrap(),
+        ));
+
+        // the other credentials are not shared
+        let other = DatasetCatalog {
+            s3_secret_key: Some("other-secret".into()),
+            ..catalog.clone()
+        };
+        assert!(!Arc::ptr_eq(
+            &provider,
+            &other.s3_credential_provider().unwrap(),
+        ));
+
+        let options = catalog.storage_options_with_credentials().await.unwrap();
+        assert_eq!(
+            options.get("AWS_ACCESS_KEY_ID").map(String::as_str),
+            Some("shared-key"),
+        );
+        assert_eq!(
+            options.get("AWS_SECRET_ACCESS_KEY").map(String::as_str),
+            Some("shared-secret"),
+        );
+        assert!(!catalog
+            .storage_options()
+            .unwrap()
+            .contains_key("AWS_ACCESS_KEY_ID"));
+    }
+
+    #[test]
+    fn parse_shared_credentials_file() {
+        let content = r#"
+[default]
+aws_access_key_id = default-key
+aws_secret_access_key = default-secret
+
+# rotated by the operator
+[profile pond-b]
+aws_access_key_id=pond-b-key
+aws_secret_access_key=pond-b-secret
+aws_session_token = pond-b-token
+"#;
+
+        let credential = parse_shared_credentials(content, "default").unwrap();
+        assert_eq!(credential.key_id, "default-key");
+        assert_eq!(credential.secret_key, "default-secret");
+        assert_eq!(credential.token, None);
+
+        let credential = parse_shared_credentials(content, "pond-b").unwrap();
+        assert_eq!(credential.key_id, "pond-b-key");
+        assert_eq!(credential.secret_key, "pond-b-secret");
+        assert_eq!(credential.token.as_deref(), Some("pond-b-token"));
+
+        assert!(parse_shared_credentials(content, "unknown").is_err());
+    }
+
+    #[test]
+    fn find_sts_response_tags() {
+        let body = "<AssumeRoleWithWebIdentityResponse><Credentials>\
+            <AccessKeyId>key</AccessKeyId><SecretAccessKey>secret</SecretAccessKey>\
+            <Expiration>2024-01-01T00:00:00Z</Expiration>\
+            </Credentials></AssumeRoleWithWebIdentityResponse>";
+
+        assert_eq!(find_xml_tag(body, "AccessKeyId"), Some("key"));
+        assert_eq!(find_xml_tag(body, "SecretAccessKey"), Some("secret"));
+        assert_eq!(find_xml_tag(body, "SessionToken"), None);
+        assert_eq!(
+            find_xml_tag(body, "Expiration").and_then(|s| s.parse::<DateTime<Utc>>().ok()),
+            "2024-01-01T00:00:00Z".parse().ok(),
+        );
+    }
+}
@@ -1,19 +1,40 @@
-use std::{collections::HashMap, fmt, ops, str::FromStr, sync::Arc};
+mod chunking;
+mod commit;
+mod compression;
+mod config;
+mod credential;
+
+use std::{
+    collections::HashMap,
+    fmt, ops,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
 
-use anyhow::{bail, Context, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{parser::ValueSource, ArgMatches, Parser, ValueEnum};
 use lance::{
     dataset::progress::{NoopFragmentWriteProgress, WriteFragmentProgress},
     io::ObjectStoreParams,
 };
-use lance_table::io::commit::{CommitHandler, UnsafeCommitHandler};
+use lance_table::io::commit::CommitHandler;
 use object_store::{
     aws::{AwsCredential, AwsCredentialProvider},
-    StaticCredentialProvider,
+    CredentialProvider, StaticCredentialProvider,
 };
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+use self::credential::{
+    CredentialKey, ProfileLoader, RefreshingCredentialProvider, SecretLoader, WebIdentityLoader,
+};
+pub use self::{
+    chunking::ChunkingMode, commit::CommitMode, compression::Compression, config::CatalogConfig,
+    credential::S3CredentialSource,
+};
+
 macro_rules! get_arg {
     ( $catalog:tt, $name:ident ) => {{
         get_arg($catalog.$name.as_ref(), stringify!($name))
@@ -44,6 +65,65 @@ pub struct DatasetCatalog {
     )]
     pub cache_dir: String,
 
+    /// Chunking mode for splitting the files into the rows.
+    #[arg(
+        global=true, long,
+        env = "CDL_CHUNKING_MODE",
+        value_enum,
+        default_value_t = ChunkingMode::default(),
+    )]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub chunking_mode: ChunkingMode,
+
+    /// Commit mode for writing the dataset manifests.
+    #[arg(
+        global=true, long,
+        env = "CDL_COMMIT_MODE",
+        value_enum,
+        default_value_t = CommitMode::default(),
+    )]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub commit_mode: CommitMode,
+
+    /// Compression codec for the file contents.
+    /// The chunks of the already compressed formats are stored as-is.
+    #[arg(
+        global=true, long,
+        env = "CDL_COMPRESSION",
+        value_enum,
+        default_value_t = Compression::default(),
+    )]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub compression: Compression,
+
+    /// Config file path with named catalog profiles.
+    /// Defaults to `~/.config/cdl/config.toml` if it exists.
+    #[arg(global = true, long, env = "CDL_CONFIG")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub config: Option<PathBuf>,
+
+    /// Encrypt the file names and parent directories as well as the file contents.
+    /// The names are encrypted deterministically with the last key,
+    /// so that the paths can still be looked up while the new keys are prepended.
+    /// It should be enabled only on the new datasets.
+    #[arg(global = true, long, env = "CDL_ENCRYPT_NAMES")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub encrypt_names: bool,
+
+    /// Base64-encoded 256-bit keys for the client-side encryption, separated by commas.
+    /// The first key encrypts the new rows, and all the keys can decrypt the existing ones,
+    /// so that the keys can be rotated.
+    /// The last key also keys the names and the content hashes, so keep it on the rotation.
+    #[arg(global = true, long, env = "CDL_ENCRYPTION_KEY")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub encryption_key: Option<String>,
+
+    /// Key file path for the client-side encryption, with a key per line.
+    /// The keys are appended after the `encryption_key`.
+    #[arg(global = true, long, env = "CDL_ENCRYPTION_KEY_PATH")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub encryption_key_path: Option<PathBuf>,
+
     /// Max file size for each batch file.
     /// The larger the value, the faster the data transfer speed.
     /// It is recommended to use the largest possible value
@@ -72,10 +152,26 @@ pub struct DatasetCatalog {
     )]
     pub max_cache_size: u64,
 
+    /// Max total file size for each upload checkpoint.
+    /// The files are committed whenever the size is reached,
+    /// so that the interrupted uploads can be resumed from the last checkpoint.
+    /// The value 0 commits all files at once.
+    #[arg(
+        global=true, long,
+        env = "CDL_MAX_CHECKPOINT_SIZE",
+        default_value_t = Self::default_max_checkpoint_size(),
+    )]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "DatasetCatalog::default_max_checkpoint_size")
+    )]
+    pub max_checkpoint_size: u64,
+
     /// Max chunk size for each file.
     /// A larger value allows more data to be stored in a row,
     /// but requires the same amount of data to be transmitted when modifying the data.
-    /// The value 0 disables the chunking.
+    /// The value 0 disables the chunking,
+    /// except for the files larger than the `max_buffer_size` (up to 1 GiB).
     #[arg(
         global=true, long,
         env = "CDL_MAX_CHUNK_SIZE",
@@ -87,6 +183,19 @@ pub struct DatasetCatalog {
     )]
     pub max_chunk_size: u64,
 
+    /// Maximum number of files read concurrently from the local filesystem.
+    /// The loaded files are still bounded by the `max_buffer_size` in total.
+    #[arg(
+        global=true, long,
+        env = "CDL_MAX_READ_THREADS",
+        default_value_t = Self::default_max_read_threads(),
+    )]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "DatasetCatalog::default_max_read_threads")
+    )]
+    pub max_read_threads: usize,
+
     /// Maximum number of threads for writing files.
     #[arg(
         global=true, long,
@@ -111,11 +220,54 @@ pub struct DatasetCatalog {
     )]
     pub min_cache_object_size: usize,
 
+    /// Capture the ownership, extended attributes and hardlinks of the local files,
+    /// so that they can be restored on the destination with sufficient privileges.
+    #[arg(global = true, long, env = "CDL_PRESERVE_METADATA")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preserve_metadata: bool,
+
+    /// Catalog profile name in the config file.
+    /// Defaults to the `default_profile` of the config file.
+    #[arg(global = true, long, env = "CDL_PROFILE")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub profile: Option<String>,
+
     /// S3 access key.
     #[arg(global = true, long, env = "AWS_ACCESS_KEY_ID")]
     #[cfg_attr(feature = "serde", serde(default))]
     pub s3_access_key: Option<String>,
 
+    /// S3 credentials file path.
+    /// It is the shared credentials file for the `profile` source,
+    /// the web identity token file for the `web-identity` source,
+    /// or the mounted secret directory for the `secret` source.
+    #[arg(global = true, long, env = "CDL_S3_CREDENTIAL_PATH")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub s3_credential_path: Option<PathBuf>,
+
+    /// S3 credentials refresh interval in seconds.
+    /// The credentials are refreshed earlier if they are going to be expired.
+    #[arg(
+        global=true, long,
+        env = "CDL_S3_CREDENTIAL_REFRESH_INTERVAL",
+        default_value_t = Self::default_s3_credential_refresh_interval(),
+    )]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "DatasetCatalog::default_s3_credential_refresh_interval")
+    )]
+    pub s3_credential_refresh_interval: u64,
+
+    /// S3 credentials source.
+    #[arg(
+        global=true, long,
+        env = "CDL_S3_CREDENTIAL_SOURCE",
+        value_enum,
+        default_value_t = S3CredentialSource::default(),
+    )]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub s3_credential_source: S3CredentialSource,
+
     /// S3 region name.
     #[arg(
         global=true, long,
@@ -128,6 +280,19 @@ pub struct DatasetCatalog {
     )]
     pub s3_endpoint: Url,
 
+    /// AWS profile name in the shared credentials file.
+    /// Used by the `profile` credentials source.
+    #[arg(
+        global=true, long,
+        env = "AWS_PROFILE",
+        default_value_t = Self::default_s3_profile(),
+    )]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "DatasetCatalog::default_s3_profile")
+    )]
+    pub s3_profile: String,
+
     /// S3 region name. Needed for AWS S3.
     #[arg(
         global=true, long,
@@ -140,6 +305,11 @@ pub struct DatasetCatalog {
     )]
     pub s3_region: String,
 
+    /// Role ARN to assume. Used by the `web-identity` credentials source.
+    #[arg(global = true, long, env = "AWS_ROLE_ARN")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub s3_role_arn: Option<String>,
+
     /// S3 secret key.
     #[arg(global = true, long, env = "AWS_SECRET_ACCESS_KEY")]
     #[cfg_attr(feature = "serde", serde(default))]
@@ -150,14 +320,30 @@ impl Default for DatasetCatalog {
     fn default() -> Self {
         Self {
             cache_dir: Self::default_cache_dir(),
+            chunking_mode: ChunkingMode::default(),
+            commit_mode: CommitMode::default(),
+            compression: Compression::default(),
+            config: None,
+            encrypt_names: false,
+            encryption_key: None,
+            encryption_key_path: None,
             max_buffer_size: Self::default_max_buffer_size(),
             max_cache_size: Self::default_max_cache_size(),
+            max_checkpoint_size: Self::default_max_checkpoint_size(),
             max_chunk_size: Self::default_max_chunk_size(),
+            max_read_threads: Self::default_max_read_threads(),
             max_write_threads: Self::default_max_write_threads(),
             min_cache_object_size: Self::default_min_cache_object_size(),
+            preserve_metadata: false,
+            profile: None,
             s3_access_key: None,
+            s3_credential_path: None,
+            s3_credential_refresh_interval: Self::default_s3_credential_refresh_interval(),
+            s3_credential_source: S3CredentialSource::default(),
             s3_endpoint: Self::default_s3_endpoint(),
+            s3_profile: Self::default_s3_profile(),
             s3_region: Self::default_s3_region(),
+            s3_role_arn: None,
             s3_secret_key: None,
         }
     }
@@ -180,6 +366,12 @@ impl DatasetCatalog {
         32 * 1024 * 1024 * 1024 // 32 GiB
     }
 
+    #[allow(clippy::identity_op)]
+    #[inline]
+    pub const fn default_max_checkpoint_size() -> u64 {
+        4 * 1024 * 1024 * 1024 // 4 GiB
+    }
+
     #[allow(clippy::identity_op)]
     #[inline]
     pub const fn default_max_chunk_size() -> u64 {
@@ -187,6 +379,11 @@ impl DatasetCatalog {
         0
     }
 
+    #[inline]
+    pub const fn default_max_read_threads() -> usize {
+        8
+    }
+
     #[inline]
     pub const fn default_max_write_threads() -> usize {
         2
@@ -198,12 +395,21 @@ impl DatasetCatalog {
         64 * 1024 * 1024 // 64 MiB
     }
 
+    #[inline]
+    pub const fn default_s3_credential_refresh_interval() -> u64 {
+        60
+    }
+
     pub fn default_s3_endpoint() -> Url {
         "http://object-storage"
             .parse()
             .expect("Invalid fallback s3 endpoint")
     }
 
+    pub fn default_s3_profile() -> String {
+        "default".into()
+    }
+
     pub fn default_s3_region() -> String {
         "auto".into()
     }
@@ -211,14 +417,44 @@ impl DatasetCatalog {
     pub fn merge(&mut self, key: &str, value: &str) -> Result<()> {
         match key {
             "cache_dir" => self.cache_dir = value.into(),
+            "chunking_mode" => {
+                self.chunking_mode =
+                    ChunkingMode::from_str(value, true).map_err(|error| anyhow!(error))?
+            }
+            "commit_mode" => {
+                self.commit_mode =
+                    CommitMode::from_str(value, true).map_err(|error| anyhow!(error))?
+            }
+            "compression" => {
+                self.compression =
+                    Compression::from_str(value, true).map_err(|error| anyhow!(error))?
+            }
+            "config" => self.config = Some(value.into()),
+            "encrypt_names" => self.encrypt_names = value.parse()?,
+            "encryption_key" => self.encryption_key = Some(value.into()),
+            "encryption_key_path" => self.encryption_key_path = Some(value.into()),
             "max_buffer_size" => self.max_buffer_size = value.parse()?,
             "max_cache_size" => self.max_cache_size = value.parse()?,
+            "max_checkpoint_size" => self.max_checkpoint_size = value.parse()?,
             "max_chunk_size" => self.max_chunk_size = value.parse()?,
+            "max_read_threads" => self.max_read_threads = value.parse()?,
             "max_write_threads" => self.max_write_threads = value.parse()?,
             "min_cache_object_size" => self.min_cache_object_size = value.parse()?,
+            "preserve_metadata" => self.preserve_metadata = value.parse()?,
+            "profile" => self.profile = Some(value.into()),
             "s3_access_key" => self.s3_access_key = Some(value.into()),
+            "s3_credential_path" => self.s3_credential_path = Some(value.into()),
+            "s3_credential_refresh_interval" => {
+                self.s3_credential_refresh_interval = value.parse()?
+            }
+            "s3_credential_source" => {
+                self.s3_credential_source =
+                    S3CredentialSource::from_str(value, true).map_err(|error| anyhow!(error))?
+            }
             "s3_endpoint" => self.s3_endpoint = value.parse()?,
+            "s3_profile" => self.s3_profile = value.into(),
             "s3_region" => self.s3_region = value.into(),
+            "s3_role_arn" => self.s3_role_arn = Some(value.into()),
             "s3_secret_key" => self.s3_secret_key = Some(value.into()),
             _ => bail!("Invalid key: {key:?}"),
         }
@@ -231,30 +467,235 @@ impl DatasetCatalog {
     ) -> Result<()> {
         iter.try_for_each(|(key, value)| self.merge(key, value))
     }
+
+    /// Apply the selected profile to the values which are not given
+    /// as command-line flags or environment variables.
+    pub fn load_profile(&mut self, matches: &ArgMatches) -> Result<()> {
+        self.load_profile_with_vars(matches, ::std::env::vars())
+    }
+
+    fn load_profile_with_vars(
+        &mut self,
+        matches: &ArgMatches,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<()> {
+        let config = CatalogConfig::load(self.config.as_deref())?;
+        let name = match self.profile.clone().or_else(|| {
+            config
+                .as_ref()
+                .and_then(|config| config.default_profile.clone())
+        }) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+
+        let values = self
+            .profile_values(config.as_ref(), &name, vars)
+            .with_context(|| format!("No such catalog profile: {name:?}"))?;
+        for (key, value) in values {
+            let is_explicit = matches.try_contains_id(&key).unwrap_or_default()
+                && matches!(
+                    matches.value_source(&key),
+                    Some(ValueSource::CommandLine | ValueSource::EnvVariable),
+                );
+            if !is_explicit {
+                self.merge(&key, &value)?;
+            }
+        }
+        self.profile = Some(name);
+        Ok(())
+    }
+
+    /// Return a catalog overridden by the given named profile.
+    ///
+    /// The current credentials are dropped if the profile selects another endpoint,
+    /// so that they are not sent to the other storage.
+    pub fn with_profile(&self, name: &str) -> Result<Self> {
+        let config = CatalogConfig::load(self.config.as_deref())?;
+        let values = self
+            .profile_values(config.as_ref(), name, ::std::env::vars())
+            .with_context(|| format!("No such catalog profile: {name:?}"))?;
+
+        let mut catalog = self.clone();
+        let is_other_endpoint = values.iter().any(|(key, value)| {
+            key == "s3_endpoint" && value.parse::<Url>().ok().as_ref() != Some(&self.s3_endpoint)
+        });
+        if is_other_endpoint {
+            catalog.clear_credentials();
+        }
+        catalog.merge_iter(
+            values
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )?;
+        catalog.profile = Some(name.into());
+        Ok(catalog)
+    }
+
+    fn clear_credentials(&mut self) {
+        self.s3_access_key = None;
+        self.s3_credential_path = None;
+        self.s3_credential_source = S3CredentialSource::default();
+        self.s3_profile = Self::default_s3_profile();
+        self.s3_role_arn = None;
+        self.s3_secret_key = None;
+    }
+
+    /// Collect the profile from the config file,
+    /// and the environment variables named as `CDL_PROFILE_{NAME}_{KEY}`,
+    /// e.g. `CDL_PROFILE_POND_S3_ENDPOINT`.
+    fn profile_values(
+        &self,
+        config: Option<&CatalogConfig>,
+        name: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Option<Vec<(String, String)>> {
+        let prefix = format!(
+            "{prefix}{name}_",
+            prefix = Self::KEY_PROFILE_PREFIX,
+            name = name.to_uppercase().replace('-', "_"),
+        );
+
+        let file = config.and_then(|config| config.profile(name));
+        let vars: Vec<_> = vars
+            .into_iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix(&prefix)
+                    .map(|key| (key.to_lowercase(), value))
+            })
+            .collect();
+        if file.is_none() && vars.is_empty() {
+            return None;
+        }
+
+        Some(
+            file.unwrap_or_default()
+                .iter()
+                .cloned()
+                .chain(vars)
+                .collect(),
+        )
+    }
 }
 
 impl DatasetCatalog {
     pub const KEY_CACHE_DIR: &'static str = "CDL_CACHE_DIR";
     pub const KEY_MAX_CACHE_SIZE: &'static str = "CDL_MAX_CACHE_SIZE";
     pub const KEY_MIN_CACHE_OBJECT_SIZE: &'static str = "CDL_MIN_CACHE_OBJECT_SIZE";
+    pub const KEY_PROFILE_PREFIX: &'static str = "CDL_PROFILE_";
 
     pub fn commit_handler(&self) -> Arc<dyn CommitHandler> {
-        Arc::new(UnsafeCommitHandler)
+        self.commit_mode.commit_handler()
     }
 
     pub fn fragment_process(&self) -> Arc<dyn WriteFragmentProgress> {
         Arc::new(NoopFragmentWriteProgress::default())
     }
 
+    /// Return the credentials provider of the S3 endpoint.
+    ///
+    /// The providers are shared by the catalogs with the same credentials config,
+    /// so that their cached credentials are reused instead of being loaded on every call.
     pub fn s3_credential_provider(&self) -> Result<AwsCredentialProvider> {
-        Ok(Arc::new(StaticCredentialProvider::new(AwsCredential {
+        static PROVIDERS: OnceLock<Mutex<HashMap<CredentialKey, AwsCredentialProvider>>> =
+            OnceLock::new();
+
+        let key = CredentialKey {
+            access_key: self.s3_access_key.clone(),
+            endpoint: self.s3_endpoint.0.clone(),
+            path: self.s3_credential_path.clone(),
+            profile: self.s3_profile.clone(),
+            refresh_interval: self.s3_credential_refresh_interval,
+            role_arn: self.s3_role_arn.clone(),
+            secret_key: self.s3_secret_key.clone(),
+            source: self.s3_credential_source,
+        };
+        let mut providers = PROVIDERS.get_or_init(Default::default).lock().unwrap();
+        if let Some(provider) = providers.get(&key) {
+            return Ok(provider.clone());
+        }
+        let provider = self.build_s3_credential_provider()?;
+        providers.insert(key, provider.clone());
+        Ok(provider)
+    }
+
+    fn build_s3_credential_provider(&self) -> Result<AwsCredentialProvider> {
+        let interval = Duration::from_secs(self.s3_credential_refresh_interval);
+        match self.s3_credential_source {
+            S3CredentialSource::Static => Ok(Arc::new(StaticCredentialProvider::new(
+                self.static_credential()?,
+            ))),
+            S3CredentialSource::Profile => Ok(Arc::new(RefreshingCredentialProvider::new(
+                self.profile_loader(),
+                interval,
+            ))),
+            S3CredentialSource::WebIdentity => {
+                let token_file = match self.s3_credential_path.clone() {
+                    Some(path) => path,
+                    None => ::std::env::var_os("AWS_WEB_IDENTITY_TOKEN_FILE")
+                        .map(Into::into)
+                        .context("Missing catalog config: s3_credential_path")?,
+                };
+                let loader = WebIdentityLoader {
+                    client: ::reqwest::Client::new(),
+                    endpoint: self.s3_endpoint.0.clone(),
+                    role_arn: self.s3_role_arn.clone(),
+                    token_file,
+                };
+                Ok(Arc::new(RefreshingCredentialProvider::new(
+                    loader, interval,
+                )))
+            }
+            S3CredentialSource::Secret => Ok(Arc::new(RefreshingCredentialProvider::new(
+                self.secret_loader()?,
+                interval,
+            ))),
+        }
+    }
+
+    fn static_credential(&self) -> Result<AwsCredential> {
+        Ok(AwsCredential {
             key_id: get_arg!(self, s3_access_key)?,
             secret_key: get_arg!(self, s3_secret_key)?,
             token: None,
-        })))
+        })
+    }
+
+    fn profile_loader(&self) -> ProfileLoader {
+        ProfileLoader {
+            path: self.s3_credential_path.clone(),
+            profile: self.s3_profile.clone(),
+        }
+    }
+
+    fn secret_loader(&self) -> Result<SecretLoader> {
+        Ok(SecretLoader {
+            path: get_arg!(self, s3_credential_path)?,
+        })
+    }
+
+    pub fn storage_options(&self) -> Result<HashMap<String, String>> {
+        self.build_storage_options(None)
+    }
+
+    /// Return the storage options with the current credentials,
+    /// e.g. to pass them to the external libraries.
+    ///
+    /// The credentials are resolved by the cached provider, so the temporary ones
+    /// (e.g. of the `web-identity` source) come with their session token.
+    pub async fn storage_options_with_credentials(&self) -> Result<HashMap<String, String>> {
+        let credential = self
+            .s3_credential_provider()?
+            .get_credential()
+            .await
+            .context("Failed to load the S3 credentials")?;
+        self.build_storage_options(Some(&credential))
     }
 
-    pub fn storage_options(&self, append_credentials: bool) -> Result<HashMap<String, String>> {
+    fn build_storage_options(
+        &self,
+        credential: Option<&AwsCredential>,
+    ) -> Result<HashMap<String, String>> {
         let allow_http = self.s3_endpoint.scheme() == "http";
         let mut endpoint = self.s3_endpoint.to_string();
         while endpoint.ends_with("/") {
@@ -274,18 +715,21 @@ impl DatasetCatalog {
         );
         // S3
         options.insert("allow_http".into(), allow_http.to_string());
-        if append_credentials {
-            options.insert("AWS_ACCESS_KEY_ID".into(), get_arg!(self, s3_access_key)?);
+        if let Some(credential) = credential {
+            options.insert("AWS_ACCESS_KEY_ID".into(), credential.key_id.clone());
         }
         options.insert("AWS_ALLOW_HTTP".into(), allow_http.to_string());
         options.insert("AWS_EC2_METADATA_DISABLED".into(), true.to_string());
         options.insert("AWS_ENDPOINT_URL".into(), endpoint);
         options.insert("AWS_REGION".into(), self.s3_region.clone());
-        if append_credentials {
+        if let Some(credential) = credential {
             options.insert(
                 "AWS_SECRET_ACCESS_KEY".into(),
-                get_arg!(self, s3_secret_key)?,
+                credential.secret_key.clone(),
             );
+            if let Some(token) = credential.token.clone() {
+                options.insert("AWS_SESSION_TOKEN".into(), token);
+            }
         }
         options.insert("AWS_VIRTUAL_HOSTED_STYLE_REQUEST".into(), "false".into());
         options.insert("conditional_put".into(), "etag".into());
@@ -296,7 +740,7 @@ impl DatasetCatalog {
         Ok(ObjectStoreParams {
             aws_credentials: Some(self.s3_credential_provider()?),
             list_is_lexically_ordered: Some(true),
-            storage_options: Some(self.storage_options(false)?),
+            storage_options: Some(self.storage_options()?),
             use_constant_size_upload_parts: false,
             ..Default::default()
         })
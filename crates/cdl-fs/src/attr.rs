@@ -0,0 +1,141 @@
+use std::{collections::BTreeMap, fs::Metadata, io::ErrorKind, path::Path};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Map, Value};
+use tracing::{debug, warn};
+
+use crate::{FileMetadataRecord, FileType};
+
+/// Capture the ownership, extended attributes and hardlink identity of the given file.
+pub(crate) fn capture(
+    path: &Path,
+    metadata: &Metadata,
+    record: &mut FileMetadataRecord,
+) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        record.uid = Some(metadata.uid());
+        record.gid = Some(metadata.gid());
+
+        // The files having the same device and inode numbers are linked together
+        if record.file_type == FileType::File && metadata.nlink() > 1 {
+            record.link_id = Some(format!(
+                "{dev:x}:{ino:x}",
+                dev = metadata.dev(),
+                ino = metadata.ino(),
+            ));
+        }
+
+        let names = match ::xattr::list(path) {
+            Ok(names) => names,
+            // Skip the filesystems without the extended attributes
+            Err(error) if error.kind() == ErrorKind::Unsupported => return Ok(()),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Failed to list the extended attributes: {path:?}"))
+            }
+        };
+        // Skip the attributes which are not readable, e.g. `trusted.*` without privileges
+        for name in names {
+            let name = name.to_string_lossy().to_string();
+            match ::xattr::get(path, &name) {
+                Ok(Some(value)) => {
+                    record.xattrs.insert(name, value);
+                }
+                Ok(None) => (),
+                Err(error) => {
+                    warn!("Skipping the extended attribute {name:?} of {path:?}: {error}");
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (path, metadata, record);
+    }
+    Ok(())
+}
+
+/// Restore the ownership and extended attributes of the given file.
+///
+/// The ownership is restored only with sufficient privileges,
+/// and the unsupported extended attributes are skipped.
+pub(crate) fn restore(path: &Path, record: &FileMetadataRecord) -> Result<()> {
+    #[cfg(unix)]
+    {
+        if record.uid.is_some() || record.gid.is_some() {
+            match ::std::os::unix::fs::lchown(path, record.uid, record.gid) {
+                Ok(()) => (),
+                Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                    debug!("Skipping the ownership of {path:?}: {error}");
+                }
+                Err(error) => {
+                    return Err(error)
+                        .with_context(|| format!("Failed to restore the ownership: {path:?}"))
+                }
+            }
+        }
+
+        for (name, value) in &record.xattrs {
+            if let Err(error) = ::xattr::set(path, name, value) {
+                warn!("Skipping the extended attribute {name:?} of {path:?}: {error}");
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (path, record);
+    }
+    Ok(())
+}
+
+/// Encode the extended attributes into the `xattrs` column.
+pub(crate) fn encode_xattrs(xattrs: &BTreeMap<String, Vec<u8>>) -> Option<String> {
+    if xattrs.is_empty() {
+        return None;
+    }
+
+    let map: Map<String, Value> = xattrs
+        .iter()
+        .map(|(name, value)| (name.clone(), Value::String(STANDARD.encode(value))))
+        .collect();
+    Some(Value::Object(map).to_string())
+}
+
+/// Decode the `xattrs` column.
+pub(crate) fn decode_xattrs(xattrs: &str) -> Result<BTreeMap<String, Vec<u8>>> {
+    let map: Map<String, Value> =
+        ::serde_json::from_str(xattrs).context("Invalid extended attributes")?;
+    map.into_iter()
+        .map(|(name, value)| match value {
+            Value::String(value) => STANDARD
+                .decode(value)
+                .map(|value| (name, value))
+                .context("Invalid extended attribute value"),
+            _ => bail!("Invalid extended attribute value: {name:?}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_xattrs_round_trip() {
+        assert_eq!(encode_xattrs(&BTreeMap::default()), None);
+
+        let xattrs = BTreeMap::from([
+            ("user.checksum".to_string(), b"abc".to_vec()),
+            ("user.binary".to_string(), vec![0, 255, 1]),
+        ]);
+        let encoded = encode_xattrs(&xattrs).unwrap();
+        assert_eq!(decode_xattrs(&encoded).unwrap(), xattrs);
+        assert!(decode_xattrs("{\"user.a\": 1}").is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use cdl_catalog::DatasetCatalog;
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{info, instrument, warn, Level};
+
+use crate::{GlobalPath, LocalFile};
+
+/// A local journal of the files committed by an upload.
+///
+/// Each line is a JSON array of `[parent, name, mtime, size]`,
+/// so that re-running the same upload skips the committed files.
+/// The journal is removed when the upload is completed.
+pub(crate) struct Checkpoint {
+    committed: HashMap<(String, String), (i64, u64)>,
+    file: fs::File,
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub(crate) async fn open(
+        catalog: &DatasetCatalog,
+        root: &Path,
+        dst: &GlobalPath,
+        restart: bool,
+    ) -> Result<Self> {
+        let path = {
+            let mut hasher = Sha256::new();
+            hasher.update(root.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(dst.to_string().as_bytes());
+            let key: String = hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            Path::new(&catalog.cache_dir)
+                .join(DIR_CHECKPOINTS)
+                .join(format!("{key}.jsonl"))
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let content = match fs::read_to_string(&path).await {
+            Ok(_) if restart => {
+                info!("Discarding the upload checkpoint: {path:?}");
+                fs::remove_file(&path).await?;
+                String::default()
+            }
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => String::default(),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Failed to read the upload checkpoint: {path:?}"))
+            }
+        };
+
+        let mut committed = HashMap::default();
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            // The last line may be truncated if the process was killed
+            match ::serde_json::from_str::<(String, String, i64, u64)>(line) {
+                Ok((parent, name, mtime, size)) => {
+                    committed.insert((parent, name), (mtime, size));
+                }
+                Err(error) => warn!("Skipping a broken upload checkpoint entry: {error}"),
+            }
+        }
+        if !committed.is_empty() {
+            info!(
+                "Resuming the upload from the checkpoint: {count} files are already committed",
+                count = committed.len(),
+            );
+        }
+
+        let mut file = fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("Failed to open the upload checkpoint: {path:?}"))?;
+        if !content.is_empty() && !content.ends_with('\n') {
+            file.write_all(b"\n").await?;
+        }
+
+        Ok(Self {
+            committed,
+            file,
+            path,
+        })
+    }
+
+    /// Return `true` if the file has been committed and not changed since then.
+    pub(crate) fn contains(&self, file: &LocalFile) -> bool {
+        let key = (file.parent.clone(), file.name.clone());
+        self.committed.get(&key)
+            == Some(&(file.metadata.mtime.timestamp_micros(), file.metadata.size))
+    }
+
+    /// Record the committed files.
+    pub(crate) async fn record(&mut self, files: &[LocalFile]) -> Result<()> {
+        let mut buf = Vec::default();
+        for file in files {
+            let entry = (
+                &file.parent,
+                &file.name,
+                file.metadata.mtime.timestamp_micros(),
+                file.metadata.size,
+            );
+            ::serde_json::to_writer(&mut buf, &entry)?;
+            buf.push(b'\n');
+        }
+
+        self.file.write_all(&buf).await?;
+        self.file
+            .sync_data()
+            .await
+            .with_context(|| format!("Failed to write the upload checkpoint: {:?}", &self.path))
+    }
+
+    /// Remove the journal of the completed upload.
+    pub(crate) async fn finish(self) -> Result<()> {
+        let Self { file, path, .. } = self;
+        drop(file);
+        fs::remove_file(&path)
+            .await
+            .with_context(|| format!("Failed to remove the upload checkpoint: {path:?}"))
+    }
+}
+
+const DIR_CHECKPOINTS: &str = "checkpoints";
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use tempfile::TempDir;
+
+    use crate::{FileMetadataRecord, FileType};
+
+    use super::*;
+
+    fn local_file(name: &str, size: u64) -> LocalFile {
+        let time = DateTime::from_timestamp_micros(1_700_000_000_000_000).unwrap();
+        LocalFile {
+            path: PathBuf::from(format!("/data/{name}")),
+            parent: String::default(),
+            name: name.into(),
+            metadata: FileMetadataRecord {
+                atime: time,
+                ctime: time,
+                mtime: time,
+                mode: 0o644,
+                size,
+                file_type: FileType::File,
+                link_target: None,
+                uid: None,
+                gid: None,
+                xattrs: Default::default(),
+                link_id: None,
+            },
+        }
+    }
+
+    #[::tokio::test]
+    async fn resume_from_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let catalog = DatasetCatalog {
+            cache_dir: temp_dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let root = Path::new("/data");
+        let dst: GlobalPath = "s3://my-bucket/data".parse().unwrap();
+
+        let mut checkpoint = Checkpoint::open(&catalog, root, &dst, false).await.unwrap();
+        checkpoint.record(&[local_file("a", 1)]).await.unwrap();
+        let path = checkpoint.path.clone();
+        drop(checkpoint);
+
+        // simulate a truncated entry of a killed process
+        let mut file = fs::File::options().append(true).open(&path).await.unwrap();
+        file.write_all(b"[\"\", \"b\", 17").await.unwrap();
+        drop(file);
+
+        let mut checkpoint = Checkpoint::open(&catalog, root, &dst, false).await.unwrap();
+        assert!(checkpoint.contains(&local_file("a", 1)));
+        assert!(!checkpoint.contains(&local_file("a", 2)));
+        assert!(!checkpoint.contains(&local_file("b", 1)));
+        checkpoint.record(&[local_file("b", 1)]).await.unwrap();
+        drop(checkpoint);
+
+        let checkpoint = Checkpoint::open(&catalog, root, &dst, false).await.unwrap();
+        assert!(checkpoint.contains(&local_file("a", 1)));
+        assert!(checkpoint.contains(&local_file("b", 1)));
+        checkpoint.finish().await.unwrap();
+        assert!(!path.exists());
+
+        // the other destinations do not share the checkpoint
+        let other: GlobalPath = "s3://my-bucket/other".parse().unwrap();
+        let checkpoint = Checkpoint::open(&catalog, root, &other, false)
+            .await
+            .unwrap();
+        assert!(!checkpoint.contains(&local_file("a", 1)));
+        checkpoint.finish().await.unwrap();
+    }
+}
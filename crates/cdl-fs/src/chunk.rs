@@ -0,0 +1,521 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Error, Result};
+use arrow::{
+    array::{self, Array, ArrayRef, AsArray, RecordBatch},
+    datatypes::{
+        DataType as ArrowDataType, Field as ArrowField, Schema as ArrowSchema, TimeUnit, UInt64Type,
+    },
+};
+use cdl_catalog::{ChunkingMode, DatasetCatalog};
+use chrono::Utc;
+use datafusion::{
+    error::DataFusionError, execution::SendableRecordBatchStream,
+    physical_plan::stream::RecordBatchStreamAdapter,
+};
+use fastcdc::v2020::{
+    FastCDC, AVERAGE_MAX, AVERAGE_MIN, MAXIMUM_MAX, MAXIMUM_MIN, MINIMUM_MAX, MINIMUM_MIN,
+};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use itertools::Itertools;
+use lance::{dataset::progress::WriteFragmentProgress, Dataset};
+use sha2::{Digest, Sha256};
+use tokio::{spawn, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{info, instrument};
+
+use crate::{
+    compress::{compress, decompress},
+    crypto::{self, Cipher},
+    escape_sql_str, insert_stream, migrate_table, try_open_dataset, DatasetPath, FileRecord,
+    FileRecordStream, Progress, MAX_PREDICATES_PER_QUERY, MAX_ROW_SIZE,
+};
+
+/// Splits the file contents into the chunks.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Chunker {
+    Fixed { size: u64 },
+    ContentDefined { min: u32, avg: u32, max: u32 },
+}
+
+impl Chunker {
+    pub(crate) fn new(catalog: &DatasetCatalog) -> Self {
+        // The files larger than the safe row size are split even if the chunking is disabled
+        let max_row_size = (catalog.max_buffer_size as u64).clamp(1, MAX_ROW_SIZE);
+
+        match catalog.chunking_mode {
+            ChunkingMode::Fixed => Self::Fixed {
+                size: match catalog.max_chunk_size {
+                    0 => max_row_size,
+                    max_chunk_size => max_chunk_size.min(max_row_size),
+                },
+            },
+            ChunkingMode::ContentDefined => {
+                let max = match catalog.max_chunk_size {
+                    0 => DEFAULT_MAX_CDC_SIZE,
+                    max_chunk_size => max_chunk_size,
+                }
+                .min(max_row_size)
+                .clamp(MAXIMUM_MIN as _, MAXIMUM_MAX as _) as u32;
+                let avg = (max / 4).clamp(AVERAGE_MIN, AVERAGE_MAX);
+                let min = (avg / 4).clamp(MINIMUM_MIN, MINIMUM_MAX);
+                Self::ContentDefined { min, avg, max }
+            }
+        }
+    }
+
+    /// Return the max size of each chunk.
+    pub(crate) const fn max_size(&self) -> u64 {
+        match *self {
+            Self::Fixed { size } => size,
+            Self::ContentDefined { max, .. } => max as _,
+        }
+    }
+
+    /// Return the size of the first chunk of the given buffer.
+    ///
+    /// The buffer should be filled up to the `max_size`, or to the end of the file.
+    pub(crate) fn cut(&self, buf: &[u8]) -> usize {
+        match *self {
+            Self::Fixed { size } => buf.len().min(size as _),
+            Self::ContentDefined { min, avg, max } => FastCDC::new(buf, min, avg, max)
+                .next()
+                .map_or(buf.len(), |chunk| chunk.length),
+        }
+    }
+}
+
+/// Return the content hash of the given chunk.
+pub(crate) fn hash_chunk(data: &[u8]) -> String {
+    encode_hash(&Sha256::digest(data))
+}
+
+pub(crate) fn encode_hash(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Return the given hash as stored in the tables, keyed with the cipher if any.
+pub(crate) fn stored_hash(cipher: Option<&Cipher>, hash: &str) -> String {
+    match cipher {
+        Some(cipher) => cipher.mac_hash(hash),
+        None => hash.into(),
+    }
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn try_open_store(
+    catalog: &DatasetCatalog,
+    dataset: &DatasetPath,
+) -> Result<Option<Dataset>> {
+    try_open_dataset(catalog, &dataset.to_uri(DIR_CHUNKS)).await
+}
+
+/// Store the new chunks of the files, and return the rootfs rows referring to them.
+#[instrument(skip_all)]
+pub(crate) async fn store_chunks(
+    catalog: &DatasetCatalog,
+    dataset: &DatasetPath,
+    stream: FileRecordStream,
+    progress: Arc<dyn WriteFragmentProgress>,
+) -> Result<Vec<FileRecord>> {
+    let store = match try_open_store(catalog, dataset).await? {
+        Some(mut store) => {
+            migrate_table(&mut store, columns_arrow()).await?;
+            Some(Arc::new(store))
+        }
+        None => None,
+    };
+    let cipher = Cipher::try_new(catalog)?;
+
+    // The hashes stored by this upload so far
+    let known = Arc::new(Mutex::new(HashSet::default()));
+    let rows = Arc::new(Mutex::new(Vec::default()));
+    let mut chunks = Box::pin(
+        stream
+            .try_chunks(MAX_PREDICATES_PER_QUERY)
+            .map_err(|error| error.1)
+            .and_then({
+                let catalog = catalog.clone();
+                let dataset = dataset.clone();
+                let cipher = cipher.clone();
+                let rows = rows.clone();
+                move |files| {
+                    let catalog = catalog.clone();
+                    let dataset = dataset.clone();
+                    let cipher = cipher.clone();
+                    let known = known.clone();
+                    let rows = rows.clone();
+                    let store = store.clone();
+                    async move {
+                        let mut chunks = Vec::with_capacity(files.len());
+                        for mut file in files {
+                            let hash = file
+                                .chunk_hash
+                                .get_or_insert_with(|| match file.checksum.as_ref() {
+                                    Some(checksum) => checksum.clone(),
+                                    None => hash_chunk(&file.data),
+                                })
+                                .clone();
+                            chunks.push(NewChunk {
+                                hash: stored_hash(cipher.as_deref(), &hash),
+                                name: file.name.clone(),
+                                data: mem::take(&mut file.data),
+                            });
+                            rows.lock().unwrap().push(file);
+                        }
+
+                        // Skip the chunks already stored, looking up the hashes of this batch only
+                        let stored = match store.as_deref() {
+                            Some(store) => {
+                                let stored = load_hashes(
+                                    store,
+                                    chunks.iter().map(|chunk| chunk.hash.as_str()),
+                                )
+                                .await?;
+                                touch_chunks(&catalog, &dataset, stored).await?
+                            }
+                            None => HashSet::default(),
+                        };
+                        let mut known = known.lock().unwrap();
+                        chunks.retain(|chunk| {
+                            !stored.contains(&chunk.hash) && known.insert(chunk.hash.clone())
+                        });
+                        Ok(stream::iter(chunks.into_iter().map(Ok)))
+                    }
+                }
+            })
+            .try_flatten()
+            .peekable(),
+    );
+
+    if chunks.as_mut().peek().await.is_some() {
+        let stream = chunk_stream_to_batch_stream(catalog, cipher, chunks);
+        insert_stream(catalog, &dataset.to_uri(DIR_CHUNKS), stream, progress).await?;
+    }
+
+    let rows = mem::take(&mut *rows.lock().unwrap());
+    info!(
+        "Stored chunks: {count} rows, {bytes} bytes",
+        count = rows.len(),
+        bytes = rows.iter().map(|file| file.chunk_size).sum::<u64>(),
+    );
+    Ok(rows)
+}
+
+/// Fill the data of the rows referring to the chunk store.
+#[instrument(skip_all)]
+pub(crate) async fn resolve_chunks(
+    store: Option<&Dataset>,
+    cipher: Option<&Cipher>,
+    files: &mut [FileRecord],
+) -> Result<()> {
+    let hashes: HashSet<_> = files
+        .iter()
+        .filter(|file| is_unresolved(file))
+        .filter_map(|file| file.chunk_hash.as_deref())
+        .collect();
+    if hashes.is_empty() {
+        return Ok(());
+    }
+    let Some(store) = store else {
+        bail!("Missing chunk store")
+    };
+
+    let chunks = fetch_chunks(store, cipher, hashes).await?;
+    for file in files.iter_mut().filter(|file| is_unresolved(file)) {
+        let Some(hash) = file.chunk_hash.as_deref() else {
+            continue;
+        };
+        match chunks.get(hash) {
+            Some(data) => file.data.clone_from(data),
+            None => bail!("Missing chunk in the chunk store: {hash}"),
+        }
+    }
+    Ok(())
+}
+
+/// Load the data of the given chunks, skipping the missing ones.
+#[instrument(skip_all)]
+pub(crate) async fn fetch_chunks(
+    store: &Dataset,
+    cipher: Option<&Cipher>,
+    hashes: HashSet<&str>,
+) -> Result<HashMap<String, Vec<u8>>> {
+    let schema = store.schema();
+    let columns: Vec<_> = columns_arrow()
+        .into_iter()
+        .map(|field| field.name().clone())
+        .filter(|name| schema.field(name).is_some())
+        .collect();
+
+    let mut chunks = HashMap::<String, Vec<u8>>::default();
+    for hashes in &hashes.into_iter().chunks(MAX_PREDICATES_PER_QUERY) {
+        let filter = format!(
+            "hash IN ({hashes})",
+            hashes = hashes
+                .map(|hash| format!("'{hash}'", hash = escape_sql_str(hash)))
+                .join(", "),
+        );
+        let mut stream = store
+            .scan()
+            .project(&columns)?
+            .filter(&filter)?
+            .try_into_stream()
+            .await?;
+        while let Some(batch) = stream.try_next().await? {
+            let (Some(hash), Some(size), Some(data)) = (
+                batch
+                    .column_by_name("hash")
+                    .and_then(|c| c.as_string_opt::<i32>()),
+                batch
+                    .column_by_name("size")
+                    .and_then(|c| c.as_primitive_opt::<UInt64Type>()),
+                batch
+                    .column_by_name("data")
+                    .and_then(|c| c.as_binary_opt::<i32>()),
+            ) else {
+                bail!("Invalid chunk store schema")
+            };
+            let codec = batch
+                .column_by_name("codec")
+                .and_then(|c| c.as_string_opt::<i32>());
+            let encryption = batch
+                .column_by_name("encryption")
+                .and_then(|c| c.as_string_opt::<i32>());
+
+            for (index, hash) in hash.iter().enumerate() {
+                let Some(hash) = hash else {
+                    continue;
+                };
+                let mut data = data.value(index).to_vec();
+                if encryption.is_some_and(|encryption| encryption.is_valid(index)) {
+                    let Some(cipher) = cipher else {
+                        bail!("Encrypted chunk requires an encryption key: {hash}")
+                    };
+                    data = cipher.decrypt_data(&data)?;
+                }
+                if let Some(codec) = codec.filter(|codec| codec.is_valid(index)) {
+                    data = decompress(codec.value(index), &data, size.value(index))?;
+                }
+                chunks.insert(hash.into(), data);
+            }
+        }
+    }
+    Ok(chunks)
+}
+
+pub(crate) fn is_unresolved(file: &FileRecord) -> bool {
+    file.chunk_hash.is_some() && file.data.len() as u64 != file.chunk_size
+}
+
+/// Load the given hashes which are already stored.
+#[instrument(skip_all)]
+async fn load_hashes(
+    store: &Dataset,
+    hashes: impl IntoIterator<Item = &str>,
+) -> Result<HashSet<String>> {
+    let mut stored = HashSet::default();
+    for hashes in &hashes.into_iter().unique().chunks(MAX_PREDICATES_PER_QUERY) {
+        let filter = format!(
+            "hash IN ({hashes})",
+            hashes = hashes
+                .map(|hash| format!("'{hash}'", hash = escape_sql_str(hash)))
+                .join(", "),
+        );
+        let mut stream = store
+            .scan()
+            .project(&["hash"])?
+            .filter(&filter)?
+            .try_into_stream()
+            .await?;
+        while let Some(batch) = stream.try_next().await? {
+            let Some(column) = batch
+                .column_by_name("hash")
+                .and_then(|c| c.as_string_opt::<i32>())
+            else {
+                bail!("Invalid chunk store schema")
+            };
+            stored.extend(column.iter().flatten().map(Into::into));
+        }
+    }
+    Ok(stored)
+}
+
+/// Touch the stored chunks referred to again, and return the ones still stored.
+///
+/// The gc keeps the chunks touched within its grace period, restoring them if removed meanwhile.
+/// The chunks removed before the touch are not returned, so that the writer stores them again.
+#[instrument(skip_all)]
+async fn touch_chunks(
+    catalog: &DatasetCatalog,
+    dataset: &DatasetPath,
+    hashes: HashSet<String>,
+) -> Result<HashSet<String>> {
+    if hashes.is_empty() {
+        return Ok(hashes);
+    }
+
+    let schema = Arc::new(touches_schema_arrow());
+    let time = Utc::now().timestamp_micros();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(array::StringArray::from_iter_values(&hashes)),
+        Arc::new(array::TimestampMicrosecondArray::from(vec![
+            time;
+            hashes.len()
+        ])),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let stream = Box::pin(RecordBatchStreamAdapter::new(
+        schema,
+        stream::iter([Ok::<_, DataFusionError>(batch)]),
+    ));
+    let uri = dataset.to_uri(DIR_TOUCHES);
+    insert_stream(catalog, &uri, stream, Arc::new(Progress::default())).await?;
+
+    // Look up the latest version again, as the gc may have removed the chunks before the touch
+    match try_open_store(catalog, dataset).await? {
+        Some(store) => load_hashes(&store, hashes.iter().map(String::as_str)).await,
+        None => Ok(HashSet::default()),
+    }
+}
+
+fn chunk_stream_to_batch_stream(
+    catalog: &DatasetCatalog,
+    cipher: Option<Arc<Cipher>>,
+    mut stream: impl 'static + Send + Unpin + Stream<Item = Result<NewChunk>>,
+) -> SendableRecordBatchStream {
+    let schema = Arc::new(schema_arrow());
+    let compression = catalog.compression;
+    let max_buffer_size = catalog.max_buffer_size;
+
+    let (tx, rx) = mpsc::channel(catalog.max_write_threads);
+
+    spawn({
+        let schema = schema.clone();
+        async move {
+            let produce = async {
+                let mut chunks = Vec::default();
+                let mut total_size = 0;
+                while let Some(NewChunk { hash, name, data }) = stream.try_next().await? {
+                    let size = data.len() as u64;
+                    let (codec, data) = match compress(compression, &name, &data)? {
+                        Some(data) => (Some(compression.to_string()), data),
+                        None => (None, data),
+                    };
+                    let (encryption, data) = match cipher.as_ref() {
+                        Some(cipher) => (Some(crypto::ENCRYPTION_V1), cipher.encrypt_data(&data)?),
+                        None => (None, data),
+                    };
+
+                    if !chunks.is_empty() && total_size + data.len() > max_buffer_size {
+                        tx.send(Ok(build_batch(&schema, mem::take(&mut chunks))?))
+                            .await?;
+                        total_size = 0;
+                    }
+                    total_size += data.len();
+                    chunks.push(StoredChunk {
+                        hash,
+                        size,
+                        codec,
+                        encryption,
+                        data,
+                    });
+                }
+                if !chunks.is_empty() {
+                    tx.send(Ok(build_batch(&schema, chunks)?)).await?;
+                }
+                Result::<_, Error>::Ok(())
+            };
+
+            // Fail the consumer so that the partial stream is not committed
+            if let Err(error) = produce.await {
+                let error = DataFusionError::External(error.into());
+                tx.send(Err(error)).await.ok();
+            }
+        }
+    });
+
+    Box::pin(RecordBatchStreamAdapter::new(
+        schema,
+        ReceiverStream::new(rx),
+    ))
+}
+
+fn build_batch(schema: &Arc<ArrowSchema>, chunks: Vec<StoredChunk>) -> Result<RecordBatch> {
+    let mut hash = array::StringBuilder::new();
+    let mut size = array::UInt64Builder::new();
+    let mut data = array::BinaryBuilder::new();
+    let mut codec = array::StringBuilder::new();
+    let mut encryption = array::StringBuilder::new();
+    for chunk in chunks {
+        hash.append_value(chunk.hash);
+        size.append_value(chunk.size);
+        data.append_value(chunk.data);
+        codec.append_option(chunk.codec);
+        encryption.append_option(chunk.encryption);
+    }
+
+    let arrays: Vec<ArrayRef> = vec![
+        Arc::new(hash.finish()),
+        Arc::new(size.finish()),
+        Arc::new(data.finish()),
+        Arc::new(codec.finish()),
+        Arc::new(encryption.finish()),
+    ];
+    RecordBatch::try_new(schema.clone(), arrays).map_err(Into::into)
+}
+
+fn columns_arrow() -> Vec<ArrowField> {
+    vec![
+        ArrowField::new("hash", ArrowDataType::Utf8, false),
+        ArrowField::new("size", ArrowDataType::UInt64, false),
+        ArrowField::new("data", ArrowDataType::Binary, false),
+        ArrowField::new("codec", ArrowDataType::Utf8, true),
+        ArrowField::new("encryption", ArrowDataType::Utf8, true),
+    ]
+}
+
+fn schema_arrow() -> ArrowSchema {
+    ArrowSchema::new(columns_arrow())
+}
+
+pub(crate) fn touches_schema_arrow() -> ArrowSchema {
+    ArrowSchema::new(vec![
+        ArrowField::new("hash", ArrowDataType::Utf8, false),
+        ArrowField::new(
+            "touch_time",
+            ArrowDataType::Timestamp(TimeUnit::Microsecond, None),
+            false,
+        ),
+    ])
+}
+
+/// A new chunk of the file to be stored.
+struct NewChunk {
+    /// The hash as stored in the chunk store.
+    hash: String,
+    name: String,
+    data: Vec<u8>,
+}
+
+/// A chunk row of the chunk store.
+struct StoredChunk {
+    hash: String,
+    /// The original size of the (compressed) data.
+    size: u64,
+    codec: Option<String>,
+    encryption: Option<&'static str>,
+    data: Vec<u8>,
+}
+
+/// The chunk store table of the content-defined chunks.
+pub(crate) const DIR_CHUNKS: &str = "chunks";
+
+/// The table of the times when the writers referred to the stored chunks again.
+pub(crate) const DIR_TOUCHES: &str = "chunk_touches";
+
+const DEFAULT_MAX_CDC_SIZE: u64 = 4 * 1024 * 1024; // 4 MiB
@@ -0,0 +1,96 @@
+use anyhow::{bail, Context, Result};
+use cdl_catalog::Compression;
+
+/// Compress the chunk data, returning `None` if it is not worth it.
+pub(crate) fn compress(codec: Compression, name: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+    if codec == Compression::None || data.len() < MIN_COMPRESS_SIZE || is_compressed(name) {
+        return Ok(None);
+    }
+
+    let compressed = match codec {
+        Compression::None => return Ok(None),
+        Compression::Lz4 => ::lz4_flex::block::compress(data),
+        Compression::Zstd => ::zstd::bulk::compress(data, ::zstd::DEFAULT_COMPRESSION_LEVEL)
+            .context("Failed to compress the chunk with zstd")?,
+    };
+
+    // Skip the incompressible contents, e.g. the encrypted or random ones
+    if compressed.len() + data.len() / 8 < data.len() {
+        Ok(Some(compressed))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Decompress the chunk data into its original size.
+pub(crate) fn decompress(codec: &str, data: &[u8], size: u64) -> Result<Vec<u8>> {
+    let decompressed = match codec {
+        "lz4" => ::lz4_flex::block::decompress(data, size as _)
+            .context("Failed to decompress the chunk with lz4")?,
+        "zstd" => ::zstd::bulk::decompress(data, size as _)
+            .context("Failed to decompress the chunk with zstd")?,
+        codec => bail!("Unknown compression codec: {codec:?}"),
+    };
+    if decompressed.len() as u64 != size {
+        bail!(
+            "Invalid decompressed chunk size: expected {size}, got {len}",
+            len = decompressed.len(),
+        )
+    }
+    Ok(decompressed)
+}
+
+/// Return `true` if the file is in the well-known compressed format.
+fn is_compressed(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, ext)| {
+        COMPRESSED_EXTENSIONS
+            .iter()
+            .any(|compressed| ext.eq_ignore_ascii_case(compressed))
+    })
+}
+
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "avif", "br", "bz2", "flac", "gif", "gz", "heic", "jpeg", "jpg", "lz4", "m4a", "mkv",
+    "mov", "mp3", "mp4", "ogg", "parquet", "png", "rar", "tgz", "webm", "webp", "xz", "zip", "zst",
+];
+
+/// The smaller chunks are not worth compressing.
+const MIN_COMPRESS_SIZE: usize = 512;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_round_trip() {
+        let data = "name,value\n".repeat(1024).into_bytes();
+        for codec in [Compression::Lz4, Compression::Zstd] {
+            let compressed = compress(codec, "data.csv", &data).unwrap().unwrap();
+            assert!(compressed.len() < data.len() / 4);
+
+            let decompressed =
+                decompress(&codec.to_string(), &compressed, data.len() as _).unwrap();
+            assert_eq!(decompressed, data);
+        }
+
+        // skip the small, already compressed or incompressible contents
+        assert!(compress(Compression::Zstd, "a.csv", b"hello")
+            .unwrap()
+            .is_none());
+        assert!(compress(Compression::Zstd, "data.CSV.gz", &data)
+            .unwrap()
+            .is_none());
+        let mut seed = 42u64;
+        let random: Vec<_> = (0..4096)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect();
+        assert!(compress(Compression::Zstd, "random.bin", &random)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,369 @@
+use core::fmt;
+use std::{fs, sync::Arc};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use aes_siv::siv::Aes256Siv;
+use anyhow::{anyhow, bail, Context, Result};
+use arrow::array::{ArrayRef, AsArray, RecordBatch, StringArray};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use cdl_catalog::DatasetCatalog;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::chunk::encode_hash;
+
+/// Encrypts the rows with the keys of the catalog.
+///
+/// Each chunk is sealed in an envelope: a random data key encrypts the chunk,
+/// and the master key encrypts (wraps) the data key.
+/// The envelope starts with the format version and the master key ID,
+/// so that the rows remain readable while the keys are being rotated.
+///
+/// The names and the content hashes are keyed with the last key instead,
+/// which stays the same while the new keys are prepended,
+/// so that the existing rows can still be looked up after the rotation.
+pub(crate) struct Cipher {
+    encrypt_names: bool,
+    keys: Vec<MasterKey>,
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cipher")
+            .field("encrypt_names", &self.encrypt_names)
+            .field("keys", &self.keys.len())
+            .finish()
+    }
+}
+
+impl Cipher {
+    /// Load the keys of the catalog, if the encryption is enabled.
+    pub(crate) fn try_new(catalog: &DatasetCatalog) -> Result<Option<Arc<Self>>> {
+        let mut encoded: Vec<String> = catalog
+            .encryption_key
+            .iter()
+            .flat_map(|keys| keys.split(','))
+            .map(Into::into)
+            .collect();
+        if let Some(path) = catalog.encryption_key_path.as_ref() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the encryption key file: {path:?}"))?;
+            encoded.extend(content.lines().map(Into::into));
+        }
+
+        let keys = encoded
+            .iter()
+            .map(|key| key.trim())
+            .filter(|key| !key.is_empty())
+            .map(MasterKey::decode)
+            .collect::<Result<Vec<_>>>()?;
+        if keys.is_empty() {
+            if catalog.encrypt_names {
+                bail!("Encrypting the names requires an encryption key")
+            }
+            return Ok(None);
+        }
+
+        Ok(Some(Arc::new(Self {
+            encrypt_names: catalog.encrypt_names,
+            keys,
+        })))
+    }
+
+    /// Seal the chunk data in an envelope with the first key.
+    pub(crate) fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let master = &self.keys[0];
+
+        let data_key = Aes256Gcm::generate_key(&mut OsRng);
+        let key_nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let wrapped_key = master
+            .cipher
+            .encrypt(&key_nonce, data_key.as_slice())
+            .map_err(|_| anyhow!("Failed to wrap the data key"))?;
+
+        let data_nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Aes256Gcm::new(&data_key)
+            .encrypt(&data_nonce, data)
+            .map_err(|_| anyhow!("Failed to encrypt the data"))?;
+
+        let mut buf = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+        buf.push(VERSION);
+        buf.extend_from_slice(&master.id);
+        buf.extend_from_slice(&key_nonce);
+        buf.extend_from_slice(&wrapped_key);
+        buf.extend_from_slice(&data_nonce);
+        buf.extend_from_slice(&ciphertext);
+        Ok(buf)
+    }
+
+    /// Open the envelope with the key it has been sealed with.
+    pub(crate) fn decrypt_data(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < HEADER_SIZE {
+            bail!("Invalid encrypted data: too short")
+        }
+        let (version, data) = data.split_at(1);
+        if version[0] != VERSION {
+            bail!("Unsupported encryption version: {}", version[0])
+        }
+        let (id, data) = data.split_at(KEY_ID_SIZE);
+        let (key_nonce, data) = data.split_at(NONCE_SIZE);
+        let (wrapped_key, data) = data.split_at(WRAPPED_KEY_SIZE);
+        let (data_nonce, ciphertext) = data.split_at(NONCE_SIZE);
+
+        let data_key = self
+            .find_key(id)?
+            .cipher
+            .decrypt(Nonce::from_slice(key_nonce), wrapped_key)
+            .map_err(|_| anyhow!("Failed to unwrap the data key"))?;
+        Aes256Gcm::new_from_slice(&data_key)
+            .map_err(|_| anyhow!("Invalid data key"))?
+            .decrypt(Nonce::from_slice(data_nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt the data"))
+    }
+
+    /// Encrypt each component of the given path deterministically,
+    /// if the names should be encrypted.
+    pub(crate) fn encrypt_path(&self, path: &str) -> Result<String> {
+        if !self.encrypt_names {
+            return Ok(path.into());
+        }
+        let master = self.stable_key();
+
+        path.split('/')
+            .map(|component| {
+                if component.is_empty() {
+                    return Ok(String::default());
+                }
+                let ciphertext = master
+                    .name_cipher()?
+                    .encrypt([NAME_HEADER], component.as_bytes())
+                    .map_err(|_| anyhow!("Failed to encrypt the name"))?;
+                Ok(format!(
+                    "{NAME_PREFIX}{id}.{name}",
+                    id = URL_SAFE_NO_PAD.encode(master.id),
+                    name = URL_SAFE_NO_PAD.encode(ciphertext),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|components| components.join("/"))
+    }
+
+    /// Decrypt the encrypted components of the given path.
+    pub(crate) fn decrypt_path(&self, path: &str) -> Result<String> {
+        if !path.contains(NAME_PREFIX) {
+            return Ok(path.into());
+        }
+
+        path.split('/')
+            .map(|component| {
+                let Some((id, name)) = component
+                    .strip_prefix(NAME_PREFIX)
+                    .and_then(|component| component.split_once('.'))
+                else {
+                    return Ok(component.into());
+                };
+                let id = URL_SAFE_NO_PAD.decode(id)?;
+                let name = URL_SAFE_NO_PAD.decode(name)?;
+                let name = self
+                    .find_key(&id)?
+                    .name_cipher()?
+                    .decrypt([NAME_HEADER], &name)
+                    .map_err(|_| anyhow!("Failed to decrypt the name"))?;
+                String::from_utf8(name).map_err(Into::into)
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|components| components.join("/"))
+    }
+
+    /// Return the keyed hash of the given content hash to be stored,
+    /// so that the stored hashes reveal nothing about the contents.
+    pub(crate) fn mac_hash(&self, hash: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.stable_key().mac_key)
+            .expect("HMAC accepts keys of any size");
+        mac.update(hash.as_bytes());
+        encode_hash(&mac.finalize().into_bytes())
+    }
+
+    /// Decrypt the `parent` and `name` columns of the given batch.
+    pub(crate) fn decrypt_batch_names(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        let schema = batch.schema();
+        let mut columns = batch.columns().to_vec();
+        for (field, column) in schema.fields().iter().zip(columns.iter_mut()) {
+            if !matches!(field.name().as_str(), "parent" | "name") {
+                continue;
+            }
+            let Some(values) = column.as_string_opt::<i32>() else {
+                continue;
+            };
+            let values = values
+                .iter()
+                .map(|value| value.map(|value| self.decrypt_path(value)).transpose())
+                .collect::<Result<StringArray>>()?;
+            *column = Arc::new(values) as ArrayRef;
+        }
+        RecordBatch::try_new(schema, columns).map_err(Into::into)
+    }
+
+    fn stable_key(&self) -> &MasterKey {
+        self.keys.last().expect("Cipher has at least one key")
+    }
+
+    fn find_key(&self, id: &[u8]) -> Result<&MasterKey> {
+        self.keys.iter().find(|key| key.id == id).with_context(|| {
+            format!(
+                "No such encryption key: {id}",
+                id = URL_SAFE_NO_PAD.encode(id),
+            )
+        })
+    }
+}
+
+struct MasterKey {
+    cipher: Aes256Gcm,
+    id: [u8; KEY_ID_SIZE],
+    mac_key: [u8; 32],
+    name_key: [u8; 64],
+}
+
+impl MasterKey {
+    fn decode(encoded: &str) -> Result<Self> {
+        let key = STANDARD
+            .decode(encoded)
+            .context("Invalid encryption key: not a base64 string")?;
+        if key.len() != KEY_SIZE {
+            bail!(
+                "Invalid encryption key: expected {KEY_SIZE} bytes, got {len}",
+                len = key.len(),
+            )
+        }
+
+        let mut id = [0; KEY_ID_SIZE];
+        id.copy_from_slice(&Sha256::digest(&key)[..KEY_ID_SIZE]);
+
+        // Derive a separate key for the names
+        let mut name_key = [0; 64];
+        name_key.copy_from_slice(
+            &Sha512::new()
+                .chain_update(NAME_HEADER)
+                .chain_update(&key)
+                .finalize(),
+        );
+
+        // ... and another one for the content hashes
+        let mut mac_key = [0; 32];
+        mac_key.copy_from_slice(
+            &Sha256::new()
+                .chain_update(MAC_HEADER)
+                .chain_update(&key)
+                .finalize(),
+        );
+
+        Ok(Self {
+            cipher: Aes256Gcm::new_from_slice(&key)
+                .map_err(|_| anyhow!("Invalid encryption key"))?,
+            id,
+            mac_key,
+            name_key,
+        })
+    }
+
+    fn name_cipher(&self) -> Result<Aes256Siv> {
+        Aes256Siv::new_from_slice(&self.name_key).map_err(|_| anyhow!("Invalid name key"))
+    }
+}
+
+/// The `encryption` column value of the rows sealed in the current envelope format.
+pub(crate) const ENCRYPTION_V1: &str = "v1";
+
+const VERSION: u8 = 1;
+
+const KEY_ID_SIZE: usize = 4;
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const WRAPPED_KEY_SIZE: usize = KEY_SIZE + TAG_SIZE;
+const HEADER_SIZE: usize = 1 + KEY_ID_SIZE + NONCE_SIZE + WRAPPED_KEY_SIZE + NONCE_SIZE + TAG_SIZE;
+
+const MAC_HEADER: &[u8] = b"cdl-fs/mac";
+const NAME_HEADER: &[u8] = b"cdl-fs/name";
+const NAME_PREFIX: &str = "~e1.";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(keys: &[[u8; KEY_SIZE]], encrypt_names: bool) -> Arc<Cipher> {
+        let catalog = DatasetCatalog {
+            encrypt_names,
+            encryption_key: Some(
+                keys.iter()
+                    .map(|key| STANDARD.encode(key))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            ..Default::default()
+        };
+        Cipher::try_new(&catalog).unwrap().unwrap()
+    }
+
+    #[test]
+    fn rotate_keys() {
+        let old = cipher(&[[1; KEY_SIZE]], false);
+        let data = old.encrypt_data(b"hello world").unwrap();
+        assert_ne!(&data[HEADER_SIZE - TAG_SIZE..], b"hello world");
+        assert_eq!(old.decrypt_data(&data).unwrap(), b"hello world");
+
+        // the old rows are still readable after the key is rotated
+        let new = cipher(&[[2; KEY_SIZE], [1; KEY_SIZE]], false);
+        assert_eq!(new.decrypt_data(&data).unwrap(), b"hello world");
+        let data = new.encrypt_data(b"hello world").unwrap();
+        assert!(old.decrypt_data(&data).is_err());
+
+        // tampered rows are rejected
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 0xff;
+        assert!(new.decrypt_data(&tampered).is_err());
+    }
+
+    #[test]
+    fn encrypt_names() {
+        let cipher = cipher(&[[1; KEY_SIZE]], true);
+        let path = cipher.encrypt_path("/a/b").unwrap();
+        assert!(path.starts_with(&format!("/{NAME_PREFIX}")));
+        assert_eq!(path.matches('/').count(), 2);
+
+        // the names are encrypted deterministically, component by component
+        let parent = cipher.encrypt_path("/a").unwrap();
+        assert_eq!(cipher.encrypt_path("/a").unwrap(), parent);
+        assert!(path.starts_with(&format!("{parent}/")));
+
+        assert_eq!(cipher.decrypt_path(&path).unwrap(), "/a/b");
+        assert_eq!(cipher.decrypt_path("/plain").unwrap(), "/plain");
+
+        // the existing paths are still looked up after the key is rotated
+        let rotated = self::cipher(&[[2; KEY_SIZE], [1; KEY_SIZE]], true);
+        assert_eq!(rotated.encrypt_path("/a/b").unwrap(), path);
+        assert_eq!(rotated.decrypt_path(&path).unwrap(), "/a/b");
+    }
+
+    #[test]
+    fn mac_hashes() {
+        let hash = encode_hash(&Sha256::digest(b"hello world"));
+        let cipher = cipher(&[[1; KEY_SIZE]], false);
+        let keyed = cipher.mac_hash(&hash);
+        assert_ne!(keyed, hash);
+        assert_eq!(keyed.len(), hash.len());
+
+        // the hashes are stable across the key rotation, but not across the datasets
+        let rotated = self::cipher(&[[2; KEY_SIZE], [1; KEY_SIZE]], false);
+        assert_eq!(rotated.mac_hash(&hash), keyed);
+        let other = self::cipher(&[[2; KEY_SIZE]], false);
+        assert_ne!(other.mac_hash(&hash), keyed);
+    }
+}
@@ -1,26 +1,35 @@
+mod attr;
+mod checkpoint;
+mod chunk;
+mod compress;
+mod crypto;
 mod functions;
+mod maintenance;
+mod progress;
+mod verify;
 
-use core::fmt;
+use core::{fmt, future};
 use std::{
+    collections::{BTreeMap, HashMap},
     io::SeekFrom,
     path::{Path, PathBuf},
     pin::Pin,
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
-use anyhow::{anyhow, bail, Context, Error, Result};
+use anyhow::{bail, Context, Error, Result};
 use arrow::{
     array::{self, ArrayBuilder, ArrayRef, AsArray, RecordBatch},
     datatypes::{
         DataType as ArrowDataType, Field as ArrowField, Schema as ArrowSchema, SchemaRef,
-        TimeUnit as ArrowTimeUnit,
+        TimeUnit as ArrowTimeUnit, TimestampMicrosecondType,
     },
 };
-use cdl_catalog::DatasetCatalog;
+use cdl_catalog::{ChunkingMode, DatasetCatalog};
 use cdl_store::build_registry;
 pub use cdl_store::CachedObjectStoreProvider;
-use chrono::{DateTime, Timelike, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Timelike, Utc};
 use datafusion::{
     error::DataFusionError,
     execution::SendableRecordBatchStream,
@@ -29,29 +38,48 @@ use datafusion::{
 };
 use filetime::FileTime;
 use futures::{stream, Stream, StreamExt, TryStreamExt};
-use glob::glob;
 use itertools::Itertools;
 use lance::{
-    dataset::{builder::DatasetBuilder, InsertBuilder, WriteDestination, WriteMode, WriteParams},
+    dataset::{
+        builder::DatasetBuilder, progress::WriteFragmentProgress, InsertBuilder,
+        NewColumnTransform, UpdateBuilder, WriteDestination, WriteMode, WriteParams,
+    },
     Dataset, Error as LanceError,
 };
 use lance_encoding::version::LanceFileVersion;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use strum::Display;
+use sha2::{Digest, Sha256};
+use strum::{Display, EnumString};
 use tokio::{
     fs,
     io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
     spawn,
-    sync::mpsc,
+    sync::{mpsc, Semaphore},
+    task::spawn_blocking,
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, info, instrument, Level};
 
+use self::{
+    checkpoint::Checkpoint,
+    chunk::{hash_chunk, Chunker, DIR_CHUNKS},
+    crypto::Cipher,
+    verify::FileVerifier,
+};
+pub use self::{
+    maintenance::{CompactReport, GcOptions, GcReport},
+    progress::{Progress, ProgressCallback, ProgressState},
+    verify::{VerifyIssue, VerifyIssueKind, VerifyReport},
+};
+
 pub struct CdlFS {
+    base_catalog: DatasetCatalog,
     catalog: DatasetCatalog,
+    cipher: Option<Arc<Cipher>>,
     ctx: SessionContext,
     path: GlobalPath,
+    view: Mutex<Option<RootfsView>>,
 }
 
 impl CdlFS {
@@ -69,12 +97,99 @@ impl CdlFS {
         self.path.to_string()
     }
 
+    /// Replace the given files and remove the others,
+    /// by appending the new rows and then deleting the superseded ones.
+    ///
+    /// The new rows are committed first, so that the files are never lost
+    /// even if the deletion fails; the leftover rows are hidden by their older commit times.
     #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn commit_files(
+        &self,
+        files: Vec<FileRecord>,
+        removed: Vec<(String, String)>,
+    ) -> Result<()> {
+        self.path.dataset.ensure_writable()?;
+
+        fn paths(files: &[(String, String)]) -> impl '_ + Iterator<Item = (&str, &str)> {
+            files
+                .iter()
+                .map(|(parent, name)| (parent.as_str(), name.as_str()))
+        }
+
+        let replaced: Vec<_> = files
+            .iter()
+            .filter(|file| file.chunk_id == 0)
+            .map(|file| (file.parent.clone(), file.name.clone()))
+            .collect();
+        let has_targets = !replaced.is_empty() || !removed.is_empty();
+
+        // Supersede the previous versions regardless of the clock of this client
+        let latest = match try_open_table(&self.catalog, &self.path.dataset).await? {
+            Some(table) if has_targets => {
+                let targets = paths(&replaced).chain(paths(&removed));
+                let predicates = file_predicates(self.cipher.as_deref(), targets)?;
+                load_latest_commit_time(&table, Some(&predicates)).await?
+            }
+            _ => None,
+        };
+        let commit_time = next_commit_time(latest);
+
+        if !files.is_empty() {
+            let stream = Box::pin(stream::iter(files.into_iter().map(Ok)));
+            let progress = self.catalog.fragment_process();
+            write_files(
+                &self.catalog,
+                &self.path.dataset,
+                stream,
+                progress,
+                commit_time,
+            )
+            .await?;
+        }
+
+        if has_targets {
+            let mut table = self.table().await?;
+            let cipher = self.cipher.as_deref();
+            delete_files(&mut table, cipher, paths(&replaced), Some(commit_time)).await?;
+            delete_files(&mut table, cipher, paths(&removed), None).await?;
+        }
+        self.invalidate()
+    }
+
+    #[inline]
     pub async fn copy_to(&self, dst: &GlobalPath) -> Result<()> {
-        let stream = self.load_all().await?;
-        dst.dump_all(&self.catalog, stream).await
+        self.copy_to_with(dst, CopyOptions::default()).await
+    }
+
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn copy_to_with(&self, dst: &GlobalPath, options: CopyOptions) -> Result<()> {
+        let CopyOptions {
+            progress,
+            restart,
+            sync,
+        } = options;
+        dst.dataset.ensure_writable()?;
+        let dst_catalog = dst.dataset.catalog(&self.base_catalog)?;
+
+        match (self.path.dataset.scheme, dst.dataset.scheme) {
+            (Scheme::Local, Scheme::S3) => {
+                self.upload_to(&dst_catalog, dst, &progress, restart, sync)
+                    .await?
+            }
+            (src, dst) if sync => bail!("Sync mode is not supported: {src} -> {dst}"),
+            _ => {
+                let stream = self.load_all(&progress).await?;
+                dst.dump_all(&dst_catalog, progress.track(stream), &progress)
+                    .await?;
+            }
+        }
+        progress.finish();
+        Ok(())
     }
 
+    /// Run the SQL query on the `rootfs` table.
+    ///
+    /// Note that the encrypted names and data are returned as they are stored.
     #[instrument(skip_all, err(level = Level::ERROR))]
     pub async fn query(&self, sql: &str) -> Result<DataFrame> {
         self.ctx().await?.sql(sql).await.map_err(Into::into)
@@ -83,80 +198,638 @@ impl CdlFS {
     #[instrument(skip_all, err(level = Level::ERROR))]
     pub async fn read_dir(&self, path: impl AsRef<Path>) -> Result<SendableRecordBatchStream> {
         let parent = trim_rel_suffix(path.as_ref().to_str().context("Invalid path")?);
-        let condition =
-            format!("WHERE parent LIKE '{parent}' AND size IS NOT NULL ORDER BY name ASC");
-        self.list_by(&condition).await
+        let parent = self.encrypt_path(parent)?;
+        let scope = format!("parent = '{parent}'", parent = escape_sql_str(&parent));
+        let condition = format!("WHERE {scope} AND size IS NOT NULL ORDER BY name ASC");
+        self.list_by(Some(&scope), &condition).await
     }
 
     #[instrument(skip_all, err(level = Level::ERROR))]
     pub async fn read_dir_all(&self) -> Result<SendableRecordBatchStream> {
         let condition = "WHERE size IS NOT NULL ORDER BY parent ASC, name ASC";
-        self.list_by(condition).await
+        self.list_by(None, condition).await
     }
 
+    /// Read the metadata of the entries in the given directory.
     #[instrument(skip_all, err(level = Level::ERROR))]
-    pub async fn read_files_by_condition(
+    pub async fn read_metadata(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<impl '_ + Send + Stream<Item = Result<Vec<FileRecord>>>> {
+        let stream = self.read_dir(path).await?;
+        let file_stream = stream.map_err(Error::from).and_then(|batch| async move {
+            let batch = FileRecordBatch::try_from(&batch)?;
+            batch.into_vec(self.cipher.as_deref())
+        });
+        Ok(file_stream)
+    }
+
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn read_metadata_all(
         &self,
-        condition: &str,
     ) -> Result<impl '_ + Send + Stream<Item = Result<Vec<FileRecord>>>> {
-        let stream = self.load_by(condition).await?;
+        let stream = self.read_dir_all().await?;
         let file_stream = stream.map_err(Error::from).and_then(|batch| async move {
             let batch = FileRecordBatch::try_from(&batch)?;
-            batch.into_vec()
+            batch.into_vec(self.cipher.as_deref())
         });
         Ok(file_stream)
     }
+
+    /// Read the chunks of the given file which overlap `offset..offset + size`.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn read_file_chunks(
+        &self,
+        parent: &str,
+        name: &str,
+        offset: u64,
+        size: u64,
+    ) -> Result<Vec<FileRecord>> {
+        let scope = format!(
+            "parent = '{parent}' AND name = '{name}'",
+            parent = escape_sql_str(&self.encrypt_path(parent)?),
+            name = escape_sql_str(&self.encrypt_path(name)?),
+        );
+        let condition = format!(
+            "{scope} AND chunk_offset < {end} AND chunk_offset + chunk_size > {offset} \
+            ORDER BY chunk_id ASC",
+            end = offset.saturating_add(size),
+        );
+        self.read_files_by(Some(&scope), &condition)
+            .await?
+            .try_concat()
+            .await
+    }
+
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn read_files_by_condition(
+        &self,
+        condition: &str,
+    ) -> Result<impl '_ + Send + Stream<Item = Result<Vec<FileRecord>>>> {
+        self.read_files_by(None, condition).await
+    }
+
+    /// Remove the entries matching the given path or glob pattern,
+    /// and return the number of the rows removed.
+    ///
+    /// The directories are removed with their descendants only if `recursive` is set.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn remove(&self, path: &str, recursive: bool) -> Result<usize> {
+        self.path.dataset.ensure_writable()?;
+
+        let path = normalize_path(path)?;
+        let mut table = self.table().await?;
+        let index = self.load_index_under(&table, &path).await?;
+        let entries = match_entries(&index, &path)?;
+        if !recursive {
+            if let Some((root, _)) = entries.iter().find(|(_, is_dir)| *is_dir) {
+                bail!("Is a directory: {root}")
+            }
+        }
+
+        let files: Vec<_> = index
+            .keys()
+            .filter(|(parent, name)| entries.iter().any(|(root, _)| is_under(root, parent, name)))
+            .collect();
+        let count = files.len();
+        if count > 0 {
+            let files = files
+                .into_iter()
+                .map(|(parent, name)| (parent.as_str(), name.as_str()));
+            delete_files(&mut table, self.cipher.as_deref(), files, None).await?;
+        }
+        info!("Removed {count} entries: {path}");
+        self.invalidate()?;
+        Ok(count)
+    }
+
+    /// Move the given file or directory, returning the number of the rows moved.
+    ///
+    /// The entry is moved into the destination if it is a directory,
+    /// or replaces the destination if both are files.
+    /// The replaced file is deleted first, and then the entry and its descendants
+    /// are moved in a single commit, so that a failure never leaves them half-moved.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn rename(&self, from: &str, to: &str) -> Result<usize> {
+        self.path.dataset.ensure_writable()?;
+
+        let from = normalize_path(from)?;
+        let mut to = normalize_path(to)?;
+        if is_glob(&from) || is_glob(&to) {
+            bail!("Glob patterns are not supported on moving: {from} -> {to}")
+        }
+
+        let table = self.table().await?;
+        let index = self.load_index_under(&table, &from).await?;
+        let is_dir = match match_entries(&index, &from)?.as_slice() {
+            [] => bail!("No such file or directory: {from}"),
+            [(_, is_dir), ..] => *is_dir,
+        };
+
+        // Move into the existing directory
+        let mut target = self.load_index_under(&table, &to).await?;
+        if let Some((_, true)) = match_entries(&target, &to)?.first() {
+            to = format!("{to}/{name}", name = split_path(&from).1);
+            target = self.load_index_under(&table, &to).await?;
+        }
+        if from == to {
+            return Ok(0);
+        }
+        if is_under(&from, split_path(&to).0, split_path(&to).1) {
+            bail!("Cannot move a directory into itself: {from} -> {to}")
+        }
+        match match_entries(&target, &to)?.first() {
+            Some((_, true)) => bail!("Directory already exists: {to}"),
+            Some(_) if is_dir => bail!("Cannot replace a file with a directory: {to}"),
+            Some(_) => {
+                let mut table = self.table().await?;
+                delete_files(&mut table, self.cipher.as_deref(), [split_path(&to)], None).await?;
+            }
+            None => (),
+        }
+
+        let count = index
+            .keys()
+            .filter(|(parent, name)| is_under(&from, parent, name))
+            .count();
+        let table = self.table().await?;
+        move_files(table, self.cipher.as_deref(), &from, &to, is_dir).await?;
+        info!("Moved {count} entries: {from} -> {to}");
+        self.invalidate()?;
+        Ok(count)
+    }
+
+    /// Update the mode and timestamps of the given file in place, without rewriting its contents.
+    ///
+    /// Only the first chunk carrying the metadata is updated, keeping its commit timestamp
+    /// so that the file still consists of the same rows.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn update_metadata(
+        &self,
+        parent: &str,
+        name: &str,
+        metadata: &FileMetadataRecord,
+    ) -> Result<()> {
+        self.path.dataset.ensure_writable()?;
+
+        let predicate = format!(
+            "parent = '{parent}' AND name = '{name}' AND chunk_id = 0",
+            parent = escape_sql_str(&self.encrypt_path(parent)?),
+            name = escape_sql_str(&self.encrypt_path(name)?),
+        );
+        let values = [
+            ("atime", timestamp_literal(metadata.atime)),
+            ("ctime", timestamp_literal(metadata.ctime)),
+            ("mtime", timestamp_literal(metadata.mtime)),
+            ("mode", metadata.mode.to_string()),
+        ];
+
+        let table = Arc::new(self.table().await?);
+        let mut builder = UpdateBuilder::new(table).update_where(&predicate)?;
+        for (column, value) in values {
+            builder = builder.set(column, &value)?;
+        }
+        builder
+            .build()?
+            .execute()
+            .await
+            .with_context(|| format!("Failed to update the metadata: {parent}/{name}"))?;
+        self.invalidate()
+    }
+
+    /// Check the checksums and sizes of the files under the path,
+    /// and report the corrupted ones.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn verify(&self) -> Result<VerifyReport> {
+        let root = match trim_rel_path(self.path.rel.to_str().context("Invalid path")?) {
+            "" => String::default(),
+            root => format!("/{root}"),
+        };
+        let filter = root_filter(&self.encrypt_path(&root)?);
+
+        let table = self.table().await?;
+        let store = chunk::try_open_store(&self.catalog, &self.path.dataset).await?;
+        let cipher = self.cipher.as_deref();
+        verify::verify_table(&table, store.as_ref(), cipher, filter.as_deref()).await
+    }
+
+    /// Merge the small fragments of the rootfs table and the chunk store.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn compact(&self, dry_run: bool) -> Result<CompactReport> {
+        self.path.dataset.ensure_writable()?;
+
+        let mut table = self.table().await?;
+        let mut store = chunk::try_open_store(&self.catalog, &self.path.dataset).await?;
+        let report =
+            maintenance::compact_all(&self.catalog, &mut table, store.as_mut(), dry_run).await?;
+        self.invalidate()?;
+        Ok(report)
+    }
+
+    /// Remove the old versions of the dataset and the files no longer referred to.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn gc(&self, options: &GcOptions) -> Result<GcReport> {
+        self.path.dataset.ensure_writable()?;
+
+        let table = self.table().await?;
+        let mut store = chunk::try_open_store(&self.catalog, &self.path.dataset).await?;
+        let touches_uri = self.path.dataset.to_uri(chunk::DIR_TOUCHES);
+        let report =
+            maintenance::gc_all(&self.catalog, &table, store.as_mut(), &touches_uri, options)
+                .await?;
+        self.invalidate()?;
+        Ok(report)
+    }
+
+    /// List the versions of the dataset with their commit times, the oldest first.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn versions(&self) -> Result<Vec<DatasetVersionInfo>> {
+        let table = self.table().await?;
+        let mut versions = Vec::default();
+        for version in table.versions().await? {
+            let dataset = table.checkout_version(version.version).await?;
+            // The manifest records the row counts of the fragments, except for the legacy ones
+            let rows = match dataset
+                .get_fragments()
+                .iter()
+                .map(|fragment| fragment.metadata().num_rows())
+                .sum::<Option<usize>>()
+            {
+                Some(rows) => rows,
+                None => dataset.count_rows(None).await?,
+            };
+            versions.push(DatasetVersionInfo {
+                version: version.version,
+                timestamp: version.timestamp,
+                rows,
+            });
+        }
+        Ok(versions)
+    }
+
+    /// Return the number of the opened version, which is the latest one if not selected.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn version(&self) -> Result<u64> {
+        self.table().await.map(|table| table.version().version)
+    }
+
+    /// List the tags of the dataset, ordered by name.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn tags(&self) -> Result<Vec<DatasetTag>> {
+        let table = self.table().await?;
+        let mut tags: Vec<_> = table
+            .tags
+            .list()
+            .await?
+            .into_iter()
+            .map(|(name, contents)| DatasetTag {
+                name,
+                version: contents.version,
+            })
+            .collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    /// Tag the given version, or the opened one if not given.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn create_tag(&self, name: &str, version: Option<u64>) -> Result<DatasetTag> {
+        // The tags are selected by the same syntax as the version numbers and times
+        if name.parse::<DatasetVersion>().is_ok() {
+            bail!("Tag name is ambiguous with a dataset version: #{name}")
+        }
+
+        let mut table = self.table().await?;
+        let version = version.unwrap_or_else(|| table.version().version);
+        table
+            .tags
+            .create(name, version)
+            .await
+            .with_context(|| format!("Failed to create the tag: #{name}"))?;
+        Ok(DatasetTag {
+            name: name.into(),
+            version,
+        })
+    }
+
+    /// Remove the given tag, leaving its version to be cleaned up.
+    #[instrument(skip_all, err(level = Level::ERROR))]
+    pub async fn delete_tag(&self, name: &str) -> Result<()> {
+        let mut table = self.table().await?;
+        table
+            .tags
+            .delete(name)
+            .await
+            .with_context(|| format!("Failed to delete the tag: #{name}"))
+    }
 }
 
 impl CdlFS {
     async fn ctx(&self) -> Result<&SessionContext> {
         if !self.ctx.table_exist(DIR_ROOTFS)? {
-            let table = Arc::new(self.table().await?);
-            self.ctx.register_table(DIR_ROOTFS, table)?;
+            let table = self.table().await?;
+            let schema = table.schema();
+            let columns = FileRecord::columns_arrow()
+                .into_iter()
+                .map(|field| field.name().clone())
+                .filter(|name| {
+                    !matches!(name.as_str(), "data" | "codec" | "encryption")
+                        && schema.field(name).is_some()
+                })
+                .map(|name| format!("f.{name}"))
+                .join(", ");
+            let has_chunk_hash = schema.field("chunk_hash").is_some();
+            let has_codecs =
+                ["codec", "encryption"].map(|name| (name, schema.field(name).is_some()));
+            let has_commit_time = schema.field("commit_time").is_some();
+            self.ctx
+                .register_table(TABLE_ROOTFS_VERSIONS, Arc::new(table))?;
+
+            // Fill the data of the content-defined chunks from the chunk store
+            let store = match has_chunk_hash {
+                true => chunk::try_open_store(&self.catalog, &self.path.dataset).await?,
+                false => None,
+            };
+            let store_columns = match store {
+                Some(store) => {
+                    // Pass the codecs and ciphers of the stored chunks to decode them
+                    let codecs = has_codecs
+                        .into_iter()
+                        .filter_map(|(name, has_codec)| {
+                            match (has_codec, store.schema().field(name).is_some()) {
+                                (true, true) => {
+                                    Some(format!(", COALESCE(c.{name}, f.{name}) AS {name}"))
+                                }
+                                (true, false) => Some(format!(", f.{name}")),
+                                (false, true) => Some(format!(", c.{name}")),
+                                (false, false) => None,
+                            }
+                        })
+                        .join("");
+                    self.ctx.register_table(DIR_CHUNKS, Arc::new(store))?;
+                    Some(format!(
+                        "{columns}, COALESCE(c.data, f.data) AS data{codecs}"
+                    ))
+                }
+                None => None,
+            };
+
+            let view = RootfsView {
+                has_commit_time,
+                store_columns,
+            };
+            let sql = view.to_sql(None);
+            *self.view.lock().unwrap() = Some(view);
+            let view = self.ctx.sql(&sql).await?.into_view();
+            self.ctx.register_table(DIR_ROOTFS, view)?;
         }
         Ok(&self.ctx)
     }
 
-    #[inline]
-    async fn list_by(&self, condition: &str) -> Result<SendableRecordBatchStream> {
-        let sql = format!(
-            "SELECT name, parent, atime, ctime, mtime, mode, size, chunk_id, chunk_offset, chunk_size, x'' AS data FROM {DIR_ROOTFS} {condition}"
-        );
+    /// Return the `rootfs` view narrowed down to the given scope of `parent` and `name`,
+    /// so that the latest versions are looked up among the matching rows only.
+    async fn scoped_view(&self, scope: Option<&str>) -> Result<String> {
+        self.ctx().await?;
+        let Some(scope) = scope else {
+            return Ok(DIR_ROOTFS.into());
+        };
+        match self.view.lock().unwrap().as_ref() {
+            Some(view) => Ok(format!(
+                "({sql}) AS {DIR_ROOTFS}",
+                sql = view.to_sql(Some(scope)),
+            )),
+            None => bail!("The rootfs table is not loaded"),
+        }
+    }
+
+    /// Drop the outdated table snapshots.
+    fn invalidate(&self) -> Result<()> {
+        *self.view.lock().unwrap() = None;
+        self.ctx.deregister_table(DIR_ROOTFS)?;
+        self.ctx.deregister_table(DIR_CHUNKS)?;
+        self.ctx.deregister_table(TABLE_ROOTFS_VERSIONS)?;
+        Ok(())
+    }
+
+    async fn list_by(
+        &self,
+        scope: Option<&str>,
+        condition: &str,
+    ) -> Result<SendableRecordBatchStream> {
+        // Skip the chunk data, keeping the metadata columns of the older tables
+        let schema = self.ctx().await?.table_provider(DIR_ROOTFS).await?.schema();
+        let view = self.scoped_view(scope).await?;
+        let columns = FileRecord::columns_arrow()
+            .into_iter()
+            .map(|field| field.name().clone())
+            .filter(|name| {
+                !matches!(name.as_str(), "data" | "codec" | "encryption")
+                    && schema.field_with_name(name).is_ok()
+            })
+            .join(", ");
+        let sql = format!("SELECT {columns}, x'' AS data FROM {view} {condition}");
         debug!("Querying LIST: {sql}");
 
         let df = self.query(&sql).await?;
-        df.execute_stream()
+        let stream = df
+            .execute_stream()
             .await
-            .context("Failed to execute the dataframe")
+            .context("Failed to execute the dataframe")?;
+
+        // Show the names in plain text
+        match self.cipher.clone() {
+            Some(cipher) => {
+                let schema = stream.schema();
+                let stream = stream.and_then(move |batch| {
+                    future::ready(
+                        cipher
+                            .decrypt_batch_names(batch)
+                            .map_err(|error| DataFusionError::External(error.into())),
+                    )
+                });
+                Ok(Box::pin(RecordBatchStreamAdapter::new(schema, stream)))
+            }
+            None => Ok(stream),
+        }
+    }
+
+    /// Load the entries which may match the given path or glob pattern.
+    async fn load_index_under(&self, table: &Dataset, path: &str) -> Result<FileIndex> {
+        // Narrow down to the directory before the first glob pattern
+        let prefix = path
+            .split('/')
+            .take_while(|component| !is_glob(component))
+            .join("/");
+        let filter = root_filter(&self.encrypt_path(&prefix)?);
+        load_index(table, self.cipher.as_deref(), filter.as_deref()).await
+    }
+
+    /// Encrypt the given path if the names are encrypted.
+    fn encrypt_path(&self, path: &str) -> Result<String> {
+        match self.cipher.as_ref() {
+            Some(cipher) => cipher.encrypt_path(path),
+            None => Ok(path.into()),
+        }
+    }
+
+    /// Upload the local files, committing them at every checkpoint.
+    ///
+    /// The committed files are recorded in a local journal,
+    /// so that re-running the interrupted upload skips them.
+    async fn upload_to(
+        &self,
+        dst_catalog: &DatasetCatalog,
+        dst: &GlobalPath,
+        progress: &Progress,
+        restart: bool,
+        sync: bool,
+    ) -> Result<()> {
+        let root = fs::canonicalize(&self.path.rel).await?;
+        let mut checkpoint = Checkpoint::open(dst_catalog, &root, dst, restart).await?;
+
+        let mut files = FileRecord::stat_all(&self.catalog, &root).await?;
+        let mut removed = Vec::default();
+        if sync {
+            (files, removed) = sync_files(dst_catalog, dst, files).await?;
+        }
+        files.retain(|file| !checkpoint.contains(file));
+        progress.add_total(
+            files.len() as _,
+            files.iter().map(|file| file.metadata.size).sum(),
+        );
+
+        // Scan the latest commit timestamp once, and carry it forward across the checkpoints
+        let cipher = Cipher::try_new(dst_catalog)?;
+        let mut commit_time = load_next_commit_time(dst_catalog, &dst.dataset).await?;
+        for files in split_checkpoints(dst_catalog, files) {
+            let stream = Box::pin(FileRecord::load_files(dst_catalog.clone(), files.clone()));
+            dst.dump_all_to_s3(dst_catalog, progress.track(stream), progress, commit_time)
+                .await?;
+
+            // Remove the rows superseded by the committed files before recording them,
+            // so that the interrupted sync re-uploads and removes them again
+            if sync {
+                let mut table = open_table(dst_catalog, &dst.dataset).await?;
+                let targets = files
+                    .iter()
+                    .map(|file| (file.parent.as_str(), file.name.as_str()));
+                delete_files(&mut table, cipher.as_deref(), targets, Some(commit_time)).await?;
+            }
+            checkpoint.record(&files).await?;
+            commit_time = next_commit_time(Some(commit_time));
+        }
+
+        // Remove the deleted files only after the others are committed,
+        // so that the interrupted sync never loses the files
+        if !removed.is_empty() {
+            let mut table = open_table(dst_catalog, &dst.dataset).await?;
+            let targets = removed
+                .iter()
+                .map(|(parent, name)| (parent.as_str(), name.as_str()));
+            delete_files(&mut table, cipher.as_deref(), targets, None).await?;
+        }
+        checkpoint.finish().await
     }
 
-    async fn load_all(&self) -> Result<FileRecordStream> {
+    async fn load_all(&self, progress: &Progress) -> Result<FileRecordStream> {
         let Self {
             catalog,
+            base_catalog: _,
+            cipher,
             ctx: _,
+            view: _,
             path: GlobalPath { dataset, rel: root },
         } = self;
 
         match dataset.scheme {
-            Scheme::Local => Ok(Box::pin(
-                FileRecord::<Vec<u8>>::load_all(catalog.clone(), root).await?,
-            )),
+            Scheme::Local => {
+                let root = fs::canonicalize(root).await?;
+                let files = FileRecord::stat_all(catalog, &root).await?;
+                progress.add_total(
+                    files.len() as _,
+                    files.iter().map(|file| file.metadata.size).sum(),
+                );
+                Ok(Box::pin(FileRecord::load_files(catalog.clone(), files)))
+            }
             Scheme::S3 => {
+                let root = match trim_rel_path(root.to_str().context("Invalid path")?) {
+                    "" => String::default(),
+                    root => format!("/{root}"),
+                };
+                let filter = root_filter(&self.encrypt_path(&root)?);
+
+                let store = Arc::new(chunk::try_open_store(catalog, dataset).await?);
                 let dataset = open_table(catalog, dataset).await?;
-                let stream = dataset
-                    .scan()
-                    // TODO: filter root path
-                    // .filter()
+                let index = load_index(&dataset, cipher.as_deref(), filter.as_deref()).await?;
+                if filter.is_some() && index.is_empty() {
+                    bail!("No such file or directory: {path}", path = self.path);
+                }
+                progress.add_total(
+                    index.len() as _,
+                    index.values().map(|(_, metadata)| metadata.size).sum(),
+                );
+                let cipher = cipher.clone();
+                let index = Arc::new(index);
+                let root = Arc::new(root);
+                let verifiers = Arc::new(Mutex::new(HashMap::default()));
+
+                let mut scanner = dataset.scan();
+                if let Some(filter) = filter.as_deref() {
+                    scanner.filter(filter)?;
+                }
+                let stream = scanner
                     .scan_in_order(true)
                     .use_stats(self.catalog.enable_statistics())
                     .try_into_stream()
                     .await?
-                    .map(|batch| {
-                        batch
-                            .map_err(Into::into)
-                            .and_then(|ref batch| FileRecordBatch::try_from(batch))
-                            .and_then(FileRecordBatch::into_vec)
-                            .map(|records| stream::iter(records.into_iter().map(Ok)))
+                    .map_err(Error::from)
+                    .and_then(move |batch| {
+                        let cipher = cipher.clone();
+                        let index = index.clone();
+                        let root = root.clone();
+                        let store = store.clone();
+                        let verifiers = verifiers.clone();
+                        async move {
+                            let cipher = cipher.as_deref();
+                            let rows = FileRecordBatch::try_from(&batch)?.into_rows(cipher)?;
+
+                            // skip the outdated versions of each file
+                            let mut records = rows
+                                .into_iter()
+                                .filter(|(commit_time, file)| {
+                                    let key = (file.parent.clone(), file.name.clone());
+                                    matches!(
+                                        index.get(&key),
+                                        Some((latest, _)) if latest == commit_time,
+                                    )
+                                })
+                                .filter_map(|(_, mut file)| {
+                                    strip_root(&root, &mut file).then_some(file)
+                                })
+                                .collect::<Vec<_>>();
+                            chunk::resolve_chunks(store.as_ref().as_ref(), cipher, &mut records)
+                                .await?;
+
+                            // never pass the corrupted chunks to the destination,
+                            // nor the hashes keyed with the source cipher
+                            let mut verifiers = verifiers.lock().unwrap();
+                            for file in &mut records {
+                                let issue = verify::verify_chunk(cipher, file).or_else(|| {
+                                    let cipher = cipher?;
+                                    verify::unkey_hashes(cipher, &mut verifiers, file)
+                                });
+                                if let Some(kind) = issue {
+                                    let issue = VerifyIssue {
+                                        parent: file.parent.clone(),
+                                        name: file.name.clone(),
+                                        chunk_id: Some(file.chunk_id),
+                                        kind,
+                                    };
+                                    bail!("Corrupted file: {issue}");
+                                }
+                            }
+                            Ok(stream::iter(records.into_iter().map(Ok)))
+                        }
                     })
                     .try_flatten();
                 Ok(Box::pin(stream))
@@ -164,9 +837,27 @@ impl CdlFS {
         }
     }
 
+    async fn read_files_by(
+        &self,
+        scope: Option<&str>,
+        condition: &str,
+    ) -> Result<impl '_ + Send + Stream<Item = Result<Vec<FileRecord>>>> {
+        let stream = self.load_by(scope, condition).await?;
+        let file_stream = stream.map_err(Error::from).and_then(|batch| async move {
+            let batch = FileRecordBatch::try_from(&batch)?;
+            batch.into_vec(self.cipher.as_deref())
+        });
+        Ok(file_stream)
+    }
+
     #[inline]
-    async fn load_by(&self, condition: &str) -> Result<SendableRecordBatchStream> {
-        let sql = format!("SELECT * FROM {DIR_ROOTFS} WHERE {condition}");
+    async fn load_by(
+        &self,
+        scope: Option<&str>,
+        condition: &str,
+    ) -> Result<SendableRecordBatchStream> {
+        let view = self.scoped_view(scope).await?;
+        let sql = format!("SELECT * FROM {view} WHERE {condition}");
         info!("Querying LOAD: {sql}");
 
         let df = self.query(&sql).await?;
@@ -178,7 +869,10 @@ impl CdlFS {
     async fn table(&self) -> Result<Dataset> {
         let Self {
             catalog,
+            base_catalog: _,
+            cipher: _,
             ctx: _,
+            view: _,
             path: GlobalPath { dataset, .. },
         } = self;
 
@@ -189,6 +883,80 @@ impl CdlFS {
     }
 }
 
+/// Builds the `rootfs` view exposing the latest version of each file.
+#[derive(Clone, Debug)]
+struct RootfsView {
+    has_commit_time: bool,
+    /// The columns of the rows joined with the chunk store, if any.
+    store_columns: Option<String>,
+}
+
+impl RootfsView {
+    /// Return the query of the view, narrowed down to the given scope if any.
+    ///
+    /// The scope on `parent` and `name` is applied before the aggregation,
+    /// as it cannot be pushed down through the join on the lookups.
+    fn to_sql(&self, scope: Option<&str>) -> String {
+        let (table, filter) = match scope {
+            Some(scope) => (
+                format!("(SELECT * FROM {TABLE_ROOTFS_VERSIONS} WHERE {scope})"),
+                format!(" AND ({scope})"),
+            ),
+            None => (TABLE_ROOTFS_VERSIONS.into(), String::default()),
+        };
+
+        // Expose the latest version of each file only
+        let sql = if self.has_commit_time {
+            format!(
+                "SELECT f.* FROM {table} AS f \
+                INNER JOIN ( \
+                    SELECT parent, name, MAX(commit_time) AS commit_time \
+                    FROM {TABLE_ROOTFS_VERSIONS} WHERE size IS NOT NULL{filter} \
+                    GROUP BY parent, name \
+                ) AS latest \
+                ON f.parent = latest.parent AND f.name = latest.name \
+                AND f.commit_time IS NOT DISTINCT FROM latest.commit_time"
+            )
+        } else {
+            format!("SELECT * FROM {table}")
+        };
+
+        match self.store_columns.as_deref() {
+            Some(columns) => {
+                // The concurrent uploads may store the same chunk twice,
+                // so keep a row per chunk of the file
+                let keys = match self.has_commit_time {
+                    true => "f.parent, f.name, f.chunk_id, f.commit_time",
+                    false => "f.parent, f.name, f.chunk_id",
+                };
+                format!(
+                    "SELECT DISTINCT ON ({keys}) {columns} \
+                    FROM ({sql}) AS f \
+                    LEFT JOIN {DIR_CHUNKS} AS c ON f.chunk_hash = c.hash"
+                )
+            }
+            None => sql,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct CopyOptions {
+    /// Reports the files, bytes and fragments written.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub progress: Progress,
+
+    /// Discard the checkpoint of the interrupted upload and start over.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub restart: bool,
+
+    /// Upload the new or changed files only, and remove the deleted ones.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sync: bool,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
@@ -211,10 +979,30 @@ impl FromStr for GlobalPath {
         let scheme = Scheme::from_str(scheme)?;
 
         let mut slice = next.split("/");
-        let name = slice.next().unwrap().trim();
+        let host = slice.next().unwrap().trim();
+        // The trailing version selector, e.g. `#v12` or `#train-v3`;
+        // `@` is left for the profile as the bucket names cannot contain either
+        let (host, version) = match host.split_once('#') {
+            Some((_, "")) => bail!("Empty dataset version: {s}"),
+            Some((host, version)) => {
+                let version = version.trim();
+                let version = version
+                    .parse()
+                    .unwrap_or_else(|_| DatasetVersion::Tag(version.into()));
+                (host.trim(), Some(version))
+            }
+            None => (host, None),
+        };
+        let (profile, name) = match host.split_once('@') {
+            Some((profile, name)) => (Some(profile.trim()), name.trim()),
+            None => (None, host),
+        };
         if name.is_empty() {
             bail!("Empty dataset name: {s}")
         }
+        if profile.is_some_and(|profile| profile.is_empty()) {
+            bail!("Empty catalog profile name: {s}")
+        }
 
         let rel = slice.join("/").trim().parse()?;
 
@@ -222,6 +1010,8 @@ impl FromStr for GlobalPath {
             dataset: DatasetPath {
                 scheme,
                 name: name.into(),
+                profile: profile.map(Into::into),
+                version,
             },
             rel,
         })
@@ -248,7 +1038,9 @@ impl GlobalPath {
     }
 
     #[instrument(skip_all, err(level = Level::ERROR))]
-    pub async fn open(self, catalog: DatasetCatalog) -> Result<CdlFS> {
+    pub async fn open(self, base_catalog: DatasetCatalog) -> Result<CdlFS> {
+        let catalog = self.dataset.catalog(&base_catalog)?;
+
         let mut config = SessionConfig::new();
         {
             let options = config.options_mut();
@@ -260,17 +1052,31 @@ impl GlobalPath {
         let ctx: SessionContext = SessionContext::new_with_config(config);
         ctx.register_udf(crate::functions::len::Udf::build());
 
+        let cipher = Cipher::try_new(&catalog)?;
+
         Ok(CdlFS {
+            base_catalog,
             catalog,
+            cipher,
             ctx,
             path: self,
+            view: Mutex::default(),
         })
     }
 
-    async fn dump_all(&self, catalog: &DatasetCatalog, stream: FileRecordStream) -> Result<()> {
+    async fn dump_all(
+        &self,
+        catalog: &DatasetCatalog,
+        stream: FileRecordStream,
+        progress: &Progress,
+    ) -> Result<()> {
         match self.dataset.scheme {
             Scheme::Local => FileRecord::dump_all(&self.rel, stream).await,
-            Scheme::S3 => self.dump_all_to_s3(catalog, stream).await,
+            Scheme::S3 => {
+                let commit_time = load_next_commit_time(catalog, &self.dataset).await?;
+                self.dump_all_to_s3(catalog, stream, progress, commit_time)
+                    .await
+            }
         }
     }
 
@@ -278,10 +1084,11 @@ impl GlobalPath {
         &self,
         catalog: &DatasetCatalog,
         stream: FileRecordStream,
+        progress: &Progress,
+        commit_time: i64,
     ) -> Result<()> {
-        let stream = file_stream_to_batch_stream(catalog, stream).await?;
-        commit_table(catalog, &self.dataset, stream).await?;
-        Ok(())
+        let progress = Arc::new(progress.clone());
+        write_files(catalog, &self.dataset, stream, progress, commit_time).await
         // let Writer {
         //     actions,
         //     count,
@@ -322,12 +1129,30 @@ impl GlobalPath {
 pub struct DatasetPath {
     pub scheme: Scheme,
     pub name: String,
+    /// The name of the catalog profile to access the dataset.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub profile: Option<String>,
+    /// The previous version of the dataset to read, if any.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub version: Option<DatasetVersion>,
 }
 
 impl fmt::Display for DatasetPath {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self { scheme, name } = self;
-        write!(f, "{scheme}://{name}")
+        let Self {
+            scheme,
+            name,
+            profile,
+            version,
+        } = self;
+        match profile {
+            Some(profile) => write!(f, "{scheme}://{profile}@{name}")?,
+            None => write!(f, "{scheme}://{name}")?,
+        }
+        match version {
+            Some(version) => write!(f, "#{version}"),
+            None => Ok(()),
+        }
     }
 }
 
@@ -337,6 +1162,26 @@ impl DatasetPath {
         DatasetPath {
             scheme: Scheme::Local,
             name: "localhost".into(),
+            profile: None,
+            version: None,
+        }
+    }
+
+    /// Fail if the dataset is pinned to a previous version, which is read-only.
+    fn ensure_writable(&self) -> Result<()> {
+        match self.version.as_ref() {
+            Some(version) => bail!("Cannot modify the previous version {version} of {self}"),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the catalog to access the dataset, applying its profile if any.
+    pub fn catalog(&self, base: &DatasetCatalog) -> Result<DatasetCatalog> {
+        match self.profile.as_deref() {
+            Some(profile) => base
+                .with_profile(profile)
+                .with_context(|| format!("Failed to load the catalog of {self}")),
+            None => Ok(base.clone()),
         }
     }
 
@@ -352,6 +1197,78 @@ impl DatasetPath {
     }
 }
 
+/// Selects a previous version of the dataset, either by its number (`#v12`),
+/// by the time it was current (`#2026-10-01T00:00Z`) or by its tag (`#train-v3`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DatasetVersion {
+    Number(u64),
+    Timestamp(DateTime<Utc>),
+    Tag(String),
+}
+
+impl FromStr for DatasetVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(number) = s.strip_prefix('v') {
+            return number
+                .parse()
+                .map(Self::Number)
+                .with_context(|| format!("Invalid dataset version: {s:?}"));
+        }
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Timestamp(timestamp.to_utc()));
+        }
+
+        // Allow omitting the seconds and the time zone (UTC)
+        let naive = s.strip_suffix('Z').unwrap_or(s);
+        ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"]
+            .into_iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(naive, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(naive, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+            .map(|timestamp| Self::Timestamp(timestamp.and_utc()))
+            .with_context(|| format!("Invalid dataset version: {s:?}"))
+    }
+}
+
+impl fmt::Display for DatasetVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "v{number}"),
+            Self::Timestamp(timestamp) => timestamp
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                .fmt(f),
+            Self::Tag(tag) => tag.fmt(f),
+        }
+    }
+}
+
+/// A version of the dataset with its commit time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct DatasetVersionInfo {
+    pub version: u64,
+    pub timestamp: DateTime<Utc>,
+    /// The number of rows in the rootfs table, including the outdated versions of the files.
+    pub rows: usize,
+}
+
+/// A named version of the dataset, which is protected from the cleanup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct DatasetTag {
+    pub name: String,
+    pub version: u64,
+}
+
 #[derive(Copy, Clone, Debug, Display, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
@@ -384,7 +1301,19 @@ struct FileRecordBatch {
     pub chunk_id: array::UInt64Array,
     pub chunk_offset: array::UInt64Array,
     pub chunk_size: array::UInt64Array,
-    pub data: array::BinaryArray,
+    pub data: Option<array::BinaryArray>,
+    pub commit_time: Option<array::TimestampMicrosecondArray>,
+    pub chunk_hash: Option<array::StringArray>,
+    pub checksum: Option<array::StringArray>,
+    pub file_checksum: Option<array::StringArray>,
+    pub codec: Option<array::StringArray>,
+    pub encryption: Option<array::StringArray>,
+    pub file_type: Option<array::StringArray>,
+    pub link_target: Option<array::StringArray>,
+    pub uid: Option<array::UInt32Array>,
+    pub gid: Option<array::UInt32Array>,
+    pub xattrs: Option<array::StringArray>,
+    pub link_id: Option<array::StringArray>,
 }
 
 impl TryFrom<&RecordBatch> for FileRecordBatch {
@@ -409,6 +1338,20 @@ impl TryFrom<&RecordBatch> for FileRecordBatch {
                 })
         }
 
+        fn get_column_opt<T>(
+            batch: &RecordBatch,
+            name: &str,
+            cast: impl FnOnce(&ArrayRef) -> Option<&T>,
+        ) -> Result<Option<T>>
+        where
+            T: Clone,
+        {
+            match batch.column_by_name(name) {
+                Some(_) => get_column(batch, name, cast).map(Some),
+                None => Ok(None),
+            }
+        }
+
         Ok(Self {
             name: get_column(batch, "name", |c| c.as_string_opt())?,
             parent: get_column(batch, "parent", |c| c.as_string_opt())?,
@@ -420,13 +1363,34 @@ impl TryFrom<&RecordBatch> for FileRecordBatch {
             chunk_id: get_column(batch, "chunk_id", |c| c.as_primitive_opt())?,
             chunk_offset: get_column(batch, "chunk_offset", |c| c.as_primitive_opt())?,
             chunk_size: get_column(batch, "chunk_size", |c| c.as_primitive_opt())?,
-            data: get_column(batch, "data", |c| c.as_binary_opt())?,
+            data: get_column_opt(batch, "data", |c| c.as_binary_opt())?,
+            commit_time: get_column_opt(batch, "commit_time", |c| c.as_primitive_opt())?,
+            chunk_hash: get_column_opt(batch, "chunk_hash", |c| c.as_string_opt())?,
+            checksum: get_column_opt(batch, "checksum", |c| c.as_string_opt())?,
+            file_checksum: get_column_opt(batch, "file_checksum", |c| c.as_string_opt())?,
+            codec: get_column_opt(batch, "codec", |c| c.as_string_opt())?,
+            encryption: get_column_opt(batch, "encryption", |c| c.as_string_opt())?,
+            file_type: get_column_opt(batch, "file_type", |c| c.as_string_opt())?,
+            link_target: get_column_opt(batch, "link_target", |c| c.as_string_opt())?,
+            uid: get_column_opt(batch, "uid", |c| c.as_primitive_opt())?,
+            gid: get_column_opt(batch, "gid", |c| c.as_primitive_opt())?,
+            xattrs: get_column_opt(batch, "xattrs", |c| c.as_string_opt())?,
+            link_id: get_column_opt(batch, "link_id", |c| c.as_string_opt())?,
         })
     }
 }
 
 impl FileRecordBatch {
-    fn into_vec(self) -> Result<Vec<FileRecord>> {
+    #[inline]
+    fn into_vec(self, cipher: Option<&Cipher>) -> Result<Vec<FileRecord>> {
+        self.into_rows(cipher)
+            .map(|rows| rows.into_iter().map(|(_, file)| file).collect())
+    }
+
+    /// Return the records with their commit timestamps (in microseconds).
+    ///
+    /// The names and the chunk data are decrypted with the given cipher, if any.
+    fn into_rows(self, cipher: Option<&Cipher>) -> Result<Vec<(Option<i64>, FileRecord)>> {
         let Self {
             name,
             parent,
@@ -439,8 +1403,23 @@ impl FileRecordBatch {
             chunk_offset,
             chunk_size,
             data,
+            commit_time,
+            chunk_hash,
+            checksum,
+            file_checksum,
+            codec,
+            encryption,
+            file_type,
+            link_target,
+            uid,
+            gid,
+            xattrs,
+            link_id,
         } = self;
 
+        // Decode the chunk data only if it is loaded
+        let has_data = data.is_some();
+
         let mut name = name.into_iter();
         let mut parent = parent.into_iter();
         let mut atime = atime.into_iter();
@@ -448,34 +1427,173 @@ impl FileRecordBatch {
         let mut mtime = mtime.into_iter();
         let mut mode = mode.into_iter();
         let mut size = size.into_iter();
-        let mut chunk_id = chunk_id.into_iter();
+        let chunk_id = chunk_id.into_iter();
         let mut chunk_offset = chunk_offset.into_iter();
         let mut chunk_size = chunk_size.into_iter();
-        let data = data.into_iter();
-
-        let mut get_metadata = || {
-            Some(FileMetadataRecord {
-                atime: DateTime::from_timestamp_micros(atime.next()??)?,
-                ctime: DateTime::from_timestamp_micros(ctime.next()??)?,
-                mtime: DateTime::from_timestamp_micros(mtime.next()??)?,
-                mode: mode.next()?? as _,
-                size: size.next()?? as _,
-            })
-        };
+        let mut data = data.as_ref().map(|data| data.into_iter());
+        let mut commit_time = commit_time
+            .as_ref()
+            .map(|commit_time| commit_time.into_iter());
+        let mut chunk_hash = chunk_hash.as_ref().map(|chunk_hash| chunk_hash.into_iter());
+        let mut checksum = checksum.as_ref().map(|checksum| checksum.into_iter());
+        let mut file_checksum = file_checksum
+            .as_ref()
+            .map(|file_checksum| file_checksum.into_iter());
+        let mut codec = codec.as_ref().map(|codec| codec.into_iter());
+        let mut encryption = encryption.as_ref().map(|encryption| encryption.into_iter());
+        let mut file_type = file_type.as_ref().map(|file_type| file_type.into_iter());
+        let mut link_target = link_target
+            .as_ref()
+            .map(|link_target| link_target.into_iter());
+        let mut uid = uid.as_ref().map(|uid| uid.into_iter());
+        let mut gid = gid.as_ref().map(|gid| gid.into_iter());
+        let mut xattrs = xattrs.as_ref().map(|xattrs| xattrs.into_iter());
+        let mut link_id = link_id.as_ref().map(|link_id| link_id.into_iter());
+
+        chunk_id
+            .filter_map(|chunk_id| {
+                // advance all columns together to keep the rows aligned
+                let name = name.next()?;
+                let parent = parent.next()?;
+                let metadata = (
+                    atime.next()?,
+                    ctime.next()?,
+                    mtime.next()?,
+                    mode.next()?,
+                    size.next()?,
+                );
+                let chunk_offset = chunk_offset.next()?;
+                let chunk_size = chunk_size.next()?;
+                let data = match data.as_mut() {
+                    Some(data) => data.next()?,
+                    None => None,
+                };
+                let commit_time = match commit_time.as_mut() {
+                    Some(commit_time) => commit_time.next()?,
+                    None => None,
+                };
+                let chunk_hash = match chunk_hash.as_mut() {
+                    Some(chunk_hash) => chunk_hash.next()?,
+                    None => None,
+                };
+                let checksum = match checksum.as_mut() {
+                    Some(checksum) => checksum.next()?,
+                    None => None,
+                };
+                let file_checksum = match file_checksum.as_mut() {
+                    Some(file_checksum) => file_checksum.next()?,
+                    None => None,
+                };
+                let codec = match codec.as_mut() {
+                    Some(codec) => codec.next()?,
+                    None => None,
+                };
+                let encryption = match encryption.as_mut() {
+                    Some(encryption) => encryption.next()?,
+                    None => None,
+                };
+                let file_type = match file_type.as_mut() {
+                    Some(file_type) => file_type.next()?,
+                    None => None,
+                };
+                let link_target = match link_target.as_mut() {
+                    Some(link_target) => link_target.next()?,
+                    None => None,
+                };
+                let uid = match uid.as_mut() {
+                    Some(uid) => uid.next()?,
+                    None => None,
+                };
+                let gid = match gid.as_mut() {
+                    Some(gid) => gid.next()?,
+                    None => None,
+                };
+                let xattrs = match xattrs.as_mut() {
+                    Some(xattrs) => xattrs.next()?,
+                    None => None,
+                };
+                let link_id = match link_id.as_mut() {
+                    Some(link_id) => link_id.next()?,
+                    None => None,
+                };
+
+                let metadata = match metadata {
+                    (Some(atime), Some(ctime), Some(mtime), Some(mode), Some(size)) => {
+                        Some(FileMetadataRecord {
+                            atime: DateTime::from_timestamp_micros(atime)?,
+                            ctime: DateTime::from_timestamp_micros(ctime)?,
+                            mtime: DateTime::from_timestamp_micros(mtime)?,
+                            mode: mode as _,
+                            size: size as _,
+                            file_type: FileType::default(),
+                            link_target: link_target.map(Into::into),
+                            uid,
+                            gid,
+                            xattrs: BTreeMap::default(),
+                            link_id: link_id.map(Into::into),
+                        })
+                    }
+                    _ => None,
+                };
 
-        Ok(data
-            .filter_map(|data| {
-                Some(FileRecord {
-                    name: name.next()??.into(),
-                    parent: parent.next()??.into(),
-                    metadata: get_metadata(),
-                    chunk_id: chunk_id.next()?? as _,
-                    chunk_offset: chunk_offset.next()?? as _,
-                    chunk_size: chunk_size.next()?? as _,
+                let file = FileRecord {
+                    name: name?.into(),
+                    parent: parent?.into(),
+                    metadata,
+                    chunk_id: chunk_id? as _,
+                    chunk_offset: chunk_offset? as _,
+                    chunk_size: chunk_size? as _,
+                    chunk_hash: chunk_hash.map(Into::into),
+                    checksum: checksum.map(Into::into),
+                    file_checksum: file_checksum.map(Into::into),
                     data: data.unwrap_or_default().to_vec(),
-                })
+                };
+                Some((commit_time, file, codec, encryption, file_type, xattrs))
             })
-            .collect())
+            .map(
+                |(commit_time, mut file, codec, encryption, file_type, xattrs)| {
+                    if let Some(metadata) = file.metadata.as_mut() {
+                        // the older rows are all regular files
+                        if let Some(file_type) = file_type {
+                            metadata.file_type = file_type
+                                .parse()
+                                .with_context(|| format!("Invalid file type: {file_type:?}"))?;
+                        }
+                        if let Some(xattrs) = xattrs {
+                            metadata.xattrs = attr::decode_xattrs(xattrs)?;
+                        }
+                    }
+                    if let Some(cipher) = cipher {
+                        file.parent = cipher.decrypt_path(&file.parent)?;
+                        file.name = cipher.decrypt_path(&file.name)?;
+                        if let Some(metadata) = file.metadata.as_mut() {
+                            if let Some(link_target) = metadata.link_target.as_mut() {
+                                *link_target = cipher.decrypt_path(link_target)?;
+                            }
+                        }
+                    }
+                    if !has_data {
+                        return Ok((commit_time, file));
+                    }
+
+                    // decrypt and decompress the chunk data transparently
+                    if encryption.is_some() {
+                        let Some(cipher) = cipher else {
+                            bail!(
+                                "Encrypted file requires an encryption key: {parent}/{name}",
+                                parent = file.parent,
+                                name = file.name,
+                            )
+                        };
+                        file.data = cipher.decrypt_data(&file.data)?;
+                    }
+                    if let Some(codec) = codec {
+                        file.data = compress::decompress(codec, &file.data, file.chunk_size)?;
+                    }
+                    Ok((commit_time, file))
+                },
+            )
+            .collect()
     }
 }
 
@@ -493,6 +1611,20 @@ struct FileRecordBuilder {
     pub chunk_offset: array::UInt64Builder,
     pub chunk_size: array::UInt64Builder,
     pub data: array::BinaryBuilder,
+    pub commit_time: array::TimestampMicrosecondBuilder,
+    pub chunk_hash: array::StringBuilder,
+    pub checksum: array::StringBuilder,
+    pub file_checksum: array::StringBuilder,
+    pub codec: array::StringBuilder,
+    pub encryption: array::StringBuilder,
+    pub file_type: array::StringBuilder,
+    pub link_target: array::StringBuilder,
+    pub uid: array::UInt32Builder,
+    pub gid: array::UInt32Builder,
+    pub xattrs: array::StringBuilder,
+    pub link_id: array::StringBuilder,
+    pub cipher: Option<Arc<Cipher>>,
+    pub timestamp: i64,
 }
 
 impl FileRecordBuilder {
@@ -502,20 +1634,61 @@ impl FileRecordBuilder {
         schema: &SchemaRef,
         file: FileRecord,
     ) -> Result<Option<RecordBatch>> {
-        let chunk_size = file.chunk_size as _;
-        match self.total_size.checked_add(chunk_size) {
+        let (codec, data) = match compress::compress(catalog.compression, &file.name, &file.data)? {
+            Some(data) => (Some(catalog.compression.to_string()), data),
+            None => (None, file.data),
+        };
+        let link_target = file
+            .metadata
+            .as_ref()
+            .and_then(|m| m.link_target.as_deref());
+        // The hashes are keyed not to reveal the contents of the encrypted files
+        let key_hash = |hash: Option<String>| {
+            hash.map(|hash| chunk::stored_hash(self.cipher.as_deref(), &hash))
+        };
+        let chunk_hash = key_hash(file.chunk_hash);
+        let checksum = key_hash(file.checksum);
+        let file_checksum = key_hash(file.file_checksum);
+
+        let (encryption, data, parent, name, link_target) = match self.cipher.as_ref() {
+            Some(cipher) => (
+                // The rows referring to the chunk store are encrypted there
+                (!data.is_empty()).then_some(crypto::ENCRYPTION_V1),
+                if data.is_empty() {
+                    data
+                } else {
+                    cipher.encrypt_data(&data)?
+                },
+                cipher.encrypt_path(&file.parent)?,
+                cipher.encrypt_path(&file.name)?,
+                link_target
+                    .map(|link_target| cipher.encrypt_path(link_target))
+                    .transpose()?,
+            ),
+            None => (
+                None,
+                data,
+                file.parent,
+                file.name,
+                link_target.map(Into::into),
+            ),
+        };
+
+        // The rows referring to the chunk store carry no data
+        let data_size = data.len();
+        match self.total_size.checked_add(data_size) {
             Some(total_size) => {
                 let batch = if total_size > catalog.max_buffer_size {
                     let batch = self.flush(schema)?;
-                    self.total_size = chunk_size;
+                    self.total_size = data_size;
                     batch
                 } else {
                     self.total_size = total_size;
                     None
                 };
 
-                self.name.append_value(file.name);
-                self.parent.append_value(file.parent);
+                self.name.append_value(name);
+                self.parent.append_value(parent);
                 self.atime
                     .append_option(file.metadata.as_ref().map(|m| m.atime.timestamp_micros()));
                 self.ctime
@@ -529,10 +1702,30 @@ impl FileRecordBuilder {
                 self.chunk_id.append_value(file.chunk_id as _);
                 self.chunk_offset.append_value(file.chunk_offset as _);
                 self.chunk_size.append_value(file.chunk_size as _);
-                self.data.append_value(file.data);
+                self.data.append_value(data);
+                self.commit_time.append_value(self.timestamp);
+                self.chunk_hash.append_option(chunk_hash);
+                self.checksum.append_option(checksum);
+                self.file_checksum.append_option(file_checksum);
+                self.codec.append_option(codec);
+                self.encryption.append_option(encryption);
+                self.file_type
+                    .append_option(file.metadata.as_ref().map(|m| m.file_type.to_string()));
+                self.link_target.append_option(link_target);
+                self.uid
+                    .append_option(file.metadata.as_ref().and_then(|m| m.uid));
+                self.gid
+                    .append_option(file.metadata.as_ref().and_then(|m| m.gid));
+                self.xattrs.append_option(
+                    file.metadata
+                        .as_ref()
+                        .and_then(|m| attr::encode_xattrs(&m.xattrs)),
+                );
+                self.link_id
+                    .append_option(file.metadata.as_ref().and_then(|m| m.link_id.clone()));
                 Ok(batch)
             }
-            None => bail!("File too large: {}", &file.name),
+            None => bail!("File too large: {name}"),
         }
     }
 
@@ -554,6 +1747,20 @@ impl FileRecordBuilder {
             chunk_offset,
             chunk_size,
             data,
+            commit_time,
+            chunk_hash,
+            checksum,
+            file_checksum,
+            codec,
+            encryption,
+            file_type,
+            link_target,
+            uid,
+            gid,
+            xattrs,
+            link_id,
+            cipher: _,
+            timestamp: _,
         } = self;
 
         *total_size = 0;
@@ -569,6 +1776,18 @@ impl FileRecordBuilder {
             Arc::new(chunk_offset.finish()),
             Arc::new(chunk_size.finish()),
             Arc::new(data.finish()),
+            Arc::new(commit_time.finish()),
+            Arc::new(chunk_hash.finish()),
+            Arc::new(checksum.finish()),
+            Arc::new(file_checksum.finish()),
+            Arc::new(codec.finish()),
+            Arc::new(encryption.finish()),
+            Arc::new(file_type.finish()),
+            Arc::new(link_target.finish()),
+            Arc::new(uid.finish()),
+            Arc::new(gid.finish()),
+            Arc::new(xattrs.finish()),
+            Arc::new(link_id.finish()),
         ];
         let batch = RecordBatch::try_new(schema.clone(), arrow_array)?;
         Ok(Some(batch))
@@ -587,53 +1806,191 @@ pub struct FileRecord<T = Vec<u8>> {
     pub chunk_id: u64,
     pub chunk_offset: u64,
     pub chunk_size: u64,
+    /// The content hash of the chunk stored in the deduplicated chunk store, if any.
+    pub chunk_hash: Option<String>,
+    /// The SHA-256 hash of the chunk data.
+    ///
+    /// The hashes are stored as HMACs in the encrypted datasets,
+    /// and returned as they are stored except on copying the files.
+    pub checksum: Option<String>,
+    /// The SHA-256 hash of the whole file contents, carried by the last chunk only.
+    pub file_checksum: Option<String>,
     pub data: T,
 }
 
 impl FileRecord {
-    #[instrument(skip(catalog))]
-    async fn load(
-        catalog: DatasetCatalog,
-        root: PathBuf,
-        path: &Path,
-    ) -> impl Stream<Item = Result<Self>> {
-        let bail = |error: Error| stream::iter(vec![Err(error)]);
+    /// Split the given file contents into the rows of the rootfs table.
+    pub fn from_bytes(
+        catalog: &DatasetCatalog,
+        parent: String,
+        name: String,
+        metadata: FileMetadataRecord,
+        data: &[u8],
+    ) -> Vec<Self> {
+        let chunker = Chunker::new(catalog);
+        let max_size = chunker.max_size() as usize;
+
+        let mut metadata = Some(metadata);
+        let mut records = Vec::default();
+        let mut offset = 0;
+        while offset < data.len() || records.is_empty() {
+            let buf = &data[offset..data.len().min(offset + max_size)];
+            let chunk = &buf[..chunker.cut(buf)];
+            offset += chunk.len();
+            records.push(Self {
+                name: name.clone(),
+                parent: parent.clone(),
+                metadata: metadata.take(),
+                chunk_id: records.len() as _,
+                chunk_offset: (offset - chunk.len()) as _,
+                chunk_size: chunk.len() as _,
+                chunk_hash: None,
+                checksum: Some(hash_chunk(chunk)),
+                file_checksum: (offset == data.len()).then(|| hash_chunk(data)),
+                data: chunk.to_vec(),
+            });
+        }
+        records
+    }
 
-        let mut file = match fs::File::open(path).await {
-            Ok(file) => file,
-            Err(error) => return bail(error.into()),
-        };
-        let metadata = match file.metadata().await {
-            Ok(metadata) => metadata,
-            Err(error) => return bail(error.into()),
+    /// Read the chunks of the given file one by one.
+    fn load(
+        catalog: &DatasetCatalog,
+        file: LocalFile,
+    ) -> impl 'static + Stream<Item = Result<Self>> {
+        let LocalFile {
+            path,
+            parent,
+            name,
+            metadata,
+        } = file;
+
+        let chunker = Chunker::new(catalog);
+        let size = metadata.size;
+
+        // The buffer keeps the bytes read ahead of the next chunk boundary
+        let state = ChunkReader {
+            file: None,
+            buf: Vec::default(),
+            chunk_id: 0,
+            hasher: Sha256::new(),
+            offset: 0,
+            metadata: Some(metadata),
         };
+        stream::unfold(Some(state), move |state| {
+            let path = path.clone();
+            let parent = parent.clone();
+            let name = name.clone();
+            async move {
+                let ChunkReader {
+                    file,
+                    mut buf,
+                    chunk_id,
+                    mut hasher,
+                    offset,
+                    mut metadata,
+                } = state?;
+
+                let remaining = size.saturating_sub(offset + buf.len() as u64);
+                let fill = (chunker.max_size() - buf.len() as u64).min(remaining);
+
+                // Open the file only if it has contents, skipping the directories and symlinks
+                let mut file = file;
+                if fill > 0 && file.is_none() {
+                    match fs::File::open(&path).await {
+                        Ok(opened) => file = Some(opened),
+                        Err(error) => return Some((Err(error.into()), None)),
+                    }
+                }
+                if let Some(file) = file.as_mut().filter(|_| fill > 0) {
+                    let filled = buf.len();
+                    buf.resize(filled + fill as usize, 0);
+                    if let Err(error) = file.read_exact(&mut buf[filled..]).await {
+                        return Some((Err(error.into()), None));
+                    }
+                }
+                if buf.is_empty() && chunk_id > 0 {
+                    return None;
+                }
 
-        if metadata.is_symlink() || !metadata.is_file() {
-            return stream::iter(Vec::default());
-        }
+                let rest = buf.split_off(chunker.cut(&buf));
+                let data = buf;
+                let chunk_size = data.len() as u64;
+                hasher.update(&data);
+
+                let is_last = offset + chunk_size >= size;
+                let record = Self {
+                    name,
+                    parent,
+                    metadata: metadata.take(),
+                    chunk_id,
+                    chunk_offset: offset,
+                    chunk_size,
+                    chunk_hash: None,
+                    checksum: Some(hash_chunk(&data)),
+                    file_checksum: is_last.then(|| chunk::encode_hash(&hasher.clone().finalize())),
+                    data,
+                };
+                let state = ChunkReader {
+                    file,
+                    buf: rest,
+                    chunk_id: chunk_id + 1,
+                    hasher,
+                    offset: offset + chunk_size,
+                    metadata,
+                };
+                Some((Ok(record), Some(state)))
+            }
+        })
+    }
+
+    /// Return the `parent`, `name` and the metadata of the given file,
+    /// directory or symlink, skipping the other special files.
+    async fn stat(
+        catalog: &DatasetCatalog,
+        root: &Path,
+        path: &Path,
+    ) -> Result<Option<(String, String, FileMetadataRecord)>> {
+        let stat = fs::symlink_metadata(path).await?;
+        let file_type = if stat.is_symlink() {
+            FileType::Symlink
+        } else if stat.is_dir() {
+            FileType::Dir
+        } else if stat.is_file() {
+            FileType::File
+        } else {
+            return Ok(None);
+        };
+        let link_target = match file_type {
+            FileType::Symlink => Some(fs::read_link(path).await?.to_string_lossy().to_string()),
+            FileType::File | FileType::Dir => None,
+        };
 
         let name = match path.file_name() {
             Some(name) => name.to_string_lossy().to_string(),
-            None => return bail(anyhow!("Empty file name: {path:?}")),
+            None => bail!("Empty file name: {path:?}"),
         };
         let parent = match path
             .parent()
-            .filter(|path| path.starts_with(&root))
+            .filter(|path| path.starts_with(root))
             .map(|path| path.to_string_lossy()[root.to_string_lossy().len()..].to_string())
         {
             Some(parent) => parent,
-            None => return bail(anyhow!("Cannot find the parent directory: {path:?}")),
+            None => bail!("Cannot find the parent directory: {path:?}"),
         };
 
         #[cfg(unix)]
-        let mut metadata = Some({
+        let mut metadata = {
             use std::os::unix::fs::MetadataExt;
 
-            let atime = DateTime::from_timestamp_nanos(metadata.atime_nsec());
-            let ctime = DateTime::from_timestamp_nanos(metadata.ctime_nsec());
-            let mtime = DateTime::from_timestamp_nanos(metadata.mtime_nsec());
-            let mode = metadata.mode();
-            let size = metadata.size();
+            let atime = DateTime::from_timestamp_nanos(stat.atime_nsec());
+            let ctime = DateTime::from_timestamp_nanos(stat.ctime_nsec());
+            let mtime = DateTime::from_timestamp_nanos(stat.mtime_nsec());
+            let mode = stat.mode();
+            let size = match file_type {
+                FileType::File => stat.size(),
+                FileType::Dir | FileType::Symlink => 0,
+            };
 
             FileMetadataRecord {
                 atime,
@@ -641,18 +1998,27 @@ impl FileRecord {
                 mtime,
                 mode,
                 size,
+                file_type,
+                link_target,
+                uid: None,
+                gid: None,
+                xattrs: BTreeMap::default(),
+                link_id: None,
             }
-        });
+        };
 
         #[cfg(windows)]
-        let mut metadata = Some({
+        let mut metadata = {
             use std::os::windows::fs::MetadataExt;
 
-            let atime = DateTime::from_timestamp_nanos(100 * metadata.last_access_time() as i64);
-            let ctime = DateTime::from_timestamp_nanos(100 * metadata.creation_time() as i64);
-            let mtime = DateTime::from_timestamp_nanos(100 * metadata.last_write_time() as i64);
+            let atime = DateTime::from_timestamp_nanos(100 * stat.last_access_time() as i64);
+            let ctime = DateTime::from_timestamp_nanos(100 * stat.creation_time() as i64);
+            let mtime = DateTime::from_timestamp_nanos(100 * stat.last_write_time() as i64);
             let mode = 0o777;
-            let size = metadata.file_size();
+            let size = match file_type {
+                FileType::File => stat.file_size(),
+                FileType::Dir | FileType::Symlink => 0,
+            };
 
             FileMetadataRecord {
                 atime,
@@ -660,102 +2026,128 @@ impl FileRecord {
                 mtime,
                 mode,
                 size,
+                file_type,
+                link_target,
+                uid: None,
+                gid: None,
+                xattrs: BTreeMap::default(),
+                link_id: None,
             }
-        });
-
-        let size = metadata.as_ref().map(|m| m.size).unwrap();
-        let chunk_ids = match catalog.max_chunk_size {
-            0 => 0..=0,
-            max_chunk_size => match size {
-                0 => 0..=0,
-                size => 0..=((size - 1) / max_chunk_size),
-            },
         };
 
-        let mut files = Vec::with_capacity(*chunk_ids.end() as _);
-        for chunk_id in chunk_ids {
-            let chunk_offset = chunk_id * catalog.max_chunk_size;
-            let chunk_size = match catalog.max_chunk_size {
-                0 => size,
-                max_chunk_size => size.min((chunk_id + 1) * max_chunk_size) - chunk_offset,
-            };
-            let mut data = vec![0; chunk_size as _];
-            let maybe_file = match file.read_exact(&mut data).await {
-                Ok(_) => Ok(Self {
-                    name: name.clone(),
-                    parent: parent.clone(),
-                    metadata: metadata.take(),
-                    chunk_id,
-                    chunk_offset,
-                    chunk_size,
-                    data,
-                }),
-                Err(error) => Err(error.into()),
-            };
-            files.push(maybe_file)
+        if catalog.preserve_metadata {
+            attr::capture(path, &stat, &mut metadata)?;
         }
-        stream::iter(files)
+        Ok(Some((parent, name, metadata)))
     }
 
+    /// Return the metadata of the files under the canonical root, in order.
     #[instrument(skip_all)]
-    async fn load_all(
-        catalog: DatasetCatalog,
-        root: &Path,
-    ) -> Result<impl 'static + Stream<Item = Result<Self>>> {
-        let root = fs::canonicalize(root).await?;
-        let pattern = format!("{}/**/*", root.to_string_lossy());
-        let list = glob(&pattern).with_context(|| format!("Failed to list files on {root:?}"))?;
-        Ok(stream::iter(list.filter_map(|path| path.ok()))
-            .then(move |path| {
-                let catalog = catalog.clone();
-                let root = root.clone();
-                async move { Self::load(catalog, root, &path).await }
+    async fn stat_all(catalog: &DatasetCatalog, root: &Path) -> Result<Vec<LocalFile>> {
+        // Scope the hardlink identities to this run, as the inode numbers are reused once freed
+        let scope = format!("{:016x}", ::rand::random::<u64>());
+        let scope = &scope;
+
+        stream::iter(Self::list_all(root).await?)
+            .map(|path| async move {
+                let stat = Self::stat(catalog, root, &path).await?;
+                Result::<_, Error>::Ok(stat.map(|(parent, name, mut metadata)| {
+                    if let Some(link_id) = metadata.link_id.as_mut() {
+                        *link_id = format!("{scope}:{link_id}");
+                    }
+                    LocalFile {
+                        path,
+                        parent,
+                        name,
+                        metadata,
+                    }
+                }))
             })
-            .flatten())
+            .buffered(catalog.max_read_threads.max(1))
+            .try_filter_map(|file| async move { Ok(file) })
+            .try_collect()
+            .await
     }
 
-    #[instrument(
-        skip(self),
-        fields(name = %self.name, parent = &self.parent),
-    )]
-    async fn dump(&self, root: &Path) -> Result<()> {
-        let path = {
-            let parent = trim_rel_path(self.parent.as_str());
-            let base_dir = root.join(parent);
-            fs::create_dir_all(&base_dir).await?;
-            base_dir.join(&self.name)
-        };
+    /// Read the given files concurrently, yielding their chunks in the given order.
+    ///
+    /// At most `max_read_threads` files are read at once,
+    /// and the pending files are bounded by the `max_buffer_size` in total.
+    /// The files larger than the `max_buffer_size` are streamed chunk by chunk alone.
+    fn load_files(
+        catalog: DatasetCatalog,
+        files: Vec<LocalFile>,
+    ) -> impl 'static + Stream<Item = Result<Self>> {
+        let max_read_threads = catalog.max_read_threads.max(1);
+        let max_permits = (catalog.max_buffer_size / BUFFER_PERMIT_SIZE).clamp(1, u32::MAX as _);
+        let buffer = Arc::new(Semaphore::new(max_permits));
 
-        let mut options = fs::File::options();
-        let mut file = options.create(true).append(true).open(&path).await?;
+        stream::iter(files)
+            // Reserve the buffer in order, so that the earlier files are never starved
+            .then(move |file| {
+                let buffer = buffer.clone();
+                async move {
+                    let permits = (file.metadata.size as usize)
+                        .div_ceil(BUFFER_PERMIT_SIZE)
+                        .clamp(1, max_permits);
+                    let permit = buffer.acquire_many_owned(permits as _).await;
+                    (file, permit)
+                }
+            })
+            .map(move |(file, permit)| {
+                let catalog = catalog.clone();
+                async move {
+                    let permit = permit?;
+                    let is_small = file.metadata.size <= catalog.max_buffer_size as u64;
+                    let chunks = Self::load(&catalog, file);
+                    let chunks: FileRecordStream = if is_small {
+                        Box::pin(stream::iter(chunks.collect::<Vec<_>>().await))
+                    } else {
+                        Box::pin(chunks)
+                    };
+
+                    // Hold the buffer until the last chunk is passed to the writer
+                    let chunks = chunks.map(move |chunk| {
+                        let _permit = &permit;
+                        chunk
+                    });
+                    Result::<_, Error>::Ok(chunks)
+                }
+            })
+            .buffered(max_read_threads)
+            .try_flatten()
+    }
 
-        if self.chunk_offset > 0 {
-            file.seek(SeekFrom::Start(self.chunk_offset)).await?;
+    /// Return the paths under the root in depth-first order, without following the symlinks.
+    ///
+    /// The directories are walked on a blocking thread, not to stall the runtime.
+    async fn list_all(root: &Path) -> Result<Vec<PathBuf>> {
+        fn walk(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+            let mut entries = ::std::fs::read_dir(dir)
+                .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+                .with_context(|| format!("Failed to list files on {dir:?}"))?;
+            entries.sort_by_key(|entry| entry.file_name());
+
+            for entry in entries {
+                let path = entry.path();
+                let is_dir = entry.file_type()?.is_dir();
+                paths.push(path.clone());
+                if is_dir {
+                    walk(&path, paths)?;
+                }
+            }
+            Ok(())
         }
-        file.write_all(&self.data).await?;
 
-        if let Some(record) = self.metadata.as_ref() {
-            ::filetime::set_file_atime(
-                &path,
-                FileTime::from_unix_time(record.atime.timestamp(), record.atime.nanosecond()),
-            )?;
-            ::filetime::set_file_mtime(
-                &path,
-                FileTime::from_unix_time(record.mtime.timestamp(), record.mtime.nanosecond()),
-            )?;
-
-            let metadata = file.metadata().await?;
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-
-                let mut perm = metadata.permissions();
-                perm.set_mode(record.mode);
-                file.set_permissions(perm).await?;
+        let root = root.to_path_buf();
+        spawn_blocking(move || {
+            let mut paths = Vec::default();
+            if root.is_dir() {
+                walk(&root, &mut paths)?;
             }
-            file.set_len(record.size).await?;
-        }
-        Ok(())
+            Ok(paths)
+        })
+        .await?
     }
 
     #[instrument(skip_all)]
@@ -764,9 +2156,86 @@ impl FileRecord {
             .await
             .with_context(|| format!("Failed to create directory: {root:?}"))?;
 
-        stream
-            .try_for_each(|file| async move { file.dump(root).await })
-            .await
+        // Keep the file open while its chunks are written one by one
+        let mut writer: Option<FileWriter> = None;
+        let mut dirs = Vec::default();
+        let mut links = HashMap::<String, (PathBuf, u64)>::default();
+        let mut linked: Option<(String, String)> = None;
+        let mut stream = ::std::pin::pin!(stream);
+        while let Some(record) = stream.try_next().await? {
+            // Skip the remaining chunks of the hardlinked file
+            if record.chunk_id > 0
+                && linked
+                    .as_ref()
+                    .is_some_and(|(parent, name)| *parent == record.parent && *name == record.name)
+            {
+                continue;
+            }
+            linked = None;
+
+            // Link the files sharing the same inode to the first one written
+            let original = record
+                .metadata
+                .as_ref()
+                .filter(|_| record.chunk_id == 0)
+                .and_then(|m| {
+                    let (original, size) = links.get(m.link_id.as_ref()?)?;
+                    // Never link the unrelated files whose identities happen to collide
+                    (*size == m.size).then_some(original)
+                });
+            if let Some(original) = original {
+                if let Some(writer) = writer.take() {
+                    writer.finish().await?;
+                }
+                restore_hardlink(root, original, &record).await?;
+                linked = Some((record.parent, record.name));
+                continue;
+            }
+
+            match record.metadata.as_ref().map(|m| m.file_type) {
+                Some(FileType::Dir) | Some(FileType::Symlink) => {
+                    if let Some(writer) = writer.take() {
+                        writer.finish().await?;
+                    }
+                    if let Some(dir) = restore_special_file(root, record).await? {
+                        dirs.push(dir);
+                    }
+                    continue;
+                }
+                Some(FileType::File) | None => (),
+            }
+
+            let is_same_file = writer
+                .as_ref()
+                .is_some_and(|writer| writer.is_same_file(&record));
+            if !is_same_file {
+                if let Some(writer) = writer.take() {
+                    writer.finish().await?;
+                }
+                let opened = FileWriter::open(root, &record).await?;
+                if let Some(m) = record.metadata.as_ref() {
+                    if let Some(link_id) = m.link_id.clone() {
+                        links.insert(link_id, (opened.path.clone(), m.size));
+                    }
+                }
+                writer = Some(opened);
+            }
+            if let Some(writer) = writer.as_mut() {
+                writer.write(record).await?;
+            }
+        }
+        if let Some(writer) = writer.take() {
+            writer.finish().await?;
+        }
+
+        // Restore the directories after their children are written, the deepest first
+        dirs.sort_by_key(|(path, _): &(PathBuf, _)| {
+            ::core::cmp::Reverse(path.components().count())
+        });
+        for (path, metadata) in dirs {
+            restore_metadata(&path, &metadata).await?;
+        }
+        Ok(())
     }
 
     fn columns_arrow() -> Vec<ArrowField> {
@@ -783,6 +2252,18 @@ impl FileRecord {
             ArrowField::new("chunk_offset", ArrowDataType::UInt64, false),
             ArrowField::new("chunk_size", ArrowDataType::UInt64, false),
             ArrowField::new("data", ArrowDataType::Binary, true),
+            ArrowField::new("commit_time", timestamp_micros(), true),
+            ArrowField::new("chunk_hash", ArrowDataType::Utf8, true),
+            ArrowField::new("checksum", ArrowDataType::Utf8, true),
+            ArrowField::new("file_checksum", ArrowDataType::Utf8, true),
+            ArrowField::new("codec", ArrowDataType::Utf8, true),
+            ArrowField::new("encryption", ArrowDataType::Utf8, true),
+            ArrowField::new("file_type", ArrowDataType::Utf8, true),
+            ArrowField::new("link_target", ArrowDataType::Utf8, true),
+            ArrowField::new("uid", ArrowDataType::UInt32, true),
+            ArrowField::new("gid", ArrowDataType::UInt32, true),
+            ArrowField::new("xattrs", ArrowDataType::Utf8, true),
+            ArrowField::new("link_id", ArrowDataType::Utf8, true),
         ]
     }
 
@@ -799,23 +2280,328 @@ pub struct FileMetadataRecord {
     pub ctime: DateTime<Utc>,
     pub mtime: DateTime<Utc>,
     pub mode: u32,
+    /// The size of the contents, or zero for the directories and symlinks.
     pub size: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub file_type: FileType,
+    /// The target path of the symlink.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub link_target: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub uid: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub gid: Option<u32>,
+    /// The extended attributes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+    /// The identity shared by the hardlinks of the same file.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub link_id: Option<String>,
+}
+
+impl FileMetadataRecord {
+    /// Return `true` if the file seems to be unchanged since the given record.
+    #[inline]
+    pub fn is_same_contents(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.mtime.timestamp_micros() == other.mtime.timestamp_micros()
+            && self.file_type == other.file_type
+            && self.link_target == other.link_target
+            && self.uid == other.uid
+            && self.gid == other.gid
+            && self.xattrs == other.xattrs
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Display, EnumString, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[strum(serialize_all = "snake_case")]
+pub enum FileType {
+    #[default]
+    File,
+    Dir,
+    Symlink,
+}
+
+/// Writes the chunks of a local file in place.
+struct FileWriter {
+    file: fs::File,
+    metadata: Option<FileMetadataRecord>,
+    name: String,
+    parent: String,
+    path: PathBuf,
+    verifier: FileVerifier,
+}
+
+impl FileWriter {
+    #[instrument(skip_all, fields(name = %record.name, parent = &record.parent))]
+    async fn open(root: &Path, record: &FileRecord) -> Result<Self> {
+        let path = {
+            let parent = trim_rel_path(record.parent.as_str());
+            let base_dir = root.join(parent);
+            fs::create_dir_all(&base_dir).await?;
+            base_dir.join(&record.name)
+        };
+
+        let file = fs::File::options()
+            .create(true)
+            .write(true)
+            // The chunks may arrive in any order, so the length is fixed on finish
+            .truncate(false)
+            .open(&path)
+            .await
+            .with_context(|| format!("Failed to open file: {path:?}"))?;
+
+        Ok(Self {
+            file,
+            metadata: None,
+            name: record.name.clone(),
+            parent: record.parent.clone(),
+            path,
+            verifier: FileVerifier::default(),
+        })
+    }
+
+    fn is_same_file(&self, record: &FileRecord) -> bool {
+        record.chunk_id > 0 && self.name == record.name && self.parent == record.parent
+    }
+
+    async fn write(&mut self, record: FileRecord) -> Result<()> {
+        if let Some(kind) = self.verifier.update(&record) {
+            self.bail(Some(record.chunk_id), kind)?;
+        }
+        if let Some(metadata) = record.metadata {
+            self.metadata = Some(metadata);
+        }
+
+        self.file.seek(SeekFrom::Start(record.chunk_offset)).await?;
+        self.file.write_all(&record.data).await?;
+        Ok(())
+    }
+
+    fn bail(&self, chunk_id: Option<u64>, kind: VerifyIssueKind) -> Result<()> {
+        let issue = VerifyIssue {
+            parent: self.parent.clone(),
+            name: self.name.clone(),
+            chunk_id,
+            kind,
+        };
+        bail!("Corrupted file: {issue}")
+    }
+
+    /// Verify the contents and restore the metadata after all chunks are written.
+    async fn finish(mut self) -> Result<()> {
+        if let Some(record) = self.metadata.as_ref() {
+            let verifier = ::std::mem::take(&mut self.verifier);
+            if let Some(kind) = verifier.finish(None, record.size) {
+                self.bail(None, kind)?;
+            }
+        }
+
+        let Self {
+            mut file,
+            metadata,
+            path,
+            ..
+        } = self;
+
+        file.flush().await?;
+        if let Some(record) = metadata {
+            file.set_len(record.size).await?;
+            // Restore the ownership before the permissions, as it may clear the setuid bits
+            attr::restore(&path, &record)?;
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                let mut perm = file.metadata().await?.permissions();
+                perm.set_mode(record.mode);
+                file.set_permissions(perm).await?;
+            }
+            drop(file);
+
+            ::filetime::set_file_times(
+                &path,
+                FileTime::from_unix_time(record.atime.timestamp(), record.atime.nanosecond()),
+                FileTime::from_unix_time(record.mtime.timestamp(), record.mtime.nanosecond()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Create the given directory or symlink,
+/// returning the directory to restore its metadata after its children are written.
+#[instrument(skip_all, fields(name = %record.name, parent = &record.parent))]
+async fn restore_special_file(
+    root: &Path,
+    record: FileRecord,
+) -> Result<Option<(PathBuf, FileMetadataRecord)>> {
+    let Some(metadata) = record.metadata else {
+        return Ok(None);
+    };
+    let path = root.join(trim_rel_path(&record.parent)).join(&record.name);
+
+    match metadata.file_type {
+        FileType::File => Ok(None),
+        FileType::Dir => {
+            fs::create_dir_all(&path)
+                .await
+                .with_context(|| format!("Failed to create directory: {path:?}"))?;
+            Ok(Some((path, metadata)))
+        }
+        FileType::Symlink => {
+            let Some(target) = metadata.link_target.as_deref() else {
+                bail!("Missing symlink target: {path:?}")
+            };
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            // Replace the existing file, but never the directory
+            match fs::symlink_metadata(&path).await {
+                Ok(stat) if stat.is_dir() => bail!("Cannot replace directory: {path:?}"),
+                Ok(_) => fs::remove_file(&path).await?,
+                Err(_) => (),
+            }
+
+            #[cfg(unix)]
+            fs::symlink(target, &path)
+                .await
+                .with_context(|| format!("Failed to create symlink: {path:?}"))?;
+            #[cfg(not(unix))]
+            bail!("Symlinks are not supported on this platform: {path:?} -> {target:?}");
+
+            attr::restore(&path, &metadata)?;
+
+            ::filetime::set_symlink_file_times(
+                &path,
+                FileTime::from_unix_time(metadata.atime.timestamp(), metadata.atime.nanosecond()),
+                FileTime::from_unix_time(metadata.mtime.timestamp(), metadata.mtime.nanosecond()),
+            )?;
+            Ok(None)
+        }
+    }
+}
+
+/// Link the given file to the original one written before.
+async fn restore_hardlink(root: &Path, original: &Path, record: &FileRecord) -> Result<()> {
+    let path = root.join(trim_rel_path(&record.parent)).join(&record.name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    match fs::symlink_metadata(&path).await {
+        Ok(stat) if stat.is_dir() => bail!("Cannot replace directory: {path:?}"),
+        Ok(_) => fs::remove_file(&path).await?,
+        Err(_) => (),
+    }
+    fs::hard_link(original, &path)
+        .await
+        .with_context(|| format!("Failed to create hardlink: {path:?} -> {original:?}"))
+}
+
+/// Restore the ownership, permissions and timestamps of the given directory.
+async fn restore_metadata(path: &Path, metadata: &FileMetadataRecord) -> Result<()> {
+    attr::restore(path, metadata)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut perm = fs::metadata(path).await?.permissions();
+        perm.set_mode(metadata.mode);
+        fs::set_permissions(path, perm).await?;
+    }
+
+    ::filetime::set_file_times(
+        path,
+        FileTime::from_unix_time(metadata.atime.timestamp(), metadata.atime.nanosecond()),
+        FileTime::from_unix_time(metadata.mtime.timestamp(), metadata.mtime.nanosecond()),
+    )
+    .with_context(|| format!("Failed to restore the metadata: {path:?}"))
+}
+
+/// A file, directory or symlink on the local filesystem.
+#[derive(Clone, Debug)]
+struct LocalFile {
+    path: PathBuf,
+    parent: String,
+    name: String,
+    metadata: FileMetadataRecord,
+}
+
+/// The state of a local file being read chunk by chunk.
+struct ChunkReader {
+    file: Option<fs::File>,
+    buf: Vec<u8>,
+    chunk_id: u64,
+    hasher: Sha256,
+    offset: u64,
+    metadata: Option<FileMetadataRecord>,
 }
 
 #[instrument(skip_all)]
 async fn open_table(catalog: &DatasetCatalog, dataset: &DatasetPath) -> Result<Dataset> {
-    let uri = dataset.to_uri(DIR_ROOTFS);
-    match DatasetBuilder::from_uri(&uri)
+    match try_open_table(catalog, dataset).await? {
+        Some(table) => Ok(table),
+        None => bail!("Empty storage"),
+    }
+}
+
+#[instrument(skip_all)]
+async fn try_open_table(
+    catalog: &DatasetCatalog,
+    dataset: &DatasetPath,
+) -> Result<Option<Dataset>> {
+    let Some(table) = try_open_dataset(catalog, &dataset.to_uri(DIR_ROOTFS)).await? else {
+        return Ok(None);
+    };
+    match dataset.version.as_ref() {
+        Some(version) => checkout_table(&table, version).await.map(Some),
+        None => Ok(Some(table)),
+    }
+}
+
+/// Check out the given previous version of the table.
+async fn checkout_table(table: &Dataset, version: &DatasetVersion) -> Result<Dataset> {
+    let number = match version {
+        DatasetVersion::Number(number) => *number,
+        DatasetVersion::Tag(tag) => {
+            return table
+                .checkout_version(tag.as_str())
+                .await
+                .with_context(|| format!("Cannot check out the dataset tag: #{tag}"))
+        }
+        // The latest version committed at the time
+        DatasetVersion::Timestamp(timestamp) => table
+            .versions()
+            .await?
+            .into_iter()
+            .filter(|version| version.timestamp <= *timestamp)
+            .map(|version| version.version)
+            .max()
+            .with_context(|| format!("No version of the dataset at {timestamp}"))?,
+    };
+    table
+        .checkout_version(number)
+        .await
+        .with_context(|| format!("Cannot check out the dataset version: v{number}"))
+}
+
+#[instrument(skip_all)]
+async fn try_open_dataset(catalog: &DatasetCatalog, uri: &str) -> Result<Option<Dataset>> {
+    match DatasetBuilder::from_uri(uri)
         .with_aws_credentials_provider(catalog.s3_credential_provider()?)
         .with_commit_handler(catalog.commit_handler())
         .with_object_store_registry(build_registry())
-        .with_storage_options(catalog.storage_options(false)?)
+        .with_storage_options(catalog.storage_options()?)
         .load()
         .await
     {
-        Ok(dataset) => Ok(dataset),
-        Err(LanceError::DatasetNotFound { .. }) => bail!("Empty storage"),
-        Err(error) => Err(error).with_context(|| format!("Cannot open a rootfs table on {uri:?}")),
+        Ok(dataset) => Ok(Some(dataset)),
+        Err(LanceError::DatasetNotFound { .. }) => Ok(None),
+        Err(error) => Err(error).with_context(|| format!("Cannot open a table on {uri:?}")),
     }
 }
 
@@ -824,10 +2610,28 @@ async fn commit_table(
     catalog: &DatasetCatalog,
     dataset: &DatasetPath,
     stream: SendableRecordBatchStream,
+    progress: Arc<dyn WriteFragmentProgress>,
 ) -> Result<Dataset> {
+    if let Some(mut table) = try_open_table(catalog, dataset).await? {
+        migrate_table(&mut table, FileRecord::columns_arrow()).await?;
+    }
+
     let uri = dataset.to_uri(DIR_ROOTFS);
+    insert_stream(catalog, &uri, stream, progress)
+        .await
+        .with_context(|| format!("Failed to commit stream to {dataset}"))
+}
+
+/// Append the stream to the given table, creating it if not exists.
+#[instrument(skip_all)]
+async fn insert_stream(
+    catalog: &DatasetCatalog,
+    uri: &str,
+    stream: SendableRecordBatchStream,
+    progress: Arc<dyn WriteFragmentProgress>,
+) -> Result<Dataset> {
     let (dest, mode) = {
-        let dest = WriteDestination::Uri(&uri);
+        let dest = WriteDestination::Uri(uri);
         let mode = WriteMode::Append;
         (dest, mode)
     };
@@ -840,7 +2644,7 @@ async fn commit_table(
         max_bytes_per_file: catalog.max_buffer_size,
         mode,
         object_store_registry: build_registry(),
-        progress: catalog.fragment_process(),
+        progress,
         store_params: Some(catalog.storage_parameters()?),
         ..Default::default()
     };
@@ -849,14 +2653,351 @@ async fn commit_table(
         .with_params(&write_params)
         .execute_stream(stream)
         .await
-        .with_context(|| format!("Failed to commit stream to {dataset}"))
+        .map_err(Into::into)
+}
+
+/// Commit the files to the rootfs table at the given commit timestamp,
+/// storing their contents into the chunk store if the content-defined chunking is enabled.
+#[instrument(skip_all)]
+async fn write_files(
+    catalog: &DatasetCatalog,
+    dataset: &DatasetPath,
+    stream: FileRecordStream,
+    progress: Arc<dyn WriteFragmentProgress>,
+    commit_time: i64,
+) -> Result<()> {
+    dataset.ensure_writable()?;
+
+    let stream: FileRecordStream = match catalog.chunking_mode {
+        ChunkingMode::Fixed => stream,
+        ChunkingMode::ContentDefined => {
+            let files = chunk::store_chunks(catalog, dataset, stream, progress.clone()).await?;
+            Box::pin(stream::iter(files.into_iter().map(Ok)))
+        }
+    };
+    let stream = file_stream_to_batch_stream(catalog, stream, commit_time).await?;
+    commit_table(catalog, dataset, stream, progress).await?;
+    Ok(())
+}
+
+/// Add the columns missing in the tables created by the older versions.
+#[instrument(skip_all)]
+async fn migrate_table(table: &mut Dataset, columns: Vec<ArrowField>) -> Result<()> {
+    let schema = table.schema();
+    let missing: Vec<_> = columns
+        .into_iter()
+        .filter(|field| schema.field(field.name()).is_none())
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    info!(
+        "Migrating the table {uri}: {columns}",
+        uri = table.uri(),
+        columns = missing.iter().map(|field| field.name()).join(", "),
+    );
+    let transform = NewColumnTransform::AllNulls(Arc::new(ArrowSchema::new(missing)));
+    table
+        .add_columns(transform, None, None)
+        .await
+        .with_context(|| format!("Failed to migrate the table: {uri}", uri = table.uri()))
+}
+
+/// Delete the rows of the given files,
+/// only the ones committed before the given timestamp if any.
+#[instrument(skip_all)]
+async fn delete_files<'a>(
+    table: &mut Dataset,
+    cipher: Option<&Cipher>,
+    files: impl IntoIterator<Item = (&'a str, &'a str)>,
+    before: Option<i64>,
+) -> Result<()> {
+    let before = match before {
+        Some(before) => Some(format!(
+            "commit_time IS NULL OR commit_time < {time}",
+            time = timestamp_literal(
+                DateTime::from_timestamp_micros(before).context("Invalid timestamp")?
+            ),
+        )),
+        None => None,
+    };
+    for predicate in file_predicates(cipher, files)? {
+        let predicate = match before.as_ref() {
+            Some(before) => format!("({predicate}) AND ({before})"),
+            None => predicate,
+        };
+        table
+            .delete(&predicate)
+            .await
+            .context("Failed to delete files")?;
+    }
+    Ok(())
+}
+
+/// Move the rows of the given entry and its descendants in a single commit.
+#[instrument(skip_all)]
+async fn move_files(
+    table: Dataset,
+    cipher: Option<&Cipher>,
+    from: &str,
+    to: &str,
+    is_dir: bool,
+) -> Result<()> {
+    let encrypt_path = |path: &str| match cipher {
+        Some(cipher) => cipher.encrypt_path(path),
+        None => Ok(path.into()),
+    };
+    let literal = |path: &str| -> Result<String> {
+        Ok(format!("'{}'", escape_sql_str(&encrypt_path(path)?)))
+    };
+
+    let (from_parent, from_name) = split_path(from);
+    let (to_parent, to_name) = split_path(to);
+    let entry = format!(
+        "parent = {parent} AND name = {name}",
+        parent = literal(from_parent)?,
+        name = literal(from_name)?,
+    );
+    let (predicate, parent, name) = if is_dir {
+        // Rename the entry itself, and re-parent its descendants
+        let enc_from = encrypt_path(from)?;
+        let descendants = format!(
+            "parent = '{from}' OR parent LIKE '{pattern}/%' ESCAPE '\\'",
+            from = escape_sql_str(&enc_from),
+            pattern = escape_sql_str(&escape_like_pattern(&enc_from)),
+        );
+        let moved_parent = format!(
+            "concat({to}, substr(parent, {offset}))",
+            to = literal(to)?,
+            offset = enc_from.chars().count() + 1,
+        );
+        (
+            format!("({entry}) OR {descendants}"),
+            format!(
+                "CASE WHEN {entry} THEN {to_parent} ELSE {moved_parent} END",
+                to_parent = literal(to_parent)?,
+            ),
+            format!(
+                "CASE WHEN {entry} THEN {to_name} ELSE name END",
+                to_name = literal(to_name)?,
+            ),
+        )
+    } else {
+        (entry, literal(to_parent)?, literal(to_name)?)
+    };
+
+    UpdateBuilder::new(Arc::new(table))
+        .update_where(&predicate)?
+        .set("parent", &parent)?
+        .set("name", &name)?
+        .build()?
+        .execute()
+        .await
+        .with_context(|| format!("Failed to move files: {from} -> {to}"))?;
+    Ok(())
+}
+
+/// Return the predicates matching the rows of the given files, in batches.
+fn file_predicates<'a>(
+    cipher: Option<&Cipher>,
+    files: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<Vec<String>> {
+    let encrypt_path = |path: &str| match cipher {
+        Some(cipher) => cipher.encrypt_path(path),
+        None => Ok(path.into()),
+    };
+
+    let predicates = files
+        .into_iter()
+        .map(|(parent, name)| {
+            Ok(format!(
+                "(parent = '{parent}' AND name = '{name}')",
+                parent = escape_sql_str(&encrypt_path(parent)?),
+                name = escape_sql_str(&encrypt_path(name)?),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .chunks(MAX_PREDICATES_PER_QUERY)
+        .into_iter()
+        .map(|mut predicates| predicates.join(" OR "))
+        .collect();
+    Ok(predicates)
+}
+
+/// Load the latest commit timestamp of the rows matching any of the given predicates,
+/// or of the whole table if not given.
+#[instrument(skip_all)]
+async fn load_latest_commit_time(
+    table: &Dataset,
+    predicates: Option<&[String]>,
+) -> Result<Option<i64>> {
+    if table.schema().field("commit_time").is_none() {
+        return Ok(None);
+    }
+
+    let filters = match predicates {
+        Some(predicates) => predicates.iter().map(Some).collect(),
+        None => vec![None],
+    };
+    let mut latest = None;
+    for filter in filters {
+        let mut scanner = table.scan();
+        scanner.project(&["commit_time"])?;
+        if let Some(filter) = filter {
+            scanner.filter(filter)?;
+        }
+        let mut stream = scanner.try_into_stream().await?;
+        while let Some(batch) = stream.try_next().await? {
+            let Some(column) = batch
+                .column_by_name("commit_time")
+                .and_then(|c| c.as_primitive_opt::<TimestampMicrosecondType>())
+            else {
+                bail!("Invalid rootfs table schema")
+            };
+            latest = latest.max(column.iter().flatten().max());
+        }
+    }
+    Ok(latest)
+}
+
+/// Return the commit timestamp superseding all the existing rows of the dataset,
+/// regardless of the clock of this client.
+#[instrument(skip_all)]
+async fn load_next_commit_time(catalog: &DatasetCatalog, dataset: &DatasetPath) -> Result<i64> {
+    let latest = match try_open_table(catalog, dataset).await? {
+        Some(table) => load_latest_commit_time(&table, None).await?,
+        None => None,
+    };
+    Ok(next_commit_time(latest))
+}
+
+/// Format the timestamp as a SQL literal of the `timestamp` columns.
+fn timestamp_literal(time: DateTime<Utc>) -> String {
+    format!(
+        "TIMESTAMP '{time}'",
+        time = time.naive_utc().format("%Y-%m-%d %H:%M:%S%.6f"),
+    )
+}
+
+/// Return the commit timestamp of the new rows,
+/// which should supersede the given latest ones even if the clocks are skewed.
+fn next_commit_time(latest: Option<i64>) -> i64 {
+    let now = Utc::now().timestamp_micros();
+    match latest {
+        Some(latest) => now.max(latest.saturating_add(1)),
+        None => now,
+    }
+}
+
+/// The latest commit timestamp and metadata of each file, indexed by `(parent, name)`.
+type FileIndex = HashMap<(String, String), (Option<i64>, FileMetadataRecord)>;
+
+/// Load the metadata of the latest version of each stored file.
+#[instrument(skip_all)]
+async fn load_index(
+    table: &Dataset,
+    cipher: Option<&Cipher>,
+    filter: Option<&str>,
+) -> Result<FileIndex> {
+    let schema = table.schema();
+    let columns: Vec<_> = FileRecord::columns_arrow()
+        .into_iter()
+        .map(|field| field.name().clone())
+        .filter(|name| name != "data" && schema.field(name).is_some())
+        .collect();
+
+    let mut stream = table
+        .scan()
+        .project(&columns)?
+        .filter(&match filter {
+            Some(filter) => format!("size IS NOT NULL AND ({filter})"),
+            None => "size IS NOT NULL".into(),
+        })?
+        .try_into_stream()
+        .await?;
+
+    let mut index = FileIndex::default();
+    while let Some(batch) = stream.try_next().await? {
+        for (commit_time, file) in FileRecordBatch::try_from(&batch)?.into_rows(cipher)? {
+            let Some(metadata) = file.metadata else {
+                continue;
+            };
+            let key = (file.parent, file.name);
+            match index.get(&key) {
+                Some((last, _)) if *last > commit_time => continue,
+                _ => {
+                    index.insert(key, (commit_time, metadata));
+                }
+            }
+        }
+    }
+    Ok(index)
+}
+
+/// Return the new or changed files to upload, and the deleted files to remove.
+///
+/// The rows of the changed files are removed once their new rows are committed.
+#[instrument(skip_all)]
+async fn sync_files(
+    catalog: &DatasetCatalog,
+    dst: &GlobalPath,
+    files: Vec<LocalFile>,
+) -> Result<(Vec<LocalFile>, Vec<(String, String)>)> {
+    let cipher = Cipher::try_new(catalog)?;
+    let table = try_open_table(catalog, &dst.dataset).await?;
+    let mut index = match table.as_ref() {
+        Some(table) => load_index(table, cipher.as_deref(), None).await?,
+        None => FileIndex::default(),
+    };
+
+    let mut changed = Vec::default();
+    for file in files {
+        let key = (file.parent.clone(), file.name.clone());
+        let is_same = index
+            .remove(&key)
+            .is_some_and(|(_, last)| last.is_same_contents(&file.metadata));
+        if !is_same {
+            changed.push(file);
+        }
+    }
+    info!(
+        "Syncing files: {changed} changed, {deleted} deleted",
+        changed = changed.len(),
+        deleted = index.len(),
+    );
+    Ok((changed, index.into_keys().collect()))
+}
+
+/// Split the files into the groups committed at each checkpoint.
+fn split_checkpoints(catalog: &DatasetCatalog, files: Vec<LocalFile>) -> Vec<Vec<LocalFile>> {
+    let max_checkpoint_size = catalog.max_checkpoint_size;
+
+    let mut groups = Vec::default();
+    let mut group = Vec::default();
+    let mut group_size = 0u64;
+    for file in files {
+        group_size = group_size.saturating_add(file.metadata.size);
+        group.push(file);
+        if max_checkpoint_size > 0 && group_size >= max_checkpoint_size {
+            groups.push(::core::mem::take(&mut group));
+            group_size = 0;
+        }
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+    groups
 }
 
 async fn file_stream_to_batch_stream(
     catalog: &DatasetCatalog,
     mut stream: FileRecordStream,
+    commit_time: i64,
 ) -> Result<SendableRecordBatchStream> {
     let schema = Arc::new(FileRecord::schema_arrow());
+    let cipher = Cipher::try_new(catalog)?;
 
     let (tx, rx) = mpsc::channel(catalog.max_write_threads);
 
@@ -864,16 +3005,28 @@ async fn file_stream_to_batch_stream(
         let arrow_schema = schema.clone();
         let catalog = catalog.clone();
         async move {
-            let mut builder = FileRecordBuilder::default();
-            while let Some(file) = stream.try_next().await? {
-                if let Some(batch) = builder.push(&catalog, &arrow_schema, file)? {
-                    tx.send(Result::<_, DataFusionError>::Ok(batch)).await?;
+            let produce = async {
+                let mut builder = FileRecordBuilder {
+                    cipher,
+                    timestamp: commit_time,
+                    ..Default::default()
+                };
+                while let Some(file) = stream.try_next().await? {
+                    if let Some(batch) = builder.push(&catalog, &arrow_schema, file)? {
+                        tx.send(Ok(batch)).await?;
+                    }
                 }
+                if let Some(batch) = builder.flush(&arrow_schema)? {
+                    tx.send(Ok(batch)).await?;
+                }
+                Result::<_, Error>::Ok(())
+            };
+
+            // Fail the consumer so that the partial stream is not committed
+            if let Err(error) = produce.await {
+                let error = DataFusionError::External(error.into());
+                tx.send(Err(error)).await.ok();
             }
-            if let Some(batch) = builder.flush(&arrow_schema)? {
-                tx.send(Result::<_, DataFusionError>::Ok(batch)).await?;
-            }
-            Result::<_, Error>::Ok(())
         }
     });
 
@@ -881,6 +3034,121 @@ async fn file_stream_to_batch_stream(
     Ok(Box::pin(stream))
 }
 
+/// Return the scan filter of the files under the given root directory,
+/// or the given root file itself.
+fn root_filter(root: &str) -> Option<String> {
+    let (parent, name) = root.rsplit_once('/')?;
+    Some(format!(
+        "parent = '{root}' OR parent LIKE '{pattern}/%' ESCAPE '\\' \
+        OR (parent = '{parent}' AND name = '{name}')",
+        root = escape_sql_str(root),
+        pattern = escape_sql_str(&escape_like_pattern(root)),
+        parent = escape_sql_str(parent),
+        name = escape_sql_str(name),
+    ))
+}
+
+/// Make the `parent` column of the given file relative to the root directory.
+///
+/// Return `false` if the file is the root directory itself,
+/// which is the destination directory.
+fn strip_root(root: &str, file: &mut FileRecord) -> bool {
+    if root.is_empty() {
+        return true;
+    }
+
+    let is_child = file
+        .parent
+        .strip_prefix(root)
+        .is_some_and(|rel| rel.is_empty() || rel.starts_with('/'));
+    if is_child {
+        file.parent = file.parent[root.len()..].to_string();
+        true
+    } else {
+        // the root is the file itself
+        file.parent = String::default();
+        !file
+            .metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.file_type == FileType::Dir)
+    }
+}
+
+/// Return the paths of the entries matching the given path or glob pattern,
+/// with whether each of them is a directory.
+///
+/// The directories without their own rows are matched by their descendants.
+fn match_entries(index: &FileIndex, path: &str) -> Result<Vec<(String, bool)>> {
+    let pattern = if is_glob(path) {
+        Some(::glob::Pattern::new(path).with_context(|| format!("Invalid glob pattern: {path}"))?)
+    } else {
+        None
+    };
+    let options = ::glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let is_match = |file: &str| match pattern.as_ref() {
+        Some(pattern) => pattern.matches_with(file, options),
+        None => file == path,
+    };
+
+    let mut entries = BTreeMap::<String, bool>::default();
+    for ((parent, name), (_, metadata)) in index {
+        let file = format!("{parent}/{name}");
+        if is_match(&file) {
+            let is_dir = metadata.file_type == FileType::Dir;
+            *entries.entry(file).or_default() |= is_dir;
+        }
+        // Match the ancestor directories too
+        let mut dir = parent.as_str();
+        while !dir.is_empty() {
+            if is_match(dir) {
+                entries.insert(dir.into(), true);
+            }
+            dir = split_path(dir).0;
+        }
+    }
+    Ok(entries.into_iter().collect())
+}
+
+/// Split the given absolute path into the `parent` and `name` columns.
+fn split_path(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+/// Return whether the given row is the root entry or one of its descendants.
+fn is_under(root: &str, parent: &str, name: &str) -> bool {
+    let (root_parent, root_name) = split_path(root);
+    (parent == root_parent && name == root_name)
+        || parent
+            .strip_prefix(root)
+            .is_some_and(|rel| rel.is_empty() || rel.starts_with('/'))
+}
+
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Make the given path absolute in the dataset, e.g. `dir/a/` into `/dir/a`.
+fn normalize_path(path: &str) -> Result<String> {
+    match trim_rel_path(path) {
+        "" => bail!("Cannot modify the root directory"),
+        path => Ok(format!("/{path}")),
+    }
+}
+
+fn escape_like_pattern(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn escape_sql_str(value: &str) -> String {
+    value.replace('\'', "''")
+}
+
 fn trim_rel_path(mut path: &str) -> &str {
     while path.starts_with('/') {
         path = &path[1..];
@@ -896,3 +3164,695 @@ fn trim_rel_suffix(mut path: &str) -> &str {
 }
 
 const DIR_ROOTFS: &str = "rootfs";
+
+/// The raw rootfs table including the outdated versions of each file.
+const TABLE_ROOTFS_VERSIONS: &str = "rootfs_versions";
+
+/// The unit of the `max_buffer_size` reserved by each file being read.
+const BUFFER_PERMIT_SIZE: usize = 1024;
+
+/// The max number of the keys looked up, fetched or deleted by a single query.
+const MAX_PREDICATES_PER_QUERY: usize = 256;
+
+/// The max size of the binary data in a row.
+/// Note that each Arrow binary array is limited to 2 GiB in total.
+const MAX_ROW_SIZE: u64 = 1024 * 1024 * 1024; // 1 GiB
+
+#[cfg(test)]
+mod tests {
+    use cdl_catalog::Compression;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[::tokio::test]
+    async fn load_files_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for index in 0..64usize {
+            let dir = root.join(format!("{:02}", index / 16));
+            fs::create_dir_all(&dir).await.unwrap();
+            // the larger files are slower to read
+            let data = vec![index as u8; (64 - index) * 1024];
+            fs::write(dir.join(format!("{index:02}")), data)
+                .await
+                .unwrap();
+        }
+        let root = fs::canonicalize(&root).await.unwrap();
+
+        let catalog = DatasetCatalog {
+            max_buffer_size: 16 * 1024,
+            max_chunk_size: 4 * 1024,
+            max_read_threads: 8,
+            ..Default::default()
+        };
+        let files = FileRecord::stat_all(&catalog, &root).await.unwrap();
+        assert_eq!(files.len(), 4 + 64);
+
+        let records: Vec<_> = FileRecord::load_files(catalog, files)
+            .try_collect()
+            .await
+            .unwrap();
+        let (dirs, records): (Vec<_>, Vec<_>) = records
+            .into_iter()
+            .partition(|record| record.parent.is_empty());
+        assert_eq!(
+            dirs.iter().map(|dir| dir.name.as_str()).collect::<Vec<_>>(),
+            ["00", "01", "02", "03"],
+        );
+        let mut expected = (0..64usize)
+            .flat_map(|index| (0..(64 - index) as u64 / 4).map(move |chunk_id| (index, chunk_id)));
+        for record in &records {
+            let (index, chunk_id) = expected.next().unwrap();
+            assert_eq!(record.name, format!("{index:02}"));
+            assert_eq!(record.parent, format!("/{:02}", index / 16));
+            assert_eq!(record.chunk_id, chunk_id);
+            assert!(record.data.iter().all(|&byte| byte == index as u8));
+        }
+        assert!(expected.next().is_none());
+    }
+
+    #[test]
+    fn parse_dataset_version() {
+        let path: GlobalPath = "s3://lake#v12/dir".parse().unwrap();
+        assert_eq!(path.dataset.name, "lake");
+        assert_eq!(path.dataset.profile, None);
+        assert_eq!(path.dataset.version, Some(DatasetVersion::Number(12)));
+        assert_eq!(path.rel, Path::new("dir"));
+        assert_eq!(path.to_string(), "s3://lake#v12/dir");
+
+        let path: GlobalPath = "s3://prod@lake#2026-10-01T00:00Z".parse().unwrap();
+        assert_eq!(path.dataset.profile.as_deref(), Some("prod"));
+        assert_eq!(path.dataset.name, "lake");
+        assert_eq!(
+            path.dataset.version,
+            Some(DatasetVersion::Timestamp(
+                DateTime::from_timestamp(1_790_812_800, 0).unwrap(),
+            )),
+        );
+        assert_eq!(
+            path.dataset.to_string(),
+            "s3://prod@lake#2026-10-01T00:00:00Z"
+        );
+
+        let path: GlobalPath = "s3://prod@lake#train-v3/dir".parse().unwrap();
+        assert_eq!(path.dataset.profile.as_deref(), Some("prod"));
+        assert_eq!(
+            path.dataset.version,
+            Some(DatasetVersion::Tag("train-v3".into())),
+        );
+        assert_eq!(path.to_string(), "s3://prod@lake#train-v3/dir");
+        assert!("s3://lake#/dir".parse::<GlobalPath>().is_err());
+
+        // the profile is kept when no version is given
+        let path: GlobalPath = "s3://prod@lake/dir".parse().unwrap();
+        assert_eq!(path.dataset.profile.as_deref(), Some("prod"));
+        assert_eq!(path.dataset.version, None);
+
+        // the buckets named like the versions are not mistaken for them
+        for s in ["s3://prod@v12/dir", "s3://prod@2026-10-01/dir"] {
+            let path: GlobalPath = s.parse().unwrap();
+            assert_eq!(path.dataset.profile.as_deref(), Some("prod"));
+            assert_eq!(path.dataset.version, None);
+            assert_eq!(path.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn match_entries_by_glob() {
+        let index: FileIndex = [
+            ("", "a.log", FileType::File),
+            ("", "tmp", FileType::Dir),
+            ("/tmp", "b.log", FileType::File),
+            ("/tmp/x", "c.log", FileType::File),
+            ("/tmp/x", "d.txt", FileType::File),
+        ]
+        .into_iter()
+        .map(|(parent, name, file_type)| {
+            let metadata = FileMetadataRecord {
+                file_type,
+                ..metadata(0)
+            };
+            ((parent.into(), name.into()), (None, metadata))
+        })
+        .collect();
+        let matches = |path: &str| match_entries(&index, path).unwrap();
+
+        assert_eq!(
+            matches("/tmp/**/*.log"),
+            [("/tmp/b.log".into(), false), ("/tmp/x/c.log".into(), false)],
+        );
+        // the wildcard does not cross the directories
+        assert_eq!(matches("/*.log"), [("/a.log".into(), false)]);
+        // the implicit directories are matched too
+        assert_eq!(matches("/tmp/x"), [("/tmp/x".into(), true)]);
+        assert_eq!(matches("/tmp"), [("/tmp".into(), true)]);
+        assert!(matches("/missing").is_empty());
+
+        assert!(is_under("/tmp", "/tmp/x", "c.log"));
+        assert!(is_under("/tmp", "", "tmp"));
+        assert!(!is_under("/tmp", "/tmpfile", "a"));
+        assert_eq!(normalize_path("tmp/x/").unwrap(), "/tmp/x");
+        assert!(normalize_path("/").is_err());
+    }
+
+    fn metadata(size: usize) -> FileMetadataRecord {
+        let time = DateTime::from_timestamp_micros(1_700_000_000_000_000).unwrap();
+        FileMetadataRecord {
+            atime: time,
+            ctime: time,
+            mtime: time,
+            mode: 0o644,
+            size: size as _,
+            file_type: FileType::File,
+            link_target: None,
+            uid: None,
+            gid: None,
+            xattrs: Default::default(),
+            link_id: None,
+        }
+    }
+
+    fn chunk_ranges(catalog: &DatasetCatalog, data: &[u8]) -> Vec<(u64, u64, u64)> {
+        FileRecord::from_bytes(catalog, "".into(), "a".into(), metadata(data.len()), data)
+            .into_iter()
+            .map(|file| (file.chunk_id, file.chunk_offset, file.chunk_size))
+            .collect()
+    }
+
+    /// Generate the pseudo-random contents so that the content-defined chunks vary.
+    fn random_bytes(len: usize, mut seed: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (seed >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_large_files_into_rows() {
+        let catalog = DatasetCatalog {
+            max_buffer_size: 16 * 1024,
+            max_chunk_size: 0,
+            ..Default::default()
+        };
+        assert_eq!(chunk_ranges(&catalog, &[]), [(0, 0, 0)]);
+        assert_eq!(chunk_ranges(&catalog, &[0; 16 * 1024]), [(0, 0, 16 * 1024)],);
+        assert_eq!(
+            chunk_ranges(&catalog, &[0; 40 * 1024]),
+            [
+                (0, 0, 16 * 1024),
+                (1, 16 * 1024, 16 * 1024),
+                (2, 32 * 1024, 8 * 1024),
+            ],
+        );
+    }
+
+    #[::tokio::test]
+    async fn content_defined_chunks_survive_shifts() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+
+        let catalog = DatasetCatalog {
+            chunking_mode: ChunkingMode::ContentDefined,
+            max_buffer_size: 64 * 1024,
+            max_chunk_size: 16 * 1024,
+            ..Default::default()
+        };
+        let data = random_bytes(256 * 1024, 42);
+        let chunks =
+            FileRecord::from_bytes(&catalog, "".into(), "a".into(), metadata(data.len()), &data);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|file| file.chunk_size <= 16 * 1024));
+        assert_eq!(
+            chunks.iter().map(|file| file.chunk_size).sum::<u64>(),
+            data.len() as u64
+        );
+
+        // the streamed chunks should be equal to the in-memory ones
+        fs::write(root.join("a"), &data).await.unwrap();
+        let root = fs::canonicalize(&root).await.unwrap();
+        let files = FileRecord::stat_all(&catalog, &root).await.unwrap();
+        let records: Vec<_> = FileRecord::load_files(catalog.clone(), files)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|file| (file.chunk_offset, &file.data))
+                .collect::<Vec<_>>(),
+            chunks
+                .iter()
+                .map(|file| (file.chunk_offset, &file.data))
+                .collect::<Vec<_>>(),
+        );
+
+        // inserting a few bytes at the head should keep most of the chunks
+        let shifted = [b"hello".as_slice(), &data].concat();
+        let hashes = |data: &[u8]| -> Vec<_> {
+            FileRecord::from_bytes(&catalog, "".into(), "a".into(), metadata(data.len()), data)
+                .iter()
+                .map(|file| chunk::hash_chunk(&file.data))
+                .collect()
+        };
+        let original = hashes(&data);
+        let shifted = hashes(&shifted);
+        let shared = shifted
+            .iter()
+            .filter(|hash| original.contains(hash))
+            .count();
+        assert!(shared + 2 >= original.len());
+    }
+
+    #[test]
+    fn decompress_rows_transparently() {
+        let catalog = DatasetCatalog {
+            compression: Compression::Zstd,
+            max_buffer_size: 64 * 1024,
+            ..Default::default()
+        };
+        let data = "name,value\n".repeat(1024).into_bytes();
+        let schema = Arc::new(FileRecord::schema_arrow());
+
+        let mut builder = FileRecordBuilder::default();
+        for name in ["data.csv", "data.csv.gz"] {
+            for file in FileRecord::from_bytes(
+                &catalog,
+                "".into(),
+                name.into(),
+                metadata(data.len()),
+                &data,
+            ) {
+                assert!(builder.push(&catalog, &schema, file).unwrap().is_none());
+            }
+        }
+        let batch = builder.flush(&schema).unwrap().unwrap();
+
+        // only the compressible files are compressed
+        let codec = batch.column_by_name("codec").unwrap().as_string::<i32>();
+        assert_eq!(codec.iter().collect::<Vec<_>>(), [Some("zstd"), None]);
+
+        let files = FileRecordBatch::try_from(&batch)
+            .unwrap()
+            .into_vec(None)
+            .unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| file.data == data));
+    }
+
+    /// Build the rows of the given files committed at the timestamp.
+    fn commit_batch(timestamp: i64, files: &[(&str, &str)]) -> RecordBatch {
+        let catalog = DatasetCatalog::default();
+        let schema = Arc::new(FileRecord::schema_arrow());
+        let mut builder = FileRecordBuilder {
+            timestamp,
+            ..Default::default()
+        };
+        for (path, data) in files {
+            let (parent, name) = split_path(path);
+            let data = data.as_bytes();
+            for file in FileRecord::from_bytes(
+                &catalog,
+                parent.into(),
+                name.into(),
+                metadata(data.len()),
+                data,
+            ) {
+                assert!(builder.push(&catalog, &schema, file).unwrap().is_none());
+            }
+        }
+        builder.flush(&schema).unwrap().unwrap()
+    }
+
+    #[::tokio::test]
+    async fn expose_latest_versions() {
+        async fn query(
+            ctx: &SessionContext,
+            view: &RootfsView,
+            scope: Option<&str>,
+        ) -> Vec<String> {
+            let sql = format!(
+                "SELECT name, data FROM ({sql}) ORDER BY name",
+                sql = view.to_sql(scope),
+            );
+            let batches = ctx.sql(&sql).await.unwrap().collect().await.unwrap();
+            batches
+                .iter()
+                .flat_map(|batch| {
+                    let name = batch.column(0).as_string::<i32>();
+                    let data = batch.column(1).as_binary::<i32>();
+                    name.iter()
+                        .zip(data.iter())
+                        .map(|(name, data)| {
+                            let data = String::from_utf8_lossy(data.unwrap());
+                            format!("{name}={data}", name = name.unwrap())
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        }
+
+        let batches = vec![
+            commit_batch(2, &[("a", "new")]),
+            commit_batch(1, &[("a", "old"), ("b", "old")]),
+        ];
+        let schema = batches[0].schema();
+        let table = ::datafusion::datasource::MemTable::try_new(schema, vec![batches]).unwrap();
+        let ctx = SessionContext::new();
+        ctx.register_table(TABLE_ROOTFS_VERSIONS, Arc::new(table))
+            .unwrap();
+        let view = RootfsView {
+            has_commit_time: true,
+            store_columns: None,
+        };
+
+        // the latest version wins regardless of the order of the rows
+        assert_eq!(query(&ctx, &view, None).await, ["a=new", "b=old"]);
+        // the scope is applied before the aggregation
+        let scope = "parent = '' AND name = 'a'";
+        assert_eq!(query(&ctx, &view, Some(scope)).await, ["a=new"]);
+        let scope = "parent = '' AND name = 'b'";
+        assert_eq!(query(&ctx, &view, Some(scope)).await, ["b=old"]);
+    }
+
+    #[::tokio::test]
+    async fn delete_superseded_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let uri = root.to_str().unwrap();
+        let batches = vec![
+            commit_batch(1, &[("a", "old"), ("b", "old")]),
+            commit_batch(2, &[("a", "new")]),
+        ];
+        let schema = batches[0].schema();
+        let reader =
+            ::arrow::record_batch::RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+        let mut table = Dataset::write(reader, uri, None).await.unwrap();
+
+        // only the rows committed before the new ones are deleted
+        delete_files(&mut table, None, [("", "a")], Some(2))
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+        let predicates = file_predicates(None, [("", "a")]).unwrap();
+        let latest = load_latest_commit_time(&table, Some(&predicates)).await;
+        assert_eq!(latest.unwrap(), Some(2));
+
+        delete_files(&mut table, None, [("", "a"), ("", "b")], None)
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 0);
+    }
+
+    #[::tokio::test]
+    async fn move_directory_with_children() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let uri = root.to_str().unwrap();
+        let batch = commit_batch(
+            1,
+            &[
+                ("/src", ""),
+                ("/src/a", "a"),
+                ("/src/sub/b", "b"),
+                ("/srcx/c", "c"),
+            ],
+        );
+        let schema = batch.schema();
+        let reader = ::arrow::record_batch::RecordBatchIterator::new([Ok(batch)], schema);
+        let table = Dataset::write(reader, uri, None).await.unwrap();
+
+        move_files(table, None, "/src", "/dst/moved", true)
+            .await
+            .unwrap();
+
+        // the siblings sharing the prefix are left as they are
+        let table = Dataset::open(uri).await.unwrap();
+        let index = load_index(&table, None, None).await.unwrap();
+        let paths: BTreeMap<_, _> = index
+            .into_iter()
+            .map(|((parent, name), (_, metadata))| (format!("{parent}/{name}"), metadata.size))
+            .collect();
+        assert_eq!(
+            paths.into_iter().collect::<Vec<_>>(),
+            [
+                ("/dst/moved".into(), 0),
+                ("/dst/moved/a".into(), 1),
+                ("/dst/moved/sub/b".into(), 1),
+                ("/srcx/c".into(), 1),
+            ],
+        );
+        let data = table
+            .scan()
+            .filter("parent = '/dst/moved/sub' AND name = 'b'")
+            .unwrap()
+            .project(&["data"])
+            .unwrap()
+            .try_into_batch()
+            .await
+            .unwrap();
+        assert_eq!(data.column(0).as_binary::<i32>().value(0), b"b");
+    }
+
+    #[test]
+    fn supersede_with_skewed_clocks() {
+        let now = Utc::now().timestamp_micros();
+        assert!(next_commit_time(None) >= now);
+        assert!(next_commit_time(Some(now - 1_000_000)) >= now);
+
+        // the clock of another client may be ahead of this one
+        let ahead = now + 3_600_000_000;
+        assert_eq!(next_commit_time(Some(ahead)), ahead + 1);
+    }
+
+    #[::tokio::test]
+    async fn stream_large_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path();
+        let src = base_dir.join("src");
+        let dst = base_dir.join("dst");
+        fs::create_dir_all(&src).await.unwrap();
+        fs::create_dir_all(&dst).await.unwrap();
+
+        let data: Vec<u8> = (0..100 * 1024).map(|index| (index % 251) as u8).collect();
+        fs::write(src.join("large"), &data).await.unwrap();
+        fs::write(src.join("small"), b"hello").await.unwrap();
+        // the stale contents should be overwritten
+        fs::write(dst.join("large"), vec![0xff; 200 * 1024])
+            .await
+            .unwrap();
+
+        let catalog = DatasetCatalog {
+            max_buffer_size: 16 * 1024,
+            max_chunk_size: 0,
+            ..Default::default()
+        };
+        let src = fs::canonicalize(&src).await.unwrap();
+        let files = FileRecord::stat_all(&catalog, &src).await.unwrap();
+        let stream = FileRecord::load_files(catalog, files)
+            .inspect_ok(|record| assert!(record.data.len() <= 16 * 1024));
+        FileRecord::dump_all(&dst, stream).await.unwrap();
+
+        assert_eq!(fs::read(dst.join("large")).await.unwrap(), data);
+        assert_eq!(fs::read(dst.join("small")).await.unwrap(), b"hello");
+        assert_eq!(
+            fs::metadata(dst.join("large"))
+                .await
+                .unwrap()
+                .modified()
+                .unwrap(),
+            fs::metadata(src.join("large"))
+                .await
+                .unwrap()
+                .modified()
+                .unwrap(),
+        );
+    }
+
+    #[cfg(unix)]
+    #[::tokio::test]
+    async fn round_trip_special_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path();
+        let src = base_dir.join("src");
+        let dst = base_dir.join("dst");
+        fs::create_dir_all(src.join("empty")).await.unwrap();
+        fs::create_dir_all(src.join("locked")).await.unwrap();
+        fs::write(src.join("locked/file"), b"hello").await.unwrap();
+        fs::symlink("locked/file", src.join("link")).await.unwrap();
+        fs::symlink("missing", src.join("dangling")).await.unwrap();
+        // the symlinked directories are not followed
+        fs::symlink("locked", src.join("linked-dir")).await.unwrap();
+        fs::set_permissions(src.join("locked"), PermissionsExt::from_mode(0o555))
+            .await
+            .unwrap();
+
+        let catalog = DatasetCatalog::default();
+        let src = fs::canonicalize(&src).await.unwrap();
+        let files = FileRecord::stat_all(&catalog, &src).await.unwrap();
+        assert_eq!(files.len(), 6);
+
+        // pass the rows through the rootfs schema
+        let schema = Arc::new(FileRecord::schema_arrow());
+        let mut builder = FileRecordBuilder::default();
+        let records: Vec<_> = FileRecord::load_files(catalog.clone(), files)
+            .try_collect()
+            .await
+            .unwrap();
+        for record in records {
+            assert!(builder.push(&catalog, &schema, record).unwrap().is_none());
+        }
+        let batch = builder.flush(&schema).unwrap().unwrap();
+        let records = FileRecordBatch::try_from(&batch)
+            .unwrap()
+            .into_vec(None)
+            .unwrap();
+        FileRecord::dump_all(&dst, stream::iter(records.into_iter().map(Ok)))
+            .await
+            .unwrap();
+
+        assert!(fs::metadata(dst.join("empty")).await.unwrap().is_dir());
+        assert_eq!(fs::read(dst.join("locked/file")).await.unwrap(), b"hello");
+        assert_eq!(
+            fs::metadata(dst.join("locked"))
+                .await
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o555,
+        );
+        for (link, target) in [
+            ("link", "locked/file"),
+            ("dangling", "missing"),
+            ("linked-dir", "locked"),
+        ] {
+            assert_eq!(
+                fs::read_link(dst.join(link)).await.unwrap(),
+                Path::new(target),
+            );
+        }
+
+        for dir in [&src, &dst] {
+            fs::set_permissions(dir.join("locked"), PermissionsExt::from_mode(0o755))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[::tokio::test]
+    async fn round_trip_preserved_metadata() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path();
+        let src = base_dir.join("src");
+        let dst = base_dir.join("dst");
+        fs::create_dir_all(src.join("dir")).await.unwrap();
+        fs::write(src.join("dir/file"), b"hello").await.unwrap();
+        fs::hard_link(src.join("dir/file"), src.join("link"))
+            .await
+            .unwrap();
+        // the filesystem may not support the extended attributes
+        let has_xattrs = ::xattr::set(src.join("dir/file"), "user.cdl", b"world").is_ok();
+
+        let catalog = DatasetCatalog {
+            preserve_metadata: true,
+            ..Default::default()
+        };
+        let src = fs::canonicalize(&src).await.unwrap();
+        let files = FileRecord::stat_all(&catalog, &src).await.unwrap();
+
+        let schema = Arc::new(FileRecord::schema_arrow());
+        let mut builder = FileRecordBuilder::default();
+        let records: Vec<_> = FileRecord::load_files(catalog.clone(), files)
+            .try_collect()
+            .await
+            .unwrap();
+        for record in records {
+            assert!(builder.push(&catalog, &schema, record).unwrap().is_none());
+        }
+        let batch = builder.flush(&schema).unwrap().unwrap();
+        let records = FileRecordBatch::try_from(&batch)
+            .unwrap()
+            .into_vec(None)
+            .unwrap();
+        FileRecord::dump_all(&dst, stream::iter(records.into_iter().map(Ok)))
+            .await
+            .unwrap();
+
+        let file = fs::metadata(dst.join("dir/file")).await.unwrap();
+        let link = fs::metadata(dst.join("link")).await.unwrap();
+        assert_eq!(file.ino(), link.ino());
+        assert_eq!(file.uid(), fs::metadata(&src).await.unwrap().uid());
+        assert_eq!(fs::read(dst.join("link")).await.unwrap(), b"hello");
+        if has_xattrs {
+            assert_eq!(
+                ::xattr::get(dst.join("link"), "user.cdl").unwrap(),
+                Some(b"world".to_vec()),
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[::tokio::test]
+    async fn scope_hardlinks_per_run() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path();
+        let src = base_dir.join("src");
+        let dst = base_dir.join("dst");
+        fs::create_dir_all(&src).await.unwrap();
+        fs::write(src.join("file"), b"hello").await.unwrap();
+        fs::hard_link(src.join("file"), src.join("link"))
+            .await
+            .unwrap();
+
+        let catalog = DatasetCatalog {
+            preserve_metadata: true,
+            ..Default::default()
+        };
+        let src = fs::canonicalize(&src).await.unwrap();
+        let mut runs = Vec::default();
+        for _ in 0..2 {
+            let files = FileRecord::stat_all(&catalog, &src).await.unwrap();
+            let link_ids: Vec<_> = files
+                .into_iter()
+                .filter_map(|file| file.metadata.link_id)
+                .collect();
+            runs.push(link_ids);
+        }
+
+        // the same inode is linked within a run, but not across the runs
+        let (first, second) = (&runs[0], &runs[1]);
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0], first[1]);
+        assert_ne!(first[0], second[0]);
+
+        // the colliding identities of the unrelated files are never linked
+        let records = [("a", b"hello".as_slice()), ("b", b"world!".as_slice())]
+            .into_iter()
+            .flat_map(|(name, data)| {
+                let metadata = FileMetadataRecord {
+                    link_id: Some("collided".into()),
+                    ..metadata(data.len())
+                };
+                FileRecord::from_bytes(&catalog, "".into(), name.into(), metadata, data)
+            });
+        FileRecord::dump_all(&dst, stream::iter(records.map(Ok)))
+            .await
+            .unwrap();
+        let a = fs::metadata(dst.join("a")).await.unwrap();
+        let b = fs::metadata(dst.join("b")).await.unwrap();
+        assert_ne!(a.ino(), b.ino());
+        assert_eq!(fs::read(dst.join("b")).await.unwrap(), b"world!");
+    }
+}
@@ -0,0 +1,836 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use arrow::{
+    array::{AsArray, RecordBatchIterator},
+    datatypes::{Schema as ArrowSchema, UInt64Type},
+};
+use cdl_catalog::DatasetCatalog;
+use cdl_store::build_registry;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use itertools::Itertools;
+use lance::{
+    dataset::optimize::{compact_files, plan_compaction, CompactionOptions},
+    Dataset,
+};
+use lance_io::object_store::ObjectStore;
+use lance_table::io::commit::ManifestNamingScheme;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use crate::{escape_sql_str, timestamp_literal, try_open_dataset, MAX_PREDICATES_PER_QUERY};
+
+/// The result of merging the small fragments of a table.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct CompactReport {
+    /// The number of the fragments merged (or to be merged in the dry-run mode).
+    pub fragments_removed: usize,
+    /// The number of the new fragments written.
+    pub fragments_added: usize,
+    /// The bytes of the merged data files minus the bytes of the new ones,
+    /// which are reclaimed once the old versions are collected.
+    ///
+    /// This is always zero in the dry-run mode.
+    pub bytes: u64,
+}
+
+impl CompactReport {
+    fn merge(&mut self, other: Self) {
+        self.fragments_removed += other.fragments_removed;
+        self.fragments_added += other.fragments_added;
+        self.bytes += other.bytes;
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct GcOptions {
+    /// Report what would be removed without removing anything.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dry_run: bool,
+
+    /// Skip the tagged versions instead of failing on them.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keep_tags: bool,
+
+    /// Remove the versions older than the given age, except the latest one.
+    #[cfg_attr(feature = "serde", serde(default = "GcOptions::default_older_than"))]
+    pub older_than: Duration,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            keep_tags: false,
+            older_than: Self::default_older_than(),
+        }
+    }
+}
+
+impl GcOptions {
+    pub const fn default_older_than() -> Duration {
+        Duration::from_secs(30 * 24 * 60 * 60) // 30 days
+    }
+}
+
+/// The result of removing the old versions and the unreferenced files.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct GcReport {
+    /// The number of the rootfs versions removed.
+    pub versions: u64,
+    /// The number of the unreferenced chunks removed from the chunk store.
+    pub chunks: u64,
+    /// The uncompressed size of the unreferenced chunks.
+    pub chunk_bytes: u64,
+    /// The bytes of the data files and manifests removed from the storage.
+    ///
+    /// In the dry-run mode, this is estimated from the manifests and data files
+    /// which only the removed versions refer to.
+    pub bytes: u64,
+}
+
+/// Merge the small fragments of the table up to the `max_buffer_size`.
+#[instrument(skip_all)]
+pub(crate) async fn compact_table(
+    catalog: &DatasetCatalog,
+    table: &mut Dataset,
+    dry_run: bool,
+) -> Result<CompactReport> {
+    let options = compaction_options(catalog);
+    if dry_run {
+        let plan = plan_compaction(table, &options).await?;
+        return Ok(CompactReport {
+            fragments_removed: plan.tasks().iter().map(|task| task.fragments.len()).sum(),
+            fragments_added: 0,
+            bytes: 0,
+        });
+    }
+
+    let old_files = data_files(table);
+    let metrics = compact_files(table, options, None)
+        .await
+        .with_context(|| format!("Failed to compact the table: {uri}", uri = table.uri()))?;
+    let new_files = data_files(table);
+
+    let bytes = if metrics.fragments_removed > 0 {
+        let removed: HashSet<_> = old_files.difference(&new_files).cloned().collect();
+        let added: HashSet<_> = new_files.difference(&old_files).cloned().collect();
+        let sizes = load_data_file_sizes(catalog, table).await?;
+        let size = |files: &HashSet<String>| -> u64 {
+            files.iter().filter_map(|file| sizes.get(file)).sum()
+        };
+        size(&removed).saturating_sub(size(&added))
+    } else {
+        0
+    };
+    info!(
+        "Compacted the table {uri}: {removed} fragments into {added}, reclaiming {bytes} bytes",
+        uri = table.uri(),
+        removed = metrics.fragments_removed,
+        added = metrics.fragments_added,
+    );
+    Ok(CompactReport {
+        fragments_removed: metrics.fragments_removed,
+        fragments_added: metrics.fragments_added,
+        bytes,
+    })
+}
+
+/// Compact both the rootfs table and the chunk store, if any.
+pub(crate) async fn compact_all(
+    catalog: &DatasetCatalog,
+    table: &mut Dataset,
+    store: Option<&mut Dataset>,
+    dry_run: bool,
+) -> Result<CompactReport> {
+    let mut report = compact_table(catalog, table, dry_run).await?;
+    if let Some(store) = store {
+        report.merge(compact_table(catalog, store, dry_run).await?);
+    }
+    Ok(report)
+}
+
+/// Remove the old versions of the rootfs table,
+/// and then the chunks which none of the remaining versions refer to.
+///
+/// The chunks stored or touched within the `older_than` period are kept,
+/// as the rows referring to them may not be committed yet.
+#[instrument(skip_all)]
+pub(crate) async fn gc_all(
+    catalog: &DatasetCatalog,
+    table: &Dataset,
+    store: Option<&mut Dataset>,
+    touches_uri: &str,
+    options: &GcOptions,
+) -> Result<GcReport> {
+    let GcOptions {
+        dry_run,
+        keep_tags,
+        older_than,
+    } = *options;
+    let older_than = ::chrono::Duration::from_std(older_than).context("Too long duration")?;
+    let cutoff = Utc::now() - older_than;
+
+    // Select the versions to keep, as the cleanup does
+    let latest = table.version().version;
+    let tagged: BTreeMap<u64, String> = table
+        .tags
+        .list()
+        .await?
+        .into_iter()
+        .map(|(name, contents)| (contents.version, name))
+        .collect();
+    let (kept, removed): (Vec<_>, Vec<_>) = table
+        .versions()
+        .await?
+        .into_iter()
+        .partition(|version| version.version == latest || version.timestamp >= cutoff);
+    let (tagged_old, removed): (Vec<_>, Vec<_>) = removed
+        .into_iter()
+        .partition(|version| tagged.contains_key(&version.version));
+    if let Some(version) = tagged_old.first().filter(|_| !keep_tags) {
+        bail!(
+            "Cannot remove the tagged version v{version} (#{tag}); pass the keep-tags option to skip it",
+            version = version.version,
+            tag = tagged[&version.version],
+        )
+    }
+    let kept: Vec<_> = kept
+        .into_iter()
+        .chain(tagged_old)
+        .map(|version| version.version)
+        .collect();
+    let removed: Vec<_> = removed.into_iter().map(|version| version.version).collect();
+
+    let mut report = GcReport {
+        versions: removed.len() as _,
+        ..Default::default()
+    };
+    if dry_run {
+        report.bytes += estimate_removed_bytes(catalog, table, &kept, &removed).await?;
+    } else if !removed.is_empty() {
+        let stats = table
+            .cleanup_old_versions(older_than, Some(false), Some(false))
+            .await
+            .with_context(|| format!("Failed to clean up the table: {uri}", uri = table.uri()))?;
+        report.versions = stats.old_versions;
+        report.bytes += stats.bytes_removed;
+    }
+
+    if let Some(store) = store {
+        let mut referenced = ReferencedHashes::load(table, &kept).await?;
+        gc_store(
+            catalog,
+            table,
+            store,
+            touches_uri,
+            &mut referenced,
+            options,
+            &mut report,
+        )
+        .await?;
+    }
+
+    info!(
+        "Collected {versions} versions and {chunks} chunks ({chunk_bytes} bytes), \
+        reclaiming {bytes} bytes{dry_run}",
+        dry_run = if dry_run { " (dry-run)" } else { "" },
+        versions = report.versions,
+        chunks = report.chunks,
+        chunk_bytes = report.chunk_bytes,
+        bytes = report.bytes,
+    );
+    Ok(report)
+}
+
+/// The chunk hashes which the scanned versions of the rootfs table refer to.
+#[derive(Debug, Default)]
+struct ReferencedHashes {
+    hashes: HashSet<String>,
+    /// The fragments already scanned.
+    fragments: HashSet<u64>,
+    /// The latest version scanned.
+    version: u64,
+}
+
+impl ReferencedHashes {
+    /// Load the chunk hashes which the given versions of the rootfs table refer to.
+    async fn load(table: &Dataset, versions: &[u64]) -> Result<Self> {
+        let mut referenced = Self {
+            version: table.version().version,
+            ..Default::default()
+        };
+        referenced.scan(table, versions).await?;
+        Ok(referenced)
+    }
+
+    /// Load the versions committed since the last scan,
+    /// and return the chunk hashes which they newly refer to.
+    #[instrument(skip_all)]
+    async fn refresh(&mut self, table: &Dataset) -> Result<HashSet<String>> {
+        let latest = table.latest_version_id().await?;
+        let versions: Vec<_> = (self.version + 1..=latest).collect();
+        self.version = latest;
+        self.scan(table, &versions).await
+    }
+
+    /// Scan the fragments of the given versions which are not scanned yet,
+    /// including their deleted rows, which may be still visible in the older versions.
+    #[instrument(skip_all)]
+    async fn scan(&mut self, table: &Dataset, versions: &[u64]) -> Result<HashSet<String>> {
+        if table.schema().field("chunk_hash").is_none() {
+            return Ok(HashSet::default());
+        }
+
+        let mut fragments = BTreeMap::default();
+        for &version in versions {
+            for fragment in table.checkout_version(version).await?.get_fragments() {
+                let mut metadata = fragment.metadata().clone();
+                if self.fragments.insert(metadata.id) {
+                    metadata.deletion_file = None;
+                    fragments.insert(metadata.id, metadata);
+                }
+            }
+        }
+        if fragments.is_empty() {
+            return Ok(HashSet::default());
+        }
+
+        let mut stream = table
+            .scan()
+            .with_fragments(fragments.into_values().collect())
+            .project(&["chunk_hash"])?
+            .filter("chunk_hash IS NOT NULL")?
+            .try_into_stream()
+            .await?;
+
+        let mut hashes = HashSet::default();
+        while let Some(batch) = stream.try_next().await? {
+            let Some(column) = batch
+                .column_by_name("chunk_hash")
+                .and_then(|c| c.as_string_opt::<i32>())
+            else {
+                bail!("Invalid rootfs table schema")
+            };
+            hashes.extend(
+                column
+                    .iter()
+                    .flatten()
+                    .filter(|hash| !self.hashes.contains(*hash))
+                    .map(String::from),
+            );
+        }
+        self.hashes.extend(hashes.iter().cloned());
+        Ok(hashes)
+    }
+}
+
+/// Remove the unreferenced chunks which had been stored and touched before the cutoff.
+///
+/// The writers skip storing the chunks which already exist, touching them instead.
+/// A writer may look up a chunk before the removal and commit its rows after this scan,
+/// so the chunks touched since the cutoff are kept, or restored if touched during the removal;
+/// the writers touching them after the removal no longer find them, and store them again.
+/// The chunks referred to by the rows committed meanwhile are restored as well.
+///
+/// The removed chunks are reclaimed from the storage
+/// once the versions of the chunk store referring to them get older than the cutoff.
+#[instrument(skip_all)]
+async fn gc_store(
+    catalog: &DatasetCatalog,
+    table: &Dataset,
+    store: &mut Dataset,
+    touches_uri: &str,
+    referenced: &mut ReferencedHashes,
+    options: &GcOptions,
+    report: &mut GcReport,
+) -> Result<()> {
+    let GcOptions {
+        dry_run,
+        keep_tags: _,
+        older_than,
+    } = *options;
+    let older_than = ::chrono::Duration::from_std(older_than).context("Too long duration")?;
+    let cutoff = Utc::now() - older_than;
+    let versions = store.versions().await?;
+    let Some(version) = versions
+        .iter()
+        .filter(|version| version.timestamp < cutoff)
+        .map(|version| version.version)
+        .max()
+    else {
+        info!("No chunks are stored before {cutoff}");
+        return Ok(());
+    };
+
+    let mut stream = store
+        .checkout_version(version)
+        .await?
+        .scan()
+        .project(&["hash", "size"])?
+        .try_into_stream()
+        .await?;
+    let touched = load_touched_hashes(catalog, touches_uri, cutoff).await?;
+    let mut unreferenced = HashMap::<String, u64>::default();
+    while let Some(batch) = stream.try_next().await? {
+        let (Some(hash), Some(size)) = (
+            batch
+                .column_by_name("hash")
+                .and_then(|c| c.as_string_opt::<i32>()),
+            batch
+                .column_by_name("size")
+                .and_then(|c| c.as_primitive_opt::<UInt64Type>()),
+        ) else {
+            bail!("Invalid chunk store schema")
+        };
+        for (hash, size) in hash.iter().zip(size.iter()) {
+            let Some(hash) =
+                hash.filter(|hash| !referenced.hashes.contains(*hash) && !touched.contains(*hash))
+            else {
+                continue;
+            };
+            let size = size.unwrap_or_default();
+            if unreferenced.insert(hash.into(), size).is_none() {
+                report.chunks += 1;
+                report.chunk_bytes += size;
+            }
+        }
+    }
+    if dry_run {
+        // The cleanup keeps the latest version and the ones within the period
+        let latest = store.version().version;
+        let (kept, removed): (Vec<_>, Vec<_>) = versions
+            .iter()
+            .partition(|version| version.version == latest || version.timestamp >= cutoff);
+        let kept: Vec<_> = kept.into_iter().map(|version| version.version).collect();
+        let removed: Vec<_> = removed.into_iter().map(|version| version.version).collect();
+        report.bytes += estimate_removed_bytes(catalog, store, &kept, &removed).await?;
+        return Ok(());
+    }
+
+    if !unreferenced.is_empty() {
+        let source = store.version().version;
+        for hashes in &unreferenced.keys().chunks(MAX_PREDICATES_PER_QUERY) {
+            store.delete(&hash_predicate(hashes)).await?;
+        }
+
+        // Restore the chunks which the rows committed or the writers touched since the scan refer to
+        let mut revived = referenced.refresh(table).await?;
+        revived.extend(load_touched_hashes(catalog, touches_uri, cutoff).await?);
+        let revived: Vec<_> = revived
+            .into_iter()
+            .filter(|hash| unreferenced.contains_key(hash))
+            .collect();
+        if !revived.is_empty() {
+            restore_chunks(store, source, &revived).await?;
+            for hash in &revived {
+                report.chunks -= 1;
+                report.chunk_bytes -= unreferenced[hash];
+            }
+            info!(
+                "Restored {count} chunks referred to again",
+                count = revived.len()
+            );
+        }
+
+        // Rewrite the fragments without the deleted chunks
+        compact_table(catalog, store, false).await?;
+    }
+    let stats = store
+        .cleanup_old_versions(older_than, Some(false), Some(false))
+        .await
+        .with_context(|| format!("Failed to clean up the table: {uri}", uri = store.uri()))?;
+    report.bytes += stats.bytes_removed;
+
+    // Forget the touches older than the cutoff
+    if let Some(mut touches) = try_open_dataset(catalog, touches_uri).await? {
+        touches
+            .delete(&format!("touch_time < {}", timestamp_literal(cutoff)))
+            .await?;
+        let stats = touches
+            .cleanup_old_versions(older_than, Some(false), Some(false))
+            .await
+            .with_context(|| format!("Failed to clean up the table: {touches_uri}"))?;
+        report.bytes += stats.bytes_removed;
+    }
+    Ok(())
+}
+
+/// Load the hashes of the chunks which the writers touched since the given time.
+#[instrument(skip_all)]
+async fn load_touched_hashes(
+    catalog: &DatasetCatalog,
+    touches_uri: &str,
+    since: DateTime<Utc>,
+) -> Result<HashSet<String>> {
+    // Open the latest version, which the writers may have created meanwhile
+    let Some(touches) = try_open_dataset(catalog, touches_uri).await? else {
+        return Ok(HashSet::default());
+    };
+
+    let mut stream = touches
+        .scan()
+        .project(&["hash"])?
+        .filter(&format!("touch_time >= {}", timestamp_literal(since)))?
+        .try_into_stream()
+        .await?;
+    let mut hashes = HashSet::default();
+    while let Some(batch) = stream.try_next().await? {
+        let Some(column) = batch
+            .column_by_name("hash")
+            .and_then(|c| c.as_string_opt::<i32>())
+        else {
+            bail!("Invalid chunk touches schema")
+        };
+        hashes.extend(column.iter().flatten().map(String::from));
+    }
+    Ok(hashes)
+}
+
+/// Copy back the given chunks from the source version of the chunk store.
+#[instrument(skip_all)]
+async fn restore_chunks(store: &mut Dataset, source: u64, hashes: &[String]) -> Result<()> {
+    let source = store.checkout_version(source).await?;
+    let schema = Arc::new(ArrowSchema::from(source.schema()));
+    let mut batches = Vec::default();
+    for hashes in &hashes.iter().chunks(MAX_PREDICATES_PER_QUERY) {
+        let stream = source
+            .scan()
+            .filter(&hash_predicate(hashes))?
+            .try_into_stream()
+            .await?;
+        batches.extend(stream.try_collect::<Vec<_>>().await?);
+    }
+
+    let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+    store
+        .append(reader, None)
+        .await
+        .with_context(|| format!("Failed to restore the chunks: {uri}", uri = store.uri()))
+}
+
+fn hash_predicate<'a>(hashes: impl Iterator<Item = &'a String>) -> String {
+    format!(
+        "hash IN ({hashes})",
+        hashes = hashes
+            .map(|hash| format!("'{hash}'", hash = escape_sql_str(hash)))
+            .join(", "),
+    )
+}
+
+/// Estimate the bytes of the manifests and data files
+/// which are referred to by the removed versions only.
+#[instrument(skip_all)]
+async fn estimate_removed_bytes(
+    catalog: &DatasetCatalog,
+    table: &Dataset,
+    kept: &[u64],
+    removed: &[u64],
+) -> Result<u64> {
+    if removed.is_empty() {
+        return Ok(0);
+    }
+
+    let mut kept_files = HashSet::<String>::default();
+    for &version in kept {
+        kept_files.extend(data_files(&table.checkout_version(version).await?));
+    }
+    let mut removed_files = HashSet::<String>::default();
+    for &version in removed {
+        removed_files.extend(
+            data_files(&table.checkout_version(version).await?)
+                .into_iter()
+                .filter(|file| !kept_files.contains(file)),
+        );
+    }
+    let removed: HashSet<_> = removed.iter().copied().collect();
+
+    let mut bytes: u64 = load_data_file_sizes(catalog, table)
+        .await?
+        .into_iter()
+        .filter(|(name, _)| removed_files.contains(name))
+        .map(|(_, size)| size)
+        .sum();
+
+    let params = catalog.storage_parameters()?;
+    let (object_store, base) =
+        ObjectStore::from_uri_and_params(build_registry(), table.uri(), &params).await?;
+    let mut objects = object_store
+        .read_dir_all(&base.child("_versions"), None)
+        .await?;
+    while let Some(object) = objects.try_next().await? {
+        if object
+            .location
+            .filename()
+            .and_then(|name| ManifestNamingScheme::detect_scheme(name)?.parse_version(name))
+            .is_some_and(|version| removed.contains(&version))
+        {
+            bytes += object.size as u64;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Return the data files which the current version of the table refers to.
+fn data_files(table: &Dataset) -> HashSet<String> {
+    table
+        .get_fragments()
+        .iter()
+        .flat_map(|fragment| fragment.metadata().files.iter())
+        .map(|file| file.path.clone())
+        .collect()
+}
+
+/// Load the sizes of all the data files stored in the table, by their names.
+async fn load_data_file_sizes(
+    catalog: &DatasetCatalog,
+    table: &Dataset,
+) -> Result<HashMap<String, u64>> {
+    let params = catalog.storage_parameters()?;
+    let (object_store, base) =
+        ObjectStore::from_uri_and_params(build_registry(), table.uri(), &params).await?;
+    let mut sizes = HashMap::default();
+    let mut objects = object_store.read_dir_all(&base.child("data"), None).await?;
+    while let Some(object) = objects.try_next().await? {
+        if let Some(name) = object.location.filename() {
+            sizes.insert(name.to_string(), object.size as u64);
+        }
+    }
+    Ok(sizes)
+}
+
+fn compaction_options(catalog: &DatasetCatalog) -> CompactionOptions {
+    CompactionOptions {
+        materialize_deletions: true,
+        max_bytes_per_file: Some(catalog.max_buffer_size),
+        num_threads: Some(catalog.max_write_threads),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{
+            ArrayRef, BinaryArray, RecordBatch, StringArray, TimestampMicrosecondArray, UInt64Array,
+        },
+        datatypes::{DataType as ArrowDataType, Field as ArrowField},
+    };
+    use lance::dataset::{WriteMode, WriteParams};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn catalog() -> DatasetCatalog {
+        DatasetCatalog {
+            s3_access_key: Some("test".into()),
+            s3_secret_key: Some("test".into()),
+            ..Default::default()
+        }
+    }
+
+    async fn write_rootfs(uri: &str, hashes: &[&str], mode: WriteMode) -> Dataset {
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "chunk_hash",
+            ArrowDataType::Utf8,
+            true,
+        )]));
+        let column: ArrayRef = Arc::new(StringArray::from(hashes.to_vec()));
+        let batch = RecordBatch::try_new(schema.clone(), vec![column]).unwrap();
+        let reader = RecordBatchIterator::new([Ok(batch)], schema);
+        let params = WriteParams {
+            mode,
+            ..Default::default()
+        };
+        Dataset::write(reader, uri, Some(params)).await.unwrap()
+    }
+
+    async fn write_store(uri: &str, hashes: &[&str]) -> Dataset {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("hash", ArrowDataType::Utf8, false),
+            ArrowField::new("size", ArrowDataType::UInt64, false),
+            ArrowField::new("data", ArrowDataType::Binary, false),
+        ]));
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(hashes.to_vec())),
+            Arc::new(UInt64Array::from(vec![1; hashes.len()])),
+            Arc::new(BinaryArray::from_iter_values(hashes)),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        let reader = RecordBatchIterator::new([Ok(batch)], schema);
+        Dataset::write(reader, uri, None).await.unwrap()
+    }
+
+    async fn write_touches(uri: &str, hashes: &[&str]) {
+        let schema = Arc::new(crate::chunk::touches_schema_arrow());
+        let time = Utc::now().timestamp_micros();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(hashes.to_vec())),
+            Arc::new(TimestampMicrosecondArray::from(vec![time; hashes.len()])),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        let reader = RecordBatchIterator::new([Ok(batch)], schema);
+        let params = WriteParams {
+            mode: WriteMode::Append,
+            ..Default::default()
+        };
+        Dataset::write(reader, uri, Some(params)).await.unwrap();
+    }
+
+    async fn load_stored_hashes(uri: &str) -> Vec<String> {
+        let batches: Vec<_> = Dataset::open(uri)
+            .await
+            .unwrap()
+            .scan()
+            .project(&["hash"])
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let column = batch.column(0).as_string::<i32>();
+                column
+                    .iter()
+                    .flatten()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .sorted()
+            .collect()
+    }
+
+    #[::tokio::test]
+    async fn restore_chunks_referred_again() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let rootfs_uri = root.join("rootfs");
+        let rootfs_uri = rootfs_uri.to_str().unwrap();
+        let store_uri = root.join("chunks");
+        let store_uri = store_uri.to_str().unwrap();
+        let touches_uri = root.join("chunk_touches");
+        let touches_uri = touches_uri.to_str().unwrap();
+
+        let table = write_rootfs(rootfs_uri, &["a"], WriteMode::Create).await;
+        let mut store = write_store(store_uri, &["a", "b", "c"]).await;
+
+        let mut referenced = ReferencedHashes::load(&table, &[table.version().version])
+            .await
+            .unwrap();
+        assert_eq!(referenced.hashes, HashSet::from(["a".into()]));
+
+        // a writer refers to the stored chunk again after the scan
+        write_rootfs(rootfs_uri, &["b"], WriteMode::Append).await;
+
+        let options = GcOptions {
+            older_than: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut report = GcReport::default();
+        gc_store(
+            &catalog(),
+            &table,
+            &mut store,
+            touches_uri,
+            &mut referenced,
+            &options,
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.chunks, 1);
+        assert_eq!(report.chunk_bytes, 1);
+        assert_eq!(load_stored_hashes(store_uri).await, ["a", "b"]);
+    }
+
+    #[::tokio::test]
+    async fn keep_chunks_touched_before_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let rootfs_uri = root.join("rootfs");
+        let rootfs_uri = rootfs_uri.to_str().unwrap();
+        let store_uri = root.join("chunks");
+        let store_uri = store_uri.to_str().unwrap();
+        let touches_uri = root.join("chunk_touches");
+        let touches_uri = touches_uri.to_str().unwrap();
+
+        let table = write_rootfs(rootfs_uri, &["a"], WriteMode::Create).await;
+        let mut store = write_store(store_uri, &["a", "b", "c"]).await;
+        let mut referenced = ReferencedHashes::load(&table, &[table.version().version])
+            .await
+            .unwrap();
+
+        // a writer finds the stored chunk and touches it within the grace period
+        let older_than = Duration::from_millis(500);
+        ::tokio::time::sleep(older_than + Duration::from_millis(100)).await;
+        write_touches(touches_uri, &["b"]).await;
+
+        let options = GcOptions {
+            older_than,
+            ..Default::default()
+        };
+        let mut report = GcReport::default();
+        gc_store(
+            &catalog(),
+            &table,
+            &mut store,
+            touches_uri,
+            &mut referenced,
+            &options,
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.chunks, 1);
+        assert_eq!(report.chunk_bytes, 1);
+
+        // the writer commits the rows referring to it after the gc
+        write_rootfs(rootfs_uri, &["b"], WriteMode::Append).await;
+        assert_eq!(load_stored_hashes(store_uri).await, ["a", "b"]);
+    }
+
+    #[::tokio::test]
+    async fn estimate_removed_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let uri = root.to_str().unwrap();
+        let catalog = catalog();
+
+        write_rootfs(uri, &["a"], WriteMode::Create).await;
+        write_rootfs(uri, &["b"], WriteMode::Append).await;
+        let table = write_rootfs(uri, &["c"], WriteMode::Overwrite).await;
+
+        // nothing is removed with the versions kept
+        let estimate = |kept: &'static [u64], removed: &'static [u64]| {
+            let (catalog, table) = (&catalog, &table);
+            async move {
+                estimate_removed_bytes(catalog, table, kept, removed)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(estimate(&[1, 2, 3], &[]).await, 0);
+
+        // the appended version still refers to the data file of the previous one
+        let manifest = estimate(&[2, 3], &[1]).await;
+        assert!(manifest > 0);
+        let files = estimate(&[3], &[1, 2]).await;
+        assert!(files > manifest);
+    }
+}
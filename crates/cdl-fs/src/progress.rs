@@ -0,0 +1,201 @@
+use core::fmt;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use lance::{dataset::progress::WriteFragmentProgress, Result as LanceResult};
+use lance_table::format::Fragment;
+use tracing::info;
+
+use crate::{FileRecord, FileRecordStream};
+
+pub type ProgressCallback = Arc<dyn Send + Sync + Fn(&ProgressState)>;
+
+/// Tracks the files, bytes and fragments written by a copy.
+#[derive(Clone, Default)]
+pub struct Progress {
+    inner: Arc<ProgressInner>,
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Progress").field(&self.state()).finish()
+    }
+}
+
+impl Progress {
+    pub fn new(callback: ProgressCallback) -> Self {
+        Self {
+            inner: Arc::new(ProgressInner {
+                callback: Some(callback),
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn state(&self) -> ProgressState {
+        let ProgressInner {
+            callback: _,
+            started_at,
+            files,
+            bytes,
+            fragments,
+            total_files,
+            total_bytes,
+            last_callback: _,
+            last_event: _,
+        } = &*self.inner;
+
+        let total_files = total_files.load(Ordering::Relaxed);
+        let total_bytes = total_bytes.load(Ordering::Relaxed);
+        ProgressState {
+            files: files.load(Ordering::Relaxed),
+            bytes: bytes.load(Ordering::Relaxed),
+            fragments: fragments.load(Ordering::Relaxed),
+            total_files: Some(total_files).filter(|&total| total > 0),
+            total_bytes: Some(total_bytes).filter(|&total| total > 0),
+            elapsed: started_at.elapsed(),
+        }
+    }
+
+    pub(crate) fn add_total(&self, files: u64, bytes: u64) {
+        self.inner.total_files.fetch_add(files, Ordering::Relaxed);
+        self.inner.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.report(false);
+    }
+
+    /// Count the files and bytes passing through the given stream.
+    pub(crate) fn track(&self, stream: FileRecordStream) -> FileRecordStream {
+        let progress = self.clone();
+        Box::pin(stream.inspect_ok(move |file: &FileRecord| {
+            if file.chunk_id == 0 {
+                progress.inner.files.fetch_add(1, Ordering::Relaxed);
+            }
+            progress
+                .inner
+                .bytes
+                .fetch_add(file.chunk_size, Ordering::Relaxed);
+            progress.report(false);
+        }))
+    }
+
+    pub(crate) fn finish(&self) {
+        self.report(true)
+    }
+
+    fn report(&self, force: bool) {
+        let now = Instant::now();
+        let is_ready = |last: &Mutex<Instant>, interval| {
+            let mut last = last.lock().unwrap();
+            if force || now.duration_since(*last) >= interval {
+                *last = now;
+                true
+            } else {
+                false
+            }
+        };
+
+        if let Some(callback) = self.inner.callback.as_ref() {
+            if is_ready(&self.inner.last_callback, CALLBACK_INTERVAL) {
+                callback(&self.state());
+            }
+        }
+        if is_ready(&self.inner.last_event, EVENT_INTERVAL) {
+            let state = self.state();
+            info!(
+                files = state.files,
+                bytes = state.bytes,
+                fragments = state.fragments,
+                total_files = state.total_files,
+                total_bytes = state.total_bytes,
+                throughput = state.throughput(),
+                eta_secs = state.eta().map(|eta| eta.as_secs()),
+                "Copying files",
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl WriteFragmentProgress for Progress {
+    async fn begin(&self, _fragment: &Fragment) -> LanceResult<()> {
+        Ok(())
+    }
+
+    async fn complete(&self, _fragment: &Fragment) -> LanceResult<()> {
+        self.inner.fragments.fetch_add(1, Ordering::Relaxed);
+        self.report(false);
+        Ok(())
+    }
+}
+
+struct ProgressInner {
+    callback: Option<ProgressCallback>,
+    started_at: Instant,
+    files: AtomicU64,
+    bytes: AtomicU64,
+    fragments: AtomicU64,
+    total_files: AtomicU64,
+    total_bytes: AtomicU64,
+    last_callback: Mutex<Instant>,
+    last_event: Mutex<Instant>,
+}
+
+impl Default for ProgressInner {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            callback: None,
+            started_at: now,
+            files: AtomicU64::default(),
+            bytes: AtomicU64::default(),
+            fragments: AtomicU64::default(),
+            total_files: AtomicU64::default(),
+            total_bytes: AtomicU64::default(),
+            last_callback: Mutex::new(now),
+            last_event: Mutex::new(now),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProgressState {
+    pub files: u64,
+    pub bytes: u64,
+    pub fragments: u64,
+    pub total_files: Option<u64>,
+    pub total_bytes: Option<u64>,
+    pub elapsed: Duration,
+}
+
+impl ProgressState {
+    /// Return the average throughput in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed > 0.0 {
+            self.bytes as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Return the estimated remaining time, if the total size is known.
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total_bytes?.saturating_sub(self.bytes);
+        let throughput = self.throughput();
+        if throughput > 0.0 {
+            Some(Duration::from_secs_f64(remaining as f64 / throughput))
+        } else {
+            None
+        }
+    }
+}
+
+const CALLBACK_INTERVAL: Duration = Duration::from_millis(100);
+const EVENT_INTERVAL: Duration = Duration::from_secs(10);
@@ -0,0 +1,486 @@
+use core::fmt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::Result;
+use futures::TryStreamExt;
+use lance::Dataset;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument};
+
+use crate::{
+    chunk::{self, encode_hash, hash_chunk},
+    crypto::Cipher,
+    load_index, FileRecord, FileRecordBatch,
+};
+
+/// The result of verifying the files of a dataset.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct VerifyReport {
+    pub files: u64,
+    pub chunks: u64,
+    pub bytes: u64,
+    pub issues: Vec<VerifyIssue>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub struct VerifyIssue {
+    pub parent: String,
+    pub name: String,
+    pub chunk_id: Option<u64>,
+    pub kind: VerifyIssueKind,
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            parent,
+            name,
+            chunk_id,
+            kind,
+        } = self;
+
+        write!(f, "{parent}/{name}")?;
+        if let Some(chunk_id) = chunk_id {
+            write!(f, " (chunk {chunk_id})")?;
+        }
+        write!(f, ": {kind}")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum VerifyIssueKind {
+    ChunkChecksum { expected: String, actual: String },
+    ChunkOffset { offset: u64 },
+    ChunkSize { expected: u64, actual: u64 },
+    FileChecksum { expected: String, actual: String },
+    FileSize { expected: u64, actual: u64 },
+    MissingChunk { hash: String },
+}
+
+impl fmt::Display for VerifyIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ChunkChecksum { expected, actual } => {
+                write!(
+                    f,
+                    "chunk checksum mismatch: expected {expected}, got {actual}"
+                )
+            }
+            Self::ChunkOffset { offset } => write!(f, "overlapping chunk at offset {offset}"),
+            Self::ChunkSize { expected, actual } => {
+                write!(f, "chunk size mismatch: expected {expected}, got {actual}")
+            }
+            Self::FileChecksum { expected, actual } => {
+                write!(
+                    f,
+                    "file checksum mismatch: expected {expected}, got {actual}"
+                )
+            }
+            Self::FileSize { expected, actual } => {
+                write!(f, "file size mismatch: expected {expected}, got {actual}")
+            }
+            Self::MissingChunk { hash } => write!(f, "missing chunk in the chunk store: {hash}"),
+        }
+    }
+}
+
+/// Check the data of the given chunk against its size and checksum,
+/// which is keyed with the cipher, if any.
+pub(crate) fn verify_chunk(cipher: Option<&Cipher>, file: &FileRecord) -> Option<VerifyIssueKind> {
+    let size = file.data.len() as u64;
+    if size != file.chunk_size {
+        return Some(VerifyIssueKind::ChunkSize {
+            expected: file.chunk_size,
+            actual: size,
+        });
+    }
+
+    let expected = file.checksum.as_ref()?;
+    let actual = chunk::stored_hash(cipher, &hash_chunk(&file.data));
+    if *expected != actual {
+        Some(VerifyIssueKind::ChunkChecksum {
+            expected: expected.clone(),
+            actual,
+        })
+    } else {
+        None
+    }
+}
+
+/// Hashes the chunks of a file in order of their offsets.
+#[derive(Default)]
+pub(crate) struct FileVerifier {
+    checksum: Option<String>,
+    failed: bool,
+    hasher: Sha256,
+    offset: u64,
+    pending: BTreeMap<u64, Vec<u8>>,
+    size: Option<u64>,
+}
+
+impl FileVerifier {
+    /// Feed the chunk, returning an issue if it overlaps the previous ones.
+    pub(crate) fn update(&mut self, file: &FileRecord) -> Option<VerifyIssueKind> {
+        if let Some(checksum) = file.file_checksum.as_ref() {
+            self.checksum = Some(checksum.clone());
+        }
+        if let Some(metadata) = file.metadata.as_ref() {
+            self.size = Some(metadata.size);
+        }
+        if self.failed {
+            return None;
+        }
+
+        let offset = file.chunk_offset;
+        if offset < self.offset || self.pending.contains_key(&offset) {
+            self.failed = true;
+            return Some(VerifyIssueKind::ChunkOffset { offset });
+        }
+        if offset > self.offset {
+            // Keep the chunks arrived out of order until the gap is filled
+            self.pending.insert(offset, file.data.clone());
+            return None;
+        }
+
+        self.hasher.update(&file.data);
+        self.offset += file.data.len() as u64;
+        while let Some(data) = self.pending.remove(&self.offset) {
+            self.hasher.update(&data);
+            self.offset += data.len() as u64;
+        }
+        None
+    }
+
+    /// Skip the file-level checks as the chunk-level issue is already reported.
+    pub(crate) fn fail(&mut self) {
+        self.failed = true;
+        self.pending.clear();
+    }
+
+    /// Return the size of the file once all of its chunks are hashed.
+    pub(crate) fn completed_size(&self) -> Option<u64> {
+        self.size
+            .filter(|&size| !self.failed && size == self.offset)
+    }
+
+    /// Check the hashed contents against the file size and checksum,
+    /// which is keyed with the cipher, if any.
+    pub(crate) fn finish(self, cipher: Option<&Cipher>, size: u64) -> Option<VerifyIssueKind> {
+        let Self {
+            checksum,
+            failed,
+            hasher,
+            offset,
+            pending: _,
+            size: _,
+        } = self;
+
+        if failed {
+            return None;
+        }
+        if offset != size {
+            return Some(VerifyIssueKind::FileSize {
+                expected: size,
+                actual: offset,
+            });
+        }
+
+        let expected = checksum?;
+        let actual = chunk::stored_hash(cipher, &encode_hash(&hasher.finalize()));
+        if expected != actual {
+            Some(VerifyIssueKind::FileChecksum { expected, actual })
+        } else {
+            None
+        }
+    }
+}
+
+/// Replace the keyed hashes of the verified chunk with the plain ones,
+/// so that the destination can key them again with its own cipher.
+///
+/// The file checksum is checked as soon as all chunks of the file are hashed,
+/// and is passed on only if the chunk carrying it completes the file.
+pub(crate) fn unkey_hashes(
+    cipher: &Cipher,
+    verifiers: &mut HashMap<(String, String), FileVerifier>,
+    file: &mut FileRecord,
+) -> Option<VerifyIssueKind> {
+    let checksum = hash_chunk(&file.data);
+    if file.chunk_hash.is_some() {
+        file.chunk_hash = Some(checksum.clone());
+    }
+    if file.checksum.is_some() {
+        file.checksum = Some(checksum);
+    }
+
+    let key = (file.parent.clone(), file.name.clone());
+    let verifier = verifiers.entry(key.clone()).or_default();
+    if let Some(kind) = verifier.update(file) {
+        return Some(kind);
+    }
+    let carried = file.file_checksum.take().is_some();
+    let size = verifier.completed_size()?;
+
+    let verifier = verifiers.remove(&key)?;
+    let checksum = encode_hash(&verifier.hasher.clone().finalize());
+    if let Some(kind) = verifier.finish(Some(cipher), size) {
+        return Some(kind);
+    }
+    if carried {
+        file.file_checksum = Some(checksum);
+    }
+    None
+}
+
+/// Scan the latest version of the files and report the corrupted ones.
+#[instrument(skip_all)]
+pub(crate) async fn verify_table(
+    table: &Dataset,
+    store: Option<&Dataset>,
+    cipher: Option<&Cipher>,
+    filter: Option<&str>,
+) -> Result<VerifyReport> {
+    let index = load_index(table, cipher, filter).await?;
+
+    let mut scanner = table.scan();
+    if let Some(filter) = filter {
+        scanner.filter(filter)?;
+    }
+    let mut stream = scanner.scan_in_order(true).try_into_stream().await?;
+
+    let mut report = VerifyReport {
+        files: index.len() as _,
+        ..Default::default()
+    };
+    let mut verifiers = HashMap::<(String, String), FileVerifier>::default();
+    while let Some(batch) = stream.try_next().await? {
+        // skip the outdated versions of each file
+        let files: Vec<_> = FileRecordBatch::try_from(&batch)?
+            .into_rows(cipher)?
+            .into_iter()
+            .filter(|(commit_time, file)| {
+                let key = (file.parent.clone(), file.name.clone());
+                matches!(index.get(&key), Some((latest, _)) if latest == commit_time)
+            })
+            .map(|(_, file)| file)
+            .collect();
+
+        let hashes: HashSet<_> = files
+            .iter()
+            .filter(|file| chunk::is_unresolved(file))
+            .filter_map(|file| file.chunk_hash.as_deref())
+            .collect();
+        let chunks = match store {
+            Some(store) if !hashes.is_empty() => chunk::fetch_chunks(store, cipher, hashes).await?,
+            _ => HashMap::default(),
+        };
+
+        for mut file in files {
+            let key = (file.parent.clone(), file.name.clone());
+            let verifier = verifiers.entry(key).or_default();
+
+            let missing = match file.chunk_hash.as_ref() {
+                Some(hash) if chunk::is_unresolved(&file) => match chunks.get(hash) {
+                    Some(data) => {
+                        file.data.clone_from(data);
+                        None
+                    }
+                    None => Some(VerifyIssueKind::MissingChunk { hash: hash.clone() }),
+                },
+                _ => None,
+            };
+            let issue = match missing {
+                Some(kind) => {
+                    verifier.fail();
+                    Some(kind)
+                }
+                None => {
+                    report.chunks += 1;
+                    report.bytes += file.data.len() as u64;
+                    match verify_chunk(cipher, &file) {
+                        Some(kind) => {
+                            verifier.fail();
+                            Some(kind)
+                        }
+                        None => verifier.update(&file),
+                    }
+                }
+            };
+            if let Some(kind) = issue {
+                report.issues.push(VerifyIssue {
+                    parent: file.parent,
+                    name: file.name,
+                    chunk_id: Some(file.chunk_id),
+                    kind,
+                });
+            }
+        }
+    }
+
+    for ((parent, name), (_, metadata)) in index {
+        let verifier = verifiers
+            .remove(&(parent.clone(), name.clone()))
+            .unwrap_or_default();
+        if let Some(kind) = verifier.finish(cipher, metadata.size) {
+            report.issues.push(VerifyIssue {
+                parent,
+                name,
+                chunk_id: None,
+                kind,
+            });
+        }
+    }
+    report
+        .issues
+        .sort_by(|a, b| (&a.parent, &a.name, a.chunk_id).cmp(&(&b.parent, &b.name, b.chunk_id)));
+
+    info!(
+        "Verified {files} files: {chunks} chunks, {bytes} bytes, {issues} issues",
+        files = report.files,
+        chunks = report.chunks,
+        bytes = report.bytes,
+        issues = report.issues.len(),
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use cdl_catalog::DatasetCatalog;
+    use chrono::DateTime;
+
+    use crate::{FileMetadataRecord, FileType};
+
+    use super::*;
+
+    fn chunks(data: &[u8]) -> Vec<FileRecord> {
+        let catalog = DatasetCatalog {
+            max_buffer_size: 4,
+            max_chunk_size: 0,
+            ..Default::default()
+        };
+        let time = DateTime::from_timestamp_micros(1_700_000_000_000_000).unwrap();
+        let metadata = FileMetadataRecord {
+            atime: time,
+            ctime: time,
+            mtime: time,
+            mode: 0o644,
+            size: data.len() as _,
+            file_type: FileType::File,
+            link_target: None,
+            uid: None,
+            gid: None,
+            xattrs: Default::default(),
+            link_id: None,
+        };
+        FileRecord::from_bytes(&catalog, "".into(), "a".into(), metadata, data)
+    }
+
+    fn verify(files: &[FileRecord], size: u64) -> Vec<VerifyIssueKind> {
+        verify_with(None, files, size)
+    }
+
+    fn verify_with(
+        cipher: Option<&Cipher>,
+        files: &[FileRecord],
+        size: u64,
+    ) -> Vec<VerifyIssueKind> {
+        let mut verifier = FileVerifier::default();
+        let mut issues = Vec::default();
+        for file in files {
+            match verify_chunk(cipher, file) {
+                Some(kind) => {
+                    issues.push(kind);
+                    verifier.fail();
+                }
+                None => issues.extend(verifier.update(file)),
+            }
+        }
+        issues.extend(verifier.finish(cipher, size));
+        issues
+    }
+
+    #[test]
+    fn verify_checksums() {
+        let files = chunks(b"hello world");
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|file| file.checksum.is_some()));
+        assert!(files[2].file_checksum.is_some());
+        assert_eq!(verify(&files, 11), []);
+
+        // the chunks may arrive out of order
+        let reversed: Vec<_> = files.iter().rev().cloned().collect();
+        assert_eq!(verify(&reversed, 11), []);
+
+        // truncated file
+        assert!(matches!(
+            verify(&files[..2], 11)[..],
+            [VerifyIssueKind::FileSize {
+                expected: 11,
+                actual: 8,
+            }],
+        ));
+
+        // corrupted chunk
+        let mut corrupted = files.clone();
+        corrupted[1].data[0] ^= 0xff;
+        assert!(matches!(
+            verify(&corrupted, 11)[..],
+            [VerifyIssueKind::ChunkChecksum { .. }],
+        ));
+
+        // corrupted chunk with a consistent chunk checksum
+        corrupted[1].checksum = Some(hash_chunk(&corrupted[1].data));
+        assert!(matches!(
+            verify(&corrupted, 11)[..],
+            [VerifyIssueKind::FileChecksum { .. }],
+        ));
+    }
+
+    #[test]
+    fn verify_keyed_checksums() {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let catalog = DatasetCatalog {
+            encryption_key: Some(STANDARD.encode([1; 32])),
+            ..Default::default()
+        };
+        let cipher = Cipher::try_new(&catalog).unwrap().unwrap();
+        let cipher = cipher.as_ref();
+
+        // the hashes are stored as keyed
+        let plain = chunks(b"hello world");
+        let mut files = plain.clone();
+        for file in &mut files {
+            for hash in [&mut file.checksum, &mut file.file_checksum] {
+                if let Some(hash) = hash.as_mut() {
+                    *hash = chunk::stored_hash(Some(cipher), hash);
+                }
+            }
+        }
+        assert_eq!(verify_with(Some(cipher), &files, 11), []);
+        assert!(matches!(
+            verify(&files, 11)[..],
+            [VerifyIssueKind::ChunkChecksum { .. }, ..],
+        ));
+
+        // and passed on as plain once verified
+        let mut verifiers = HashMap::default();
+        for file in &mut files {
+            assert_eq!(unkey_hashes(cipher, &mut verifiers, file), None);
+        }
+        assert!(verifiers.is_empty());
+        for (file, plain) in files.iter().zip(&plain) {
+            assert_eq!(file.checksum, plain.checksum);
+            assert_eq!(file.file_checksum, plain.file_checksum);
+        }
+    }
+}
@@ -1,22 +1,35 @@
-use std::{ffi::OsStr, sync::Arc, time::SystemTime};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    time::{Duration, SystemTime},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Error, Result};
 use cdl_catalog::DatasetCatalog;
-use cdl_fs::GlobalPath;
+use cdl_fs::{FileMetadataRecord, FileRecord, FileType as FileRecordType, GlobalPath};
+use chrono::{DateTime, Utc};
 use fuser::{
-    Filesystem, KernelConfig, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData, ReplyDirectory,
-    ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyLock, ReplyLseek, ReplyOpen,
-    ReplyStatfs, ReplyWrite, ReplyXattr, Request, TimeOrNow,
+    FileType, Filesystem, KernelConfig, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyLock, ReplyLseek,
+    ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, Request, TimeOrNow,
+};
+use futures::TryStreamExt;
+use libc::{
+    c_int, EEXIST, EINVAL, EIO, EISDIR, ENODATA, ENOENT, ENOSYS, ENOTDIR, ENOTEMPTY, EPERM, ERANGE,
+    O_TRUNC, S_IFDIR, S_IFLNK, S_IFREG,
 };
-use lance::Dataset;
-use libc::{c_int, ENOSYS, EPERM};
 use tokio::runtime::Handle;
-use tracing::{debug, instrument, warn, Level};
+use tracing::{debug, error, instrument, Level};
+
+use crate::inode::{InodeTable, BLOCK_SIZE};
 
 pub struct CdlFS {
-    catalog: DatasetCatalog,
+    dirty: HashMap<u64, Vec<u8>>,
     handle: Handle,
-    table: Arc<Dataset>,
+    handles: HashMap<u64, Vec<FileRecord>>,
+    inner: ::cdl_fs::CdlFS,
+    inodes: InodeTable,
+    next_fh: u64,
 }
 
 impl CdlFS {
@@ -24,77 +37,402 @@ impl CdlFS {
 
     #[instrument(skip_all, err(level = Level::ERROR))]
     pub async fn load(catalog: DatasetCatalog, path: GlobalPath) -> Result<Self> {
-        let table = Arc::new(path.open(catalog).await?);
+        let inner = path.open(catalog).await?;
+
+        let mut inodes = InodeTable::default();
+        inner
+            .read_metadata_all()
+            .await?
+            .try_for_each(|files| {
+                for file in files {
+                    let parent = inodes.insert_dir_all(&file.parent);
+                    match file.metadata.as_ref().map(|m| m.file_type) {
+                        Some(FileRecordType::Dir) => {
+                            let ino = inodes.insert_dir(parent, file.name);
+                            if let Some(inode) = inodes.get_mut(ino) {
+                                inode.metadata = file.metadata;
+                            }
+                        }
+                        Some(FileRecordType::File | FileRecordType::Symlink) | None => {
+                            inodes.insert_file(parent, file.name, file.metadata);
+                        }
+                    }
+                }
+                async { Ok(()) }
+            })
+            .await?;
+
+        Ok(Self {
+            dirty: HashMap::default(),
+            handle: Handle::current(),
+            handles: HashMap::default(),
+            inner,
+            inodes,
+            next_fh: 1,
+        })
+    }
+
+    fn path_of(&self, ino: u64) -> Result<(String, String)> {
+        let inode = self
+            .inodes
+            .get(ino)
+            .ok_or_else(|| anyhow!("No such inode: {ino}"))?;
+        let parent = self
+            .inodes
+            .parent_path(ino)
+            .ok_or_else(|| anyhow!("Broken inode: {ino}"))?;
+        Ok((parent, inode.name.clone()))
+    }
+
+    fn read_chunks(&mut self, ino: u64, fh: u64, offset: u64, end: u64) -> Result<&[FileRecord]> {
+        let is_cached = self
+            .handles
+            .get(&fh)
+            .map(|chunks| covers(chunks, offset, end))
+            .unwrap_or_default();
+
+        if !is_cached {
+            let (parent, name) = self.path_of(ino)?;
+            let chunks = self.handle.block_on(self.inner.read_file_chunks(
+                &parent,
+                &name,
+                offset,
+                end - offset,
+            ))?;
+            self.handles.insert(fh, chunks);
+        }
+        Ok(self
+            .handles
+            .get(&fh)
+            .map(|chunks| chunks.as_slice())
+            .unwrap())
+    }
+
+    /// Load the whole contents of the given file on memory to be modified.
+    fn stage(&mut self, ino: u64) -> Result<&mut Vec<u8>> {
+        if !self.dirty.contains_key(&ino) {
+            let size = self
+                .inodes
+                .get(ino)
+                .and_then(|inode| inode.metadata.as_ref())
+                .map(|metadata| metadata.size)
+                .unwrap_or_default();
+
+            let data = if size > 0 {
+                let (parent, name) = self.path_of(ino)?;
+                let chunks = self
+                    .handle
+                    .block_on(self.inner.read_file_chunks(&parent, &name, 0, size))?;
+                assemble(&chunks, 0, size)
+            } else {
+                Vec::default()
+            };
+            self.dirty.insert(ino, data);
+        }
+        Ok(self.dirty.get_mut(&ino).unwrap())
+    }
+
+    /// Commit the staged files and remove the given ones from the rootfs table.
+    fn commit(&mut self, inodes: &[u64], removed: Vec<(String, String)>) -> Result<()> {
+        let mut files = Vec::default();
+        for ino in inodes {
+            if let Some(data) = self.dirty.get(ino) {
+                let (parent, name) = self.path_of(*ino)?;
+                let metadata = FileMetadataRecord {
+                    size: data.len() as _,
+                    ..self
+                        .inodes
+                        .get(*ino)
+                        .and_then(|inode| inode.metadata.clone())
+                        .unwrap_or_else(|| new_metadata(DEFAULT_FILE_MODE))
+                };
+                files.extend(FileRecord::from_bytes(
+                    self.inner.catalog(),
+                    parent,
+                    name,
+                    metadata,
+                    data,
+                ));
+            }
+        }
+        if files.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        self.handle
+            .block_on(self.inner.commit_files(files, removed))?;
+        for ino in inodes {
+            self.dirty.remove(ino);
+        }
+        self.handles.clear();
+        Ok(())
+    }
+
+    /// Create and commit an empty entry, such as a directory or a symlink.
+    fn try_create(
+        &mut self,
+        parent: u64,
+        name: &str,
+        metadata: FileMetadataRecord,
+    ) -> Result<u64, c_int> {
+        match self.inodes.get(parent) {
+            Some(inode) if inode.is_dir() => (),
+            Some(_) => return Err(ENOTDIR),
+            None => return Err(ENOENT),
+        }
+        if self.inodes.lookup(parent, name).is_some() {
+            return Err(EEXIST);
+        }
+
+        let ino = match metadata.file_type {
+            FileRecordType::Dir => {
+                let ino = self.inodes.insert_dir(parent, name.into());
+                self.inodes.get_mut(ino).ok_or(ENOENT)?.metadata = Some(metadata);
+                ino
+            }
+            FileRecordType::File | FileRecordType::Symlink => {
+                self.inodes.insert_file(parent, name.into(), Some(metadata))
+            }
+        };
+        self.dirty.insert(ino, Vec::default());
+        if let Err(error) = self.commit(&[ino], Vec::default()) {
+            self.dirty.remove(&ino);
+            self.inodes.remove(parent, name);
+            return Err(io_error(error));
+        }
+        Ok(ino)
+    }
+
+    fn try_rmdir(&mut self, parent: u64, name: &str) -> Result<(), c_int> {
+        let ino = self.inodes.lookup(parent, name).ok_or(ENOENT)?;
+        let inode = self.inodes.get(ino).ok_or(ENOENT)?;
+        if !inode.is_dir() {
+            return Err(ENOTDIR);
+        }
+        if !inode.children.is_empty() {
+            return Err(ENOTEMPTY);
+        }
+
+        // The implicit directories have no rows to remove
+        if inode.metadata.is_some() {
+            let path = self.path_of(ino).map_err(io_error)?;
+            self.dirty.remove(&ino);
+            self.commit(&[], vec![path]).map_err(io_error)?;
+        }
+        self.inodes.remove(parent, name);
+        Ok(())
+    }
+
+    fn next_fh(&mut self) -> u64 {
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        fh
+    }
+
+    fn try_setattr(
+        &mut self,
+        ino: u64,
+        mode: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        fh: Option<u64>,
+    ) -> Result<(), c_int> {
+        if self.inodes.get(ino).ok_or(ENOENT)?.is_dir()
+            || (mode.is_none() && size.is_none() && atime.is_none() && mtime.is_none())
+        {
+            return Ok(());
+        }
+
+        // Only the metadata is rewritten unless the contents are changed
+        let is_metadata_only = size.is_none() && !self.dirty.contains_key(&ino);
+        let size = match is_metadata_only {
+            true => None,
+            false => {
+                let data = self.stage(ino).map_err(io_error)?;
+                if let Some(size) = size {
+                    data.resize(size as _, 0);
+                }
+                Some(data.len() as _)
+            }
+        };
+
+        let metadata = self
+            .inodes
+            .get_mut(ino)
+            .ok_or(ENOENT)?
+            .metadata
+            .get_or_insert_with(|| new_metadata(DEFAULT_FILE_MODE));
+        metadata.ctime = Utc::now();
+        if let Some(size) = size {
+            metadata.size = size;
+        }
+        if let Some(mode) = mode {
+            metadata.mode = mode;
+        }
+        if let Some(atime) = atime {
+            metadata.atime = to_datetime(atime);
+        }
+        if let Some(mtime) = mtime {
+            metadata.mtime = to_datetime(mtime);
+        }
+
+        if is_metadata_only {
+            let metadata = metadata.clone();
+            let (parent, name) = self.path_of(ino).map_err(io_error)?;
+            self.handle
+                .block_on(self.inner.update_metadata(&parent, &name, &metadata))
+                .map_err(io_error)?;
+        } else if fh.is_none() {
+            self.commit(&[ino], Vec::default()).map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    fn try_write(&mut self, ino: u64, offset: i64, data: &[u8]) -> Result<u32, c_int> {
+        let buf = self.stage(ino).map_err(io_error)?;
+        write_at(buf, offset.max(0) as _, data);
+        let size = buf.len() as _;
+
+        let metadata = self
+            .inodes
+            .get_mut(ino)
+            .ok_or(ENOENT)?
+            .metadata
+            .get_or_insert_with(|| new_metadata(DEFAULT_FILE_MODE));
+        metadata.mtime = Utc::now();
+        metadata.size = size;
+        Ok(data.len() as _)
+    }
+
+    fn try_unlink(&mut self, parent: u64, name: &str) -> Result<(), c_int> {
+        let ino = self.inodes.lookup(parent, name).ok_or(ENOENT)?;
+        if self.inodes.get(ino).ok_or(ENOENT)?.is_dir() {
+            return Err(EISDIR);
+        }
 
-        // let mut ctx = SessionContext::new();
-        // ctx.register_table(DIR_ROOTFS, table)?;
+        let path = self.path_of(ino).map_err(io_error)?;
+        self.dirty.remove(&ino);
+        self.commit(&[], vec![path]).map_err(io_error)?;
+        self.inodes.remove(parent, name);
+        Ok(())
+    }
+
+    fn try_rename(
+        &mut self,
+        parent: u64,
+        name: &str,
+        newparent: u64,
+        newname: &str,
+    ) -> Result<(), c_int> {
+        let ino = self.inodes.lookup(parent, name).ok_or(ENOENT)?;
+        if !self.inodes.get(newparent).ok_or(ENOENT)?.is_dir() {
+            return Err(ENOTDIR);
+        }
 
-        // let sql = format!("SELECT name, parent FROM {DIR_ROOTFS}");
-        // let df = ctx.sql(&sql).await?;
-        // let stream = df.execute_stream().await?;
+        let mut removed = Vec::default();
+        match self.inodes.lookup(newparent, newname) {
+            Some(target) if target == ino => return Ok(()),
+            Some(target) => {
+                let target_inode = self.inodes.get(target).ok_or(ENOENT)?;
+                if target_inode.is_dir() && !target_inode.children.is_empty() {
+                    return Err(ENOTEMPTY);
+                }
+                for file in self.inodes.files_all(target) {
+                    removed.push(self.path_of(file).map_err(io_error)?);
+                    self.dirty.remove(&file);
+                }
+            }
+            None => (),
+        }
 
-        todo!()
-        // Ok(Self { catalog, table })
+        // Persist the pending writes before moving the rows
+        let files = self.inodes.files_all(ino);
+        self.commit(&files, removed).map_err(io_error)?;
+
+        let (from_parent, from_name) = self.path_of(ino).map_err(io_error)?;
+        let to_parent = self.inodes.dir_path(newparent).ok_or(ENOENT)?;
+        self.handle
+            .block_on(self.inner.rename(
+                &format!("{from_parent}/{from_name}"),
+                &format!("{to_parent}/{newname}"),
+            ))
+            .map_err(io_error)?;
+        self.handles.clear();
+
+        self.inodes
+            .rename(parent, name, newparent, newname.into())
+            .ok_or(ENOENT)?;
+        Ok(())
     }
 }
 
 impl Filesystem for CdlFS {
     fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
-        self.handle.block_on(async {
-            // self.table
-        });
         Ok(())
     }
 
     fn destroy(&mut self) {}
 
-    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        warn!(
-            "[Not Implemented] lookup(parent: {:#x?}, name {:?})",
-            parent, name
-        );
-        reply.error(ENOSYS);
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self
+            .inodes
+            .lookup(parent, &name.to_string_lossy())
+            .and_then(|ino| self.inodes.attr(ino, req.uid(), req.gid()))
+        {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(ENOENT),
+        }
     }
 
     fn forget(&mut self, _req: &Request<'_>, _ino: u64, _nlookup: u64) {}
 
-    fn getattr(&mut self, _req: &Request<'_>, ino: u64, fh: Option<u64>, reply: ReplyAttr) {
-        warn!(
-            "[Not Implemented] getattr(ino: {:#x?}, fh: {:#x?})",
-            ino, fh
-        );
-        reply.error(ENOSYS);
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.inodes.attr(ino, req.uid(), req.gid()) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(ENOENT),
+        }
     }
 
     fn setattr(
         &mut self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         ino: u64,
         mode: Option<u32>,
-        uid: Option<u32>,
-        gid: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
         size: Option<u64>,
-        _atime: Option<TimeOrNow>,
-        _mtime: Option<TimeOrNow>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
         _ctime: Option<SystemTime>,
         fh: Option<u64>,
         _crtime: Option<SystemTime>,
         _chgtime: Option<SystemTime>,
         _bkuptime: Option<SystemTime>,
-        flags: Option<u32>,
+        _flags: Option<u32>,
         reply: ReplyAttr,
     ) {
-        debug!(
-            "[Not Implemented] setattr(ino: {:#x?}, mode: {:?}, uid: {:?}, \
-            gid: {:?}, size: {:?}, fh: {:?}, flags: {:?})",
-            ino, mode, uid, gid, size, fh, flags
-        );
-        reply.error(ENOSYS);
+        match self
+            .try_setattr(ino, mode, size, atime, mtime, fh)
+            .and_then(|()| self.inodes.attr(ino, req.uid(), req.gid()).ok_or(ENOENT))
+        {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(error) => reply.error(error),
+        }
     }
 
     fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
-        debug!("[Not Implemented] readlink(ino: {:#x?})", ino);
-        reply.error(ENOSYS);
+        match self.inodes.get(ino) {
+            Some(inode) => match inode
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.link_target.as_ref())
+            {
+                Some(target) => reply.data(target.as_bytes()),
+                None => reply.error(EINVAL),
+            },
+            None => reply.error(ENOENT),
+        }
     }
 
     fn mknod(
@@ -117,49 +455,60 @@ impl Filesystem for CdlFS {
 
     fn mkdir(
         &mut self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         parent: u64,
         name: &OsStr,
         mode: u32,
         umask: u32,
         reply: ReplyEntry,
     ) {
-        debug!(
-            "[Not Implemented] mkdir(parent: {:#x?}, name: {:?}, mode: {}, umask: {:#x?})",
-            parent, name, mode, umask
-        );
-        reply.error(ENOSYS);
+        let metadata = FileMetadataRecord {
+            file_type: FileRecordType::Dir,
+            ..new_metadata(S_IFDIR | (mode & !umask & 0o7777))
+        };
+        match self
+            .try_create(parent, &name.to_string_lossy(), metadata)
+            .and_then(|ino| self.inodes.attr(ino, req.uid(), req.gid()).ok_or(ENOENT))
+        {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
     }
 
     fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        debug!(
-            "[Not Implemented] unlink(parent: {:#x?}, name: {:?})",
-            parent, name,
-        );
-        reply.error(ENOSYS);
+        match self.try_unlink(parent, &name.to_string_lossy()) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
     }
 
     fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        debug!(
-            "[Not Implemented] rmdir(parent: {:#x?}, name: {:?})",
-            parent, name,
-        );
-        reply.error(ENOSYS);
+        match self.try_rmdir(parent, &name.to_string_lossy()) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
     }
 
     fn symlink(
         &mut self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         parent: u64,
         link_name: &OsStr,
         target: &std::path::Path,
         reply: ReplyEntry,
     ) {
-        debug!(
-            "[Not Implemented] symlink(parent: {:#x?}, link_name: {:?}, target: {:?})",
-            parent, link_name, target,
-        );
-        reply.error(EPERM);
+        let metadata = FileMetadataRecord {
+            file_type: FileRecordType::Symlink,
+            link_target: Some(target.to_string_lossy().into()),
+            ..new_metadata(S_IFLNK | 0o777)
+        };
+        match self
+            .try_create(parent, &link_name.to_string_lossy(), metadata)
+            .and_then(|ino| self.inodes.attr(ino, req.uid(), req.gid()).ok_or(ENOENT))
+        {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
     }
 
     fn rename(
@@ -169,15 +518,18 @@ impl Filesystem for CdlFS {
         name: &OsStr,
         newparent: u64,
         newname: &OsStr,
-        flags: u32,
+        _flags: u32,
         reply: ReplyEmpty,
     ) {
-        debug!(
-            "[Not Implemented] rename(parent: {:#x?}, name: {:?}, newparent: {:#x?}, \
-            newname: {:?}, flags: {})",
-            parent, name, newparent, newname, flags,
-        );
-        reply.error(ENOSYS);
+        match self.try_rename(
+            parent,
+            &name.to_string_lossy(),
+            newparent,
+            &newname.to_string_lossy(),
+        ) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
     }
 
     fn link(
@@ -195,8 +547,20 @@ impl Filesystem for CdlFS {
         reply.error(EPERM);
     }
 
-    fn open(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: ReplyOpen) {
-        reply.opened(0, 0);
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        match self.inodes.get(ino) {
+            Some(inode) if inode.is_dir() => reply.error(EISDIR),
+            Some(_) => {
+                if flags & O_TRUNC != 0 {
+                    if let Err(error) = self.try_setattr(ino, None, Some(0), None, None, Some(0)) {
+                        return reply.error(error);
+                    }
+                }
+                let fh = self.next_fh();
+                reply.opened(fh, 0)
+            }
+            None => reply.error(ENOENT),
+        }
     }
 
     fn read(
@@ -206,90 +570,139 @@ impl Filesystem for CdlFS {
         fh: u64,
         offset: i64,
         size: u32,
-        flags: i32,
-        lock_owner: Option<u64>,
+        _flags: i32,
+        _lock_owner: Option<u64>,
         reply: ReplyData,
     ) {
-        warn!(
-            "[Not Implemented] read(ino: {:#x?}, fh: {}, offset: {}, size: {}, \
-            flags: {:#x?}, lock_owner: {:?})",
-            ino, fh, offset, size, flags, lock_owner
-        );
-        reply.error(ENOSYS);
+        let file_size = match self.inodes.get(ino) {
+            Some(inode) if inode.is_dir() => return reply.error(EISDIR),
+            Some(inode) => inode.metadata.as_ref().map(|m| m.size).unwrap_or_default(),
+            None => return reply.error(ENOENT),
+        };
+
+        let offset = offset.max(0) as u64;
+        if let Some(data) = self.dirty.get(&ino) {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(size as _).min(data.len());
+            return reply.data(&data[start..end]);
+        }
+
+        let end = offset.saturating_add(size as _).min(file_size);
+        if offset >= end {
+            return reply.data(&[]);
+        }
+
+        match self.read_chunks(ino, fh, offset, end) {
+            Ok(chunks) => reply.data(&assemble(chunks, offset, end)),
+            Err(error) => {
+                error!("Failed to read(ino: {ino:#x?}, fh: {fh}, offset: {offset}): {error}");
+                reply.error(EIO)
+            }
+        }
     }
 
     fn write(
         &mut self,
         _req: &Request<'_>,
         ino: u64,
-        fh: u64,
+        _fh: u64,
         offset: i64,
         data: &[u8],
-        write_flags: u32,
-        flags: i32,
-        lock_owner: Option<u64>,
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
         reply: ReplyWrite,
     ) {
-        debug!(
-            "[Not Implemented] write(ino: {:#x?}, fh: {}, offset: {}, data.len(): {}, \
-            write_flags: {:#x?}, flags: {:#x?}, lock_owner: {:?})",
-            ino,
-            fh,
-            offset,
-            data.len(),
-            write_flags,
-            flags,
-            lock_owner
-        );
-        reply.error(ENOSYS);
+        match self.try_write(ino, offset, data) {
+            Ok(written) => reply.written(written),
+            Err(error) => reply.error(error),
+        }
     }
 
-    fn flush(&mut self, _req: &Request<'_>, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
-        debug!(
-            "[Not Implemented] flush(ino: {:#x?}, fh: {}, lock_owner: {:?})",
-            ino, fh, lock_owner
-        );
-        reply.error(ENOSYS);
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        reply: ReplyEmpty,
+    ) {
+        match self.commit(&[ino], Vec::default()) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(io_error(error)),
+        }
     }
 
     fn release(
         &mut self,
         _req: &Request<'_>,
-        _ino: u64,
-        _fh: u64,
+        ino: u64,
+        fh: u64,
         _flags: i32,
         _lock_owner: Option<u64>,
         _flush: bool,
         reply: ReplyEmpty,
     ) {
-        reply.ok();
+        self.handles.remove(&fh);
+        match self.commit(&[ino], Vec::default()) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(io_error(error)),
+        }
     }
 
-    fn fsync(&mut self, _req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
-        debug!(
-            "[Not Implemented] fsync(ino: {:#x?}, fh: {}, datasync: {})",
-            ino, fh, datasync
-        );
-        reply.error(ENOSYS);
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyEmpty,
+    ) {
+        match self.commit(&[ino], Vec::default()) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(io_error(error)),
+        }
     }
 
-    fn opendir(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: ReplyOpen) {
-        reply.opened(0, 0);
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.inodes.get(ino) {
+            Some(inode) if inode.is_dir() => reply.opened(0, 0),
+            Some(_) => reply.error(ENOTDIR),
+            None => reply.error(ENOENT),
+        }
     }
 
     fn readdir(
         &mut self,
         _req: &Request<'_>,
         ino: u64,
-        fh: u64,
+        _fh: u64,
         offset: i64,
-        reply: ReplyDirectory,
+        mut reply: ReplyDirectory,
     ) {
-        warn!(
-            "[Not Implemented] readdir(ino: {:#x?}, fh: {}, offset: {})",
-            ino, fh, offset
-        );
-        reply.error(ENOSYS);
+        let inode = match self.inodes.get(ino) {
+            Some(inode) if inode.is_dir() => inode,
+            Some(_) => return reply.error(ENOTDIR),
+            None => return reply.error(ENOENT),
+        };
+
+        let entries = [
+            (ino, FileType::Directory, "."),
+            (inode.parent, FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(inode.children.iter().filter_map(|(name, &child)| {
+            self.inodes
+                .get(child)
+                .map(|child_inode| (child, child_inode.kind, name.as_str()))
+        }));
+
+        for (index, (ino, kind, name)) in entries.enumerate().skip(offset.max(0) as _) {
+            if reply.add(ino, (index + 1) as _, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
     }
 
     fn readdirplus(
@@ -334,7 +747,7 @@ impl Filesystem for CdlFS {
     }
 
     fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
-        reply.statfs(0, 0, 0, 0, 0, 512, 255, 0);
+        reply.statfs(0, 0, 0, 0, 0, BLOCK_SIZE, 255, 0);
     }
 
     fn setxattr(
@@ -362,19 +775,33 @@ impl Filesystem for CdlFS {
         size: u32,
         reply: ReplyXattr,
     ) {
-        debug!(
-            "[Not Implemented] getxattr(ino: {:#x?}, name: {:?}, size: {})",
-            ino, name, size
-        );
-        reply.error(ENOSYS);
+        let Some(inode) = self.inodes.get(ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+        match inode
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.xattrs.get(name.to_string_lossy().as_ref()))
+        {
+            Some(value) => reply_xattr(reply, size, value),
+            None => reply.error(ENODATA),
+        }
     }
 
     fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
-        debug!(
-            "[Not Implemented] listxattr(ino: {:#x?}, size: {})",
-            ino, size
-        );
-        reply.error(ENOSYS);
+        let Some(inode) = self.inodes.get(ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+        // The names are separated by the null characters
+        let names: Vec<u8> = inode
+            .metadata
+            .iter()
+            .flat_map(|metadata| metadata.xattrs.keys())
+            .flat_map(|name| name.bytes().chain([0]))
+            .collect();
+        reply_xattr(reply, size, &names)
     }
 
     fn removexattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
@@ -392,20 +819,31 @@ impl Filesystem for CdlFS {
 
     fn create(
         &mut self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         parent: u64,
         name: &OsStr,
         mode: u32,
         umask: u32,
-        flags: i32,
+        _flags: i32,
         reply: ReplyCreate,
     ) {
-        debug!(
-            "[Not Implemented] create(parent: {:#x?}, name: {:?}, mode: {}, umask: {:#x?}, \
-            flags: {:#x?})",
-            parent, name, mode, umask, flags
-        );
-        reply.error(ENOSYS);
+        match self.inodes.get(parent) {
+            Some(inode) if inode.is_dir() => (),
+            Some(_) => return reply.error(ENOTDIR),
+            None => return reply.error(ENOENT),
+        }
+
+        let metadata = new_metadata(S_IFREG | (mode & !umask & 0o7777));
+        let ino = self
+            .inodes
+            .insert_file(parent, name.to_string_lossy().into(), Some(metadata));
+        self.dirty.insert(ino, Vec::default());
+
+        let fh = self.next_fh();
+        match self.inodes.attr(ino, req.uid(), req.gid()) {
+            Some(attr) => reply.created(&TTL, &attr, 0, fh, 0),
+            None => reply.error(ENOENT),
+        }
     }
 
     fn getlk(
@@ -537,3 +975,129 @@ impl Filesystem for CdlFS {
         reply.error(ENOSYS);
     }
 }
+
+fn new_metadata(mode: u32) -> FileMetadataRecord {
+    let now = Utc::now();
+    FileMetadataRecord {
+        atime: now,
+        ctime: now,
+        mtime: now,
+        mode,
+        size: 0,
+        file_type: FileRecordType::File,
+        link_target: None,
+        uid: None,
+        gid: None,
+        xattrs: Default::default(),
+        link_id: None,
+    }
+}
+
+/// Reply the size of the value if the buffer size is zero, or the value itself if it fits.
+fn reply_xattr(reply: ReplyXattr, size: u32, value: &[u8]) {
+    if size == 0 {
+        reply.size(value.len() as _)
+    } else if value.len() > size as usize {
+        reply.error(ERANGE)
+    } else {
+        reply.data(value)
+    }
+}
+
+fn to_datetime(time: TimeOrNow) -> DateTime<Utc> {
+    match time {
+        TimeOrNow::SpecificTime(time) => time.into(),
+        TimeOrNow::Now => Utc::now(),
+    }
+}
+
+fn io_error(error: Error) -> c_int {
+    error!("{error}");
+    EIO
+}
+
+/// Check whether the cached chunks contain the whole `offset..end` range.
+fn covers(chunks: &[FileRecord], offset: u64, end: u64) -> bool {
+    let mut cursor = offset;
+    for chunk in chunks {
+        let chunk_end = chunk.chunk_offset + chunk.data.len() as u64;
+        if chunk.chunk_offset <= cursor && cursor < chunk_end {
+            cursor = chunk_end;
+        }
+    }
+    cursor >= end
+}
+
+fn assemble(chunks: &[FileRecord], offset: u64, end: u64) -> Vec<u8> {
+    let mut buf = vec![0; (end - offset) as usize];
+    for chunk in chunks {
+        let chunk_end = chunk.chunk_offset + chunk.data.len() as u64;
+        let start = chunk.chunk_offset.max(offset);
+        let stop = chunk_end.min(end);
+        if start < stop {
+            buf[(start - offset) as usize..(stop - offset) as usize].copy_from_slice(
+                &chunk.data
+                    [(start - chunk.chunk_offset) as usize..(stop - chunk.chunk_offset) as usize],
+            );
+        }
+    }
+    buf
+}
+
+/// Overwrite the buffer at the given offset, extending it with zeros if needed.
+fn write_at(buf: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    let end = offset + data.len();
+    if buf.len() < end {
+        buf.resize(end, 0);
+    }
+    buf[offset..end].copy_from_slice(data);
+}
+
+const DEFAULT_FILE_MODE: u32 = S_IFREG | 0o644;
+
+const TTL: Duration = Duration::from_secs(1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(data: &[u8]) -> Vec<FileRecord> {
+        let catalog = DatasetCatalog {
+            max_chunk_size: 4,
+            ..Default::default()
+        };
+        let metadata = FileMetadataRecord {
+            size: data.len() as _,
+            ..new_metadata(DEFAULT_FILE_MODE)
+        };
+        FileRecord::from_bytes(&catalog, "/dir".into(), "file".into(), metadata, data)
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let mut buf = Vec::default();
+        write_at(&mut buf, 0, b"hello");
+        // the gaps are filled with zeros
+        write_at(&mut buf, 8, b"world");
+        write_at(&mut buf, 1, b"E");
+        assert_eq!(buf, b"hEllo\0\0\0world");
+
+        let chunks = chunks(&buf);
+        assert_eq!(chunks.len(), 4);
+        assert!(covers(&chunks, 0, buf.len() as _));
+        assert_eq!(assemble(&chunks, 0, buf.len() as _), buf);
+        assert_eq!(assemble(&chunks, 3, 10), b"lo\0\0\0wo");
+    }
+
+    #[test]
+    fn cover_cached_ranges() {
+        let chunks = chunks(b"0123456789ab");
+        let cached = [chunks[0].clone(), chunks[2].clone()];
+        assert!(covers(&cached, 0, 4));
+        assert!(covers(&cached, 8, 12));
+        // the missing chunk in the middle has to be read again
+        assert!(!covers(&cached, 2, 10));
+        assert!(!covers(&cached, 4, 8));
+        assert_eq!(assemble(&cached, 2, 10), b"23\0\0\0\089");
+    }
+}
@@ -0,0 +1,320 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use cdl_fs::{FileMetadataRecord, FileType as FileRecordType};
+use fuser::{FileAttr, FileType, FUSE_ROOT_ID};
+
+#[derive(Debug)]
+pub(crate) struct Inode {
+    pub(crate) children: BTreeMap<String, u64>,
+    pub(crate) kind: FileType,
+    pub(crate) metadata: Option<FileMetadataRecord>,
+    pub(crate) name: String,
+    pub(crate) parent: u64,
+}
+
+impl Inode {
+    fn new_dir(parent: u64, name: String) -> Self {
+        Self {
+            children: BTreeMap::default(),
+            kind: FileType::Directory,
+            metadata: None,
+            name,
+            parent,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_dir(&self) -> bool {
+        self.kind == FileType::Directory
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct InodeTable {
+    inner: Vec<Inode>,
+    timestamp: SystemTime,
+}
+
+impl Default for InodeTable {
+    fn default() -> Self {
+        Self {
+            inner: vec![Inode::new_dir(FUSE_ROOT_ID, String::default())],
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
+impl InodeTable {
+    #[inline]
+    pub(crate) fn get(&self, ino: u64) -> Option<&Inode> {
+        ino.checked_sub(1)
+            .and_then(|index| self.inner.get(index as usize))
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self, ino: u64) -> Option<&mut Inode> {
+        ino.checked_sub(1)
+            .and_then(|index| self.inner.get_mut(index as usize))
+    }
+
+    pub(crate) fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
+        self.get(parent)
+            .and_then(|inode| inode.children.get(name))
+            .copied()
+    }
+
+    /// Return the directory inode of the given rootfs `parent` column,
+    /// creating the missing intermediate directories.
+    pub(crate) fn insert_dir_all(&mut self, path: &str) -> u64 {
+        path.split('/').filter(|name| !name.is_empty()).fold(
+            FUSE_ROOT_ID,
+            |parent, name| match self.lookup(parent, name) {
+                Some(ino) => ino,
+                None => self.insert(Inode::new_dir(parent, name.into())),
+            },
+        )
+    }
+
+    pub(crate) fn insert_dir(&mut self, parent: u64, name: String) -> u64 {
+        match self.lookup(parent, &name) {
+            Some(ino) => ino,
+            None => self.insert(Inode::new_dir(parent, name)),
+        }
+    }
+
+    pub(crate) fn insert_file(
+        &mut self,
+        parent: u64,
+        name: String,
+        metadata: Option<FileMetadataRecord>,
+    ) -> u64 {
+        match self.lookup(parent, &name) {
+            Some(ino) => {
+                let inode = self.get_mut(ino).unwrap();
+                if metadata.is_some() {
+                    inode.metadata = metadata;
+                }
+                ino
+            }
+            None => self.insert(Inode {
+                children: BTreeMap::default(),
+                kind: match metadata.as_ref().map(|m| m.file_type) {
+                    Some(FileRecordType::Symlink) => FileType::Symlink,
+                    Some(FileRecordType::Dir) => FileType::Directory,
+                    Some(FileRecordType::File) | None => FileType::RegularFile,
+                },
+                metadata,
+                name,
+                parent,
+            }),
+        }
+    }
+
+    /// Detach the given entry from its parent directory.
+    pub(crate) fn remove(&mut self, parent: u64, name: &str) -> Option<u64> {
+        self.get_mut(parent)?.children.remove(name)
+    }
+
+    /// Move the given entry, replacing the existing one on the destination.
+    pub(crate) fn rename(
+        &mut self,
+        parent: u64,
+        name: &str,
+        newparent: u64,
+        newname: String,
+    ) -> Option<u64> {
+        let ino = self.remove(parent, name)?;
+        self.remove(newparent, &newname);
+
+        let inode = self.get_mut(ino)?;
+        inode.name = newname.clone();
+        inode.parent = newparent;
+        self.get_mut(newparent)?.children.insert(newname, ino);
+        Some(ino)
+    }
+
+    /// Return the given inode and its descendants which are stored in the rootfs table,
+    /// i.e. the files, symlinks and the directories created explicitly.
+    pub(crate) fn files_all(&self, ino: u64) -> Vec<u64> {
+        match self.get(ino) {
+            Some(inode) if inode.is_dir() => inode
+                .metadata
+                .is_some()
+                .then_some(ino)
+                .into_iter()
+                .chain(
+                    inode
+                        .children
+                        .values()
+                        .flat_map(|&child| self.files_all(child)),
+                )
+                .collect(),
+            Some(_) => vec![ino],
+            None => Vec::default(),
+        }
+    }
+
+    fn insert(&mut self, inode: Inode) -> u64 {
+        let parent = inode.parent;
+        let name = inode.name.clone();
+
+        self.inner.push(inode);
+        let ino = self.inner.len() as u64;
+        if let Some(parent) = self.get_mut(parent) {
+            parent.children.insert(name, ino);
+        }
+        ino
+    }
+
+    /// Return the rootfs `parent` column of the given inode.
+    pub(crate) fn parent_path(&self, ino: u64) -> Option<String> {
+        let inode = self.get(ino)?;
+        self.dir_path(inode.parent)
+    }
+
+    /// Return the absolute path of the given directory inode, which is empty for the root.
+    pub(crate) fn dir_path(&self, mut ino: u64) -> Option<String> {
+        let mut names = Vec::default();
+        while ino != FUSE_ROOT_ID {
+            let inode = self.get(ino)?;
+            names.push(inode.name.as_str());
+            ino = inode.parent;
+        }
+        Some(
+            names
+                .into_iter()
+                .rev()
+                .fold(String::new(), |mut path, name| {
+                    path.push('/');
+                    path.push_str(name);
+                    path
+                }),
+        )
+    }
+
+    pub(crate) fn attr(&self, ino: u64, uid: u32, gid: u32) -> Option<FileAttr> {
+        let inode = self.get(ino)?;
+        let nlink = if inode.is_dir() { 2 } else { 1 };
+
+        Some(match inode.metadata.as_ref() {
+            Some(metadata) => FileAttr {
+                ino,
+                size: match metadata.link_target.as_ref() {
+                    Some(target) => target.len() as _,
+                    None => metadata.size,
+                },
+                blocks: metadata.size.div_ceil(BLOCK_SIZE as u64),
+                atime: metadata.atime.into(),
+                mtime: metadata.mtime.into(),
+                ctime: metadata.ctime.into(),
+                crtime: metadata.ctime.into(),
+                kind: inode.kind,
+                perm: (metadata.mode & 0o7777) as _,
+                nlink,
+                uid: metadata.uid.unwrap_or(uid),
+                gid: metadata.gid.unwrap_or(gid),
+                rdev: 0,
+                blksize: BLOCK_SIZE,
+                flags: 0,
+            },
+            None => FileAttr {
+                ino,
+                size: 0,
+                blocks: 0,
+                atime: self.timestamp,
+                mtime: self.timestamp,
+                ctime: self.timestamp,
+                crtime: self.timestamp,
+                kind: inode.kind,
+                perm: if inode.is_dir() { 0o755 } else { 0o644 },
+                nlink,
+                uid,
+                gid,
+                rdev: 0,
+                blksize: BLOCK_SIZE,
+                flags: 0,
+            },
+        })
+    }
+}
+
+pub(crate) const BLOCK_SIZE: u32 = 512;
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn metadata(file_type: FileRecordType) -> Option<FileMetadataRecord> {
+        let now = Utc::now();
+        Some(FileMetadataRecord {
+            atime: now,
+            ctime: now,
+            mtime: now,
+            mode: 0o644,
+            size: 0,
+            file_type,
+            link_target: None,
+            uid: None,
+            gid: None,
+            xattrs: Default::default(),
+            link_id: None,
+        })
+    }
+
+    #[test]
+    fn insert_entries() {
+        let mut inodes = InodeTable::default();
+        let dir = inodes.insert_dir_all("/a/b");
+        assert_eq!(inodes.insert_dir_all("/a/b/"), dir);
+        assert_eq!(inodes.dir_path(dir).unwrap(), "/a/b");
+        assert_eq!(inodes.dir_path(FUSE_ROOT_ID).unwrap(), "");
+
+        let file = inodes.insert_file(dir, "file".into(), metadata(FileRecordType::File));
+        assert_eq!(inodes.lookup(dir, "file"), Some(file));
+        assert_eq!(inodes.parent_path(file).unwrap(), "/a/b");
+        assert_eq!(inodes.get(file).unwrap().kind, FileType::RegularFile);
+
+        // the metadata is kept unless the new one is given
+        assert_eq!(inodes.insert_file(dir, "file".into(), None), file);
+        assert!(inodes.get(file).unwrap().metadata.is_some());
+
+        let link = inodes.insert_file(dir, "link".into(), metadata(FileRecordType::Symlink));
+        assert_eq!(inodes.get(link).unwrap().kind, FileType::Symlink);
+
+        // only the stored entries are listed, not the implicit directories
+        let a = inodes.lookup(FUSE_ROOT_ID, "a").unwrap();
+        assert_eq!(inodes.files_all(a), [file, link]);
+
+        assert_eq!(inodes.remove(dir, "file"), Some(file));
+        assert_eq!(inodes.lookup(dir, "file"), None);
+    }
+
+    #[test]
+    fn rename_entries() {
+        let mut inodes = InodeTable::default();
+        let src = inodes.insert_dir_all("/src");
+        let file = inodes.insert_file(src, "file".into(), metadata(FileRecordType::File));
+        let dst = inodes.insert_dir(FUSE_ROOT_ID, "dst".into());
+        inodes.insert_file(dst, "target".into(), metadata(FileRecordType::File));
+
+        // the destination is replaced
+        assert_eq!(inodes.rename(src, "file", dst, "target".into()), Some(file),);
+        assert_eq!(inodes.lookup(src, "file"), None);
+        assert_eq!(inodes.lookup(dst, "target"), Some(file));
+        assert_eq!(inodes.parent_path(file).unwrap(), "/dst");
+
+        // the descendants follow the moved directory
+        assert_eq!(
+            inodes.rename(FUSE_ROOT_ID, "dst", src, "moved".into()),
+            Some(dst),
+        );
+        assert_eq!(inodes.parent_path(file).unwrap(), "/src/moved");
+        assert_eq!(inodes.files_all(src), [file]);
+        assert_eq!(
+            inodes.rename(FUSE_ROOT_ID, "dst", src, "moved".into()),
+            None
+        );
+    }
+}
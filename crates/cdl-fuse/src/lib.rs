@@ -1,4 +1,5 @@
 mod fs;
+mod inode;
 
 use std::path::Path;
 
@@ -13,23 +14,27 @@ use self::fs::CdlFS;
 
 #[async_trait]
 pub trait FileMount {
-    async fn mount_to(self, catalog: DatasetCatalog, path: &Path) -> Result<()>;
+    async fn mount_to(self, catalog: DatasetCatalog, path: &Path, read_only: bool) -> Result<()>;
 }
 
 #[async_trait]
 impl FileMount for GlobalPath {
     #[instrument(skip_all)]
-    async fn mount_to(self, catalog: DatasetCatalog, path: &Path) -> Result<()> {
+    async fn mount_to(self, catalog: DatasetCatalog, path: &Path, read_only: bool) -> Result<()> {
         let mountpoint = ::tokio::fs::canonicalize(path).await?;
-        let options = vec![
+        let mut options = vec![
             // MountOption::AllowRoot,
             // MountOption::AutoUnmount,
             MountOption::FSName(CdlFS::NAME.into()),
-            MountOption::RO,
         ];
+        if read_only {
+            options.push(MountOption::RO);
+        } else {
+            options.push(MountOption::RW);
+        }
 
         let fs = CdlFS::load(catalog, self).await?;
-        mount2(fs, mountpoint, &options)?;
-        todo!()
+        ::tokio::task::spawn_blocking(move || mount2(fs, mountpoint, &options)).await??;
+        Ok(())
     }
 }
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use cdl_catalog::DatasetCatalog;
-use clap::Parser;
+use clap::{ArgMatches, Parser};
 
 #[derive(Clone, Debug, PartialEq, Parser)]
 pub struct Args {
@@ -12,7 +12,8 @@ pub struct Args {
 }
 
 impl Args {
-    pub(super) async fn execute(self) -> Result<()> {
+    pub(super) async fn execute(mut self, matches: &ArgMatches) -> Result<()> {
+        self.catalog.load_profile(matches)?;
         self.command.execute(self.catalog).await
     }
 }
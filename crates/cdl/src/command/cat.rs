@@ -0,0 +1,76 @@
+use anyhow::{bail, Result};
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::{FileType, GlobalPath};
+use clap::Parser;
+use tokio::io::{self, AsyncWriteExt};
+use tracing::instrument;
+
+use super::{entry_path, read_entry};
+
+/// Print the contents of the given file in the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct CatArgs {
+    pub target: GlobalPath,
+}
+
+impl CatArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let path = entry_path(&self.target.rel)?;
+        let fs = self.target.clone().open(catalog).await?;
+
+        let file = read_entry(&fs, &path).await?;
+        let Some(metadata) = file.as_ref().and_then(|file| file.metadata.as_ref()) else {
+            bail!("No such file: {target}", target = self.target)
+        };
+        match metadata.file_type {
+            FileType::File => (),
+            FileType::Dir => bail!("Is a directory: {target}", target = self.target),
+            FileType::Symlink => bail!("Is a symlink: {target}", target = self.target),
+        }
+
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", &path));
+
+        // Load the chunks window by window so that the large files are not buffered at once
+        let size = metadata.size;
+        let mut stdout = io::stdout();
+        let mut offset = 0;
+        while offset < size {
+            let end = offset.saturating_add(WINDOW_SIZE).min(size);
+            let chunks = fs
+                .read_file_chunks(parent, name, offset, end - offset)
+                .await?;
+            for chunk in &chunks {
+                let chunk_end = chunk.chunk_offset + chunk.data.len() as u64;
+                if chunk_end <= offset {
+                    continue;
+                }
+                if chunk.chunk_offset > offset {
+                    bail!(
+                        "Missing chunk of {target} at {offset}",
+                        target = self.target
+                    )
+                }
+                let start = (offset - chunk.chunk_offset) as usize;
+                let stop = (chunk_end.min(end) - chunk.chunk_offset) as usize;
+                stdout.write_all(&chunk.data[start..stop]).await?;
+                offset += (stop - start) as u64;
+                if offset >= end {
+                    break;
+                }
+            }
+            if offset < end {
+                bail!(
+                    "Missing chunk of {target} at {offset}",
+                    target = self.target
+                )
+            }
+        }
+        stdout.flush().await?;
+        Ok(())
+    }
+}
+
+/// The maximum size of the chunks loaded at once.
+const WINDOW_SIZE: u64 = 64 * 1024 * 1024;
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::GlobalPath;
+use clap::Parser;
+use tracing::{info, instrument};
+
+/// Merge the small fragments of the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct CompactArgs {
+    pub target: GlobalPath,
+
+    /// Report the fragments to be merged without merging them.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl CompactArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let fs = self.target.open(catalog).await?;
+        let report = fs.compact(self.dry_run).await?;
+        if self.dry_run {
+            info!(
+                "{fragments} fragments would be merged",
+                fragments = report.fragments_removed,
+            );
+        } else {
+            info!(
+                "Merged {removed} fragments into {added}, reclaiming {bytes} bytes",
+                removed = report.fragments_removed,
+                added = report.fragments_added,
+                bytes = report.bytes,
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,10 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 use cdl_catalog::DatasetCatalog;
-use cdl_fs::GlobalPath;
+use cdl_fs::{CopyOptions, GlobalPath, Progress, ProgressState};
 use clap::Parser;
+use indicatif::{ProgressBar, ProgressStyle};
 use tracing::instrument;
 
 /// Copy the dataset's data into the other's specific directory
@@ -10,12 +13,66 @@ use tracing::instrument;
 pub struct CopyArgs {
     pub from: GlobalPath,
     pub to: GlobalPath,
+
+    /// Hide the progress bar.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Discard the checkpoint of the interrupted upload and start over.
+    #[arg(long)]
+    pub restart: bool,
+
+    /// Upload the new or changed files only, and remove the deleted ones.
+    #[arg(long)]
+    pub sync: bool,
 }
 
 impl CopyArgs {
     #[instrument(skip_all)]
     pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
         let fs = self.from.open(catalog).await?;
-        fs.copy_to(&self.to).await
+
+        let bar = if self.no_progress {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(0)
+        };
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{bar:40} {bytes}/{total_bytes} ({binary_bytes_per_sec}, ETA {eta}) {msg}",
+            )?
+            .progress_chars("=> "),
+        );
+
+        let progress = Progress::new(Arc::new({
+            let bar = bar.clone();
+            move |state: &ProgressState| {
+                if let Some(total_bytes) = state.total_bytes {
+                    bar.set_length(total_bytes);
+                }
+                bar.set_position(state.bytes);
+                bar.set_message(match state.total_files {
+                    Some(total_files) => format!(
+                        "{files}/{total_files} files, {fragments} fragments",
+                        files = state.files,
+                        fragments = state.fragments,
+                    ),
+                    None => format!(
+                        "{files} files, {fragments} fragments",
+                        files = state.files,
+                        fragments = state.fragments,
+                    ),
+                });
+            }
+        }));
+
+        let options = CopyOptions {
+            progress,
+            restart: self.restart,
+            sync: self.sync,
+        };
+        let result = fs.copy_to_with(&self.to, options).await;
+        bar.finish();
+        result
     }
 }
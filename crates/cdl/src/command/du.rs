@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::{FileType, GlobalPath};
+use clap::{ArgAction, Parser};
+use futures::TryStreamExt;
+use tracing::instrument;
+
+use super::{entry_path, format_size};
+
+/// Summarize the size of each directory in the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+#[command(disable_help_flag = true)]
+pub struct DuArgs {
+    pub target: GlobalPath,
+
+    /// Print the total size of the target only.
+    #[arg(short, long, conflicts_with = "max_depth")]
+    pub summarize: bool,
+
+    /// Print the directories only up to the given depth below the target.
+    #[arg(short = 'd', long, value_name = "DEPTH")]
+    pub max_depth: Option<usize>,
+
+    /// Print the sizes in powers of 1024 (e.g. `1.5 MiB`).
+    #[arg(short, long)]
+    pub human_readable: bool,
+
+    /// Print help
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
+}
+
+impl DuArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let path = entry_path(&self.target.rel)?;
+        let fs = self.target.clone().open(catalog).await?;
+
+        // The total sizes of the directories, keyed by their paths relative to the target
+        let mut dirs = BTreeMap::<String, u64>::default();
+        if path.is_empty() {
+            dirs.insert(String::new(), 0);
+        }
+        let mut stream = Box::pin(fs.read_metadata_all().await?);
+        while let Some(files) = stream.try_next().await? {
+            for file in files {
+                let Some(metadata) = file.metadata else {
+                    continue;
+                };
+                let size = match metadata.file_type {
+                    FileType::File => metadata.size,
+                    FileType::Dir | FileType::Symlink => 0,
+                };
+                let full_path = format!("{parent}/{name}", parent = file.parent, name = file.name);
+                if full_path == path {
+                    // The target may be a file itself
+                    *dirs.entry(String::new()).or_default() += size;
+                    continue;
+                }
+                let Some(rel) = file.parent.strip_prefix(&path) else {
+                    continue;
+                };
+                if !rel.is_empty() && !rel.starts_with('/') {
+                    continue;
+                }
+
+                if metadata.file_type == FileType::Dir {
+                    dirs.entry(format!("{rel}/{name}", name = file.name))
+                        .or_default();
+                }
+                // Add the size to the parent directory and all of its ancestors
+                let mut dir = rel;
+                loop {
+                    *dirs.entry(dir.into()).or_default() += size;
+                    match dir.rsplit_once('/') {
+                        Some((parent, _)) => dir = parent,
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        let Some(total) = dirs.remove("") else {
+            bail!("No such file or directory: {target}", target = self.target)
+        };
+        if !self.summarize {
+            for (dir, size) in dirs {
+                let depth = dir.matches('/').count();
+                if self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+                    continue;
+                }
+                println!(
+                    "{size}\t{path}{dir}",
+                    size = format_size(size, self.human_readable),
+                );
+            }
+        }
+        println!(
+            "{size}\t{path}",
+            size = format_size(total, self.human_readable),
+            path = if path.is_empty() { "/" } else { &path },
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::{GcOptions, GlobalPath};
+use clap::Parser;
+use tracing::{info, instrument};
+
+/// Remove the old versions of the dataset and the unreferenced files
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct GcArgs {
+    pub target: GlobalPath,
+
+    /// Report what would be removed without removing anything.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Skip the tagged versions instead of failing on them.
+    #[arg(long)]
+    pub keep_tags: bool,
+
+    /// Remove the versions older than the given age (e.g. `30d`), except the latest one.
+    #[arg(long, default_value = "30d", value_parser = ::humantime::parse_duration)]
+    pub older_than: Duration,
+}
+
+impl GcArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let fs = self.target.open(catalog).await?;
+        let options = GcOptions {
+            dry_run: self.dry_run,
+            keep_tags: self.keep_tags,
+            older_than: self.older_than,
+        };
+        let report = fs.gc(&options).await?;
+
+        let verb = if self.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        info!(
+            "{verb} {versions} versions and {chunks} chunks ({chunk_bytes} bytes)",
+            versions = report.versions,
+            chunks = report.chunks,
+            chunk_bytes = report.chunk_bytes,
+        );
+        if self.dry_run {
+            info!("Would reclaim about {bytes} bytes", bytes = report.bytes);
+        } else {
+            info!("Reclaimed {bytes} bytes", bytes = report.bytes);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{bail, Result};
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::{FileRecord, FileType, GlobalPath};
+use clap::{ArgAction, Parser};
+use futures::TryStreamExt;
+use tracing::instrument;
+
+use super::{entry_path, format_mode, format_size, read_entry};
+
+/// List the files of the given directory in the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+#[command(disable_help_flag = true)]
+pub struct LsArgs {
+    pub target: GlobalPath,
+
+    /// Print the mode, owner, size and modified time of each entry.
+    #[arg(short, long)]
+    pub long: bool,
+
+    /// List the subdirectories recursively, printing the relative paths.
+    #[arg(short = 'R', long)]
+    pub recursive: bool,
+
+    /// Print the sizes in powers of 1024 (e.g. `1.5 MiB`).
+    #[arg(short, long)]
+    pub human_readable: bool,
+
+    /// Print help
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
+}
+
+impl LsArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let path = entry_path(&self.target.rel)?;
+        let fs = self.target.clone().open(catalog).await?;
+
+        let entry = if path.is_empty() {
+            None
+        } else {
+            read_entry(&fs, &path).await?
+        };
+        if let Some(file) = entry.as_ref().filter(|file| {
+            file.metadata
+                .as_ref()
+                .is_some_and(|metadata| metadata.file_type != FileType::Dir)
+        }) {
+            self.print(file, &file.name);
+            return Ok(());
+        }
+
+        let mut found = false;
+        if self.recursive {
+            let mut stream = Box::pin(fs.read_metadata_all().await?);
+            while let Some(files) = stream.try_next().await? {
+                for file in files {
+                    let Some(rel) = file.parent.strip_prefix(&path) else {
+                        continue;
+                    };
+                    if !rel.is_empty() && !rel.starts_with('/') {
+                        continue;
+                    }
+                    let name = format!("{rel}/{name}", name = file.name);
+                    self.print(&file, name.trim_start_matches('/'));
+                    found = true;
+                }
+            }
+        } else {
+            let mut stream = Box::pin(fs.read_metadata(&path).await?);
+            while let Some(files) = stream.try_next().await? {
+                for file in files.iter().filter(|file| file.parent == path) {
+                    self.print(file, &file.name);
+                    found = true;
+                }
+            }
+        }
+
+        if !found && entry.is_none() && !path.is_empty() {
+            bail!("No such file or directory: {target}", target = self.target)
+        }
+        Ok(())
+    }
+
+    fn print(&self, file: &FileRecord, name: &str) {
+        let Some(metadata) = file.metadata.as_ref() else {
+            return;
+        };
+        if !self.long {
+            println!("{name}");
+            return;
+        }
+
+        let owner = |id: Option<u32>| id.map(|id| id.to_string()).unwrap_or_else(|| "-".into());
+        let link = match metadata.link_target.as_ref() {
+            Some(target) => format!(" -> {target}"),
+            None => String::new(),
+        };
+        println!(
+            "{mode} {uid:>5} {gid:>5} {size:>10} {mtime} {name}{link}",
+            mode = format_mode(metadata),
+            uid = owner(metadata.uid),
+            gid = owner(metadata.gid),
+            size = format_size(metadata.size, self.human_readable),
+            mtime = metadata.mtime.format("%Y-%m-%d %H:%M"),
+        );
+    }
+}
@@ -1,27 +1,116 @@
+pub mod cat;
+pub mod compact;
 pub mod copy;
+pub mod du;
+pub mod gc;
+pub mod ls;
 #[cfg(target_os = "linux")]
 pub mod mount;
+pub mod mv;
 pub mod query;
+pub mod rm;
+pub mod stat;
+pub mod tag;
+pub mod verify;
+pub mod versions;
 
-use anyhow::Result;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use byte_unit::{Byte, UnitType};
 use cdl_catalog::DatasetCatalog;
+use cdl_fs::{CdlFS, FileMetadataRecord, FileRecord, FileType};
 use clap::Subcommand;
+use futures::TryStreamExt;
 
 #[derive(Clone, Debug, PartialEq, Subcommand)]
 pub enum Command {
+    Cat(self::cat::CatArgs),
+    Compact(self::compact::CompactArgs),
     Cp(self::copy::CopyArgs),
+    Du(self::du::DuArgs),
+    Gc(self::gc::GcArgs),
+    Ls(self::ls::LsArgs),
     #[cfg(target_os = "linux")]
     Mount(self::mount::MountArgs),
+    Mv(self::mv::MvArgs),
     Query(self::query::QueryArgs),
+    Rm(self::rm::RmArgs),
+    Stat(self::stat::StatArgs),
+    Tag(self::tag::TagArgs),
+    Verify(self::verify::VerifyArgs),
+    Versions(self::versions::VersionsArgs),
 }
 
 impl Command {
     pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
         match self {
+            Self::Cat(args) => args.execute(catalog).await,
+            Self::Compact(args) => args.execute(catalog).await,
             Self::Cp(args) => args.execute(catalog).await,
+            Self::Du(args) => args.execute(catalog).await,
+            Self::Gc(args) => args.execute(catalog).await,
+            Self::Ls(args) => args.execute(catalog).await,
             #[cfg(target_os = "linux")]
             Self::Mount(args) => args.execute(catalog).await,
+            Self::Mv(args) => args.execute(catalog).await,
             Self::Query(args) => args.execute(catalog).await,
+            Self::Rm(args) => args.execute(catalog).await,
+            Self::Stat(args) => args.execute(catalog).await,
+            Self::Tag(args) => args.execute(catalog).await,
+            Self::Verify(args) => args.execute(catalog).await,
+            Self::Versions(args) => args.execute(catalog).await,
+        }
+    }
+}
+
+/// Return the absolute path of the entry in the dataset, or an empty string for the root.
+fn entry_path(rel: &Path) -> Result<String> {
+    let path = rel.to_str().context("Invalid path")?.trim_matches('/');
+    if path.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(format!("/{path}"))
+    }
+}
+
+/// Read the metadata of the given entry, if it has been stored.
+async fn read_entry(fs: &CdlFS, path: &str) -> Result<Option<FileRecord>> {
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut stream = Box::pin(fs.read_metadata(parent).await?);
+    while let Some(files) = stream.try_next().await? {
+        if let Some(file) = files
+            .into_iter()
+            .find(|file| file.parent == parent && file.name == name)
+        {
+            return Ok(Some(file));
         }
     }
+    Ok(None)
+}
+
+fn format_size(size: u64, human_readable: bool) -> String {
+    if human_readable {
+        let size = Byte::from_u64(size).get_appropriate_unit(UnitType::Binary);
+        format!("{size:.1}")
+    } else {
+        size.to_string()
+    }
+}
+
+/// Format the file type and the permissions as `ls -l` does, e.g. `drwxr-xr-x`.
+fn format_mode(metadata: &FileMetadataRecord) -> String {
+    let kind = match metadata.file_type {
+        FileType::File => '-',
+        FileType::Dir => 'd',
+        FileType::Symlink => 'l',
+    };
+    let perms = (0..9).rev().map(|bit| {
+        if metadata.mode & (1 << bit) == 0 {
+            '-'
+        } else {
+            ['x', 'w', 'r'][bit % 3]
+        }
+    });
+    ::core::iter::once(kind).chain(perms).collect()
 }
@@ -13,11 +13,17 @@ use tracing::instrument;
 pub struct MountArgs {
     pub from: GlobalPath,
     pub to: PathBuf,
+
+    /// Allow modifying the dataset through the mounted directory.
+    #[arg(long)]
+    pub read_write: bool,
 }
 
 impl MountArgs {
     #[instrument(skip_all)]
     pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
-        self.from.mount_to(catalog, &self.to).await
+        self.from
+            .mount_to(catalog, &self.to, !self.read_write)
+            .await
     }
 }
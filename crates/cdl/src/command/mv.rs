@@ -0,0 +1,35 @@
+use anyhow::{bail, Result};
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::{GlobalPath, Scheme};
+use clap::Parser;
+use tracing::{info, instrument};
+
+/// Move the file or directory inside the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct MvArgs {
+    pub from: GlobalPath,
+
+    /// The destination in the same dataset, or its path only.
+    pub to: GlobalPath,
+}
+
+impl MvArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        if self.to.dataset.scheme != Scheme::Local && self.to.dataset != self.from.dataset {
+            bail!(
+                "Cannot move files across the datasets; use `cp` instead: {from} -> {to}",
+                from = self.from,
+                to = self.to,
+            )
+        }
+
+        let from = self.from.rel.to_string_lossy().to_string();
+        let to = self.to.rel.to_string_lossy().to_string();
+        let fs = self.from.open(catalog).await?;
+        let count = fs.rename(&from, &to).await?;
+        info!("Moved {count} entries");
+        Ok(())
+    }
+}
@@ -1,7 +1,17 @@
-use anyhow::Result;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use arrow::{csv, ipc, json, util::pretty::pretty_format_batches};
 use cdl_catalog::DatasetCatalog;
 use cdl_fs::GlobalPath;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use datafusion::execution::SendableRecordBatchStream;
+use futures::TryStreamExt;
+use parquet::arrow::ArrowWriter;
 use tracing::instrument;
 
 /// Query the given SQL into the specific dataset
@@ -10,14 +20,133 @@ use tracing::instrument;
 pub struct QueryArgs {
     pub target: GlobalPath,
     pub sql: String,
+
+    /// Output format of the query results.
+    #[arg(short, long, value_enum, default_value_t = QueryFormat::default())]
+    pub format: QueryFormat,
+
+    /// Maximum number of the rows to output.
+    ///
+    /// Defaults to 10 rows when printing a table on the standard output,
+    /// otherwise all rows are written.
+    #[arg(short, long, conflicts_with = "no_limit")]
+    pub limit: Option<usize>,
+
+    /// Write all rows of the results.
+    #[arg(long)]
+    pub no_limit: bool,
+
+    /// Write the results into the given file instead of the standard output.
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
 }
 
 impl QueryArgs {
     #[instrument(skip_all)]
     pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
-        let fs = self.target.open(catalog).await?;
-        let df = fs.query(&self.sql).await?;
-        df.show_limit(10).await?;
+        let limit = self.limit();
+        let Self {
+            target,
+            sql,
+            format,
+            limit: _,
+            no_limit: _,
+            output,
+        } = self;
+
+        let fs = target.open(catalog).await?;
+        let mut df = fs.query(&sql).await?;
+        if let Some(limit) = limit {
+            df = df.limit(0, Some(limit))?;
+        }
+        let stream = df.execute_stream().await?;
+
+        let output: Box<dyn Write + Send> = match output.as_ref() {
+            Some(path) => Box::new(File::create(path).with_context(|| {
+                format!(
+                    "Failed to create the output file: {path}",
+                    path = path.display()
+                )
+            })?),
+            None => Box::new(io::stdout()),
+        };
+        let mut output = BufWriter::new(output);
+        format.write_all(stream, &mut output).await?;
+        output.flush().map_err(Into::into)
+    }
+
+    fn limit(&self) -> Option<usize> {
+        if self.no_limit {
+            None
+        } else {
+            self.limit.or_else(|| {
+                (self.format == QueryFormat::Table && self.output.is_none())
+                    .then_some(DEFAULT_LIMIT)
+            })
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum QueryFormat {
+    /// Pretty-print the results as a table.
+    #[default]
+    Table,
+    /// Comma-separated values with a header row.
+    Csv,
+    /// One JSON object per row.
+    Jsonl,
+    /// Apache Parquet file.
+    Parquet,
+    /// Apache Arrow IPC file.
+    Arrow,
+}
+
+impl QueryFormat {
+    /// Stream the record batches into the output in the given format.
+    async fn write_all(
+        self,
+        mut stream: SendableRecordBatchStream,
+        mut output: impl Write + Send,
+    ) -> Result<()> {
+        let schema = stream.schema();
+        match self {
+            Self::Table => {
+                // The column widths depend on all rows
+                let batches: Vec<_> = stream.try_collect().await?;
+                writeln!(output, "{}", pretty_format_batches(&batches)?)?;
+            }
+            Self::Csv => {
+                let mut writer = csv::WriterBuilder::new().with_header(true).build(output);
+                while let Some(batch) = stream.try_next().await? {
+                    writer.write(&batch)?;
+                }
+            }
+            Self::Jsonl => {
+                let mut writer = json::LineDelimitedWriter::new(output);
+                while let Some(batch) = stream.try_next().await? {
+                    writer.write(&batch)?;
+                }
+                writer.finish()?;
+            }
+            Self::Parquet => {
+                let mut writer = ArrowWriter::try_new(output, schema, None)?;
+                while let Some(batch) = stream.try_next().await? {
+                    writer.write(&batch)?;
+                }
+                writer.close()?;
+            }
+            Self::Arrow => {
+                let mut writer = ipc::writer::FileWriter::try_new(output, &schema)?;
+                while let Some(batch) = stream.try_next().await? {
+                    writer.write(&batch)?;
+                }
+                writer.finish()?;
+            }
+        }
         Ok(())
     }
 }
+
+/// The number of the rows printed on the terminal by default.
+const DEFAULT_LIMIT: usize = 10;
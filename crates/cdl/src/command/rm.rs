@@ -0,0 +1,32 @@
+use anyhow::{bail, Result};
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::GlobalPath;
+use clap::Parser;
+use tracing::{info, instrument};
+
+/// Remove the files matching the path or glob pattern from the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct RmArgs {
+    /// The path or glob pattern to remove (e.g. `s3://lake/tmp/**/*.log`).
+    pub target: GlobalPath,
+
+    /// Remove the directories and their contents.
+    #[arg(short, long)]
+    pub recursive: bool,
+}
+
+impl RmArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let path = self.target.rel.to_string_lossy().to_string();
+        let fs = self.target.clone().open(catalog).await?;
+        match fs.remove(&path, self.recursive).await? {
+            0 => bail!("No such file or directory: {target}", target = self.target),
+            count => {
+                info!("Removed {count} entries");
+                Ok(())
+            }
+        }
+    }
+}
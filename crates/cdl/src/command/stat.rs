@@ -0,0 +1,82 @@
+use anyhow::{bail, Result};
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::{FileType, GlobalPath};
+use clap::Parser;
+use futures::TryStreamExt;
+use tracing::instrument;
+
+use super::{entry_path, format_mode, format_size, read_entry};
+
+/// Print the metadata of the given file in the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct StatArgs {
+    pub target: GlobalPath,
+}
+
+impl StatArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let path = entry_path(&self.target.rel)?;
+        let fs = self.target.clone().open(catalog).await?;
+
+        let metadata = match read_entry(&fs, &path).await? {
+            Some(file) => file.metadata,
+            None => None,
+        };
+        let Some(metadata) = metadata else {
+            // The directories may be stored implicitly by their contents
+            let mut stream = Box::pin(fs.read_metadata(&path).await?);
+            let mut found = path.is_empty();
+            while !found {
+                let Some(files) = stream.try_next().await? else {
+                    break;
+                };
+                found = files.iter().any(|file| file.parent == path);
+            }
+            if found {
+                println!("  File: {path}/");
+                println!("  Type: {dir}", dir = FileType::Dir);
+                return Ok(());
+            }
+            bail!("No such file or directory: {target}", target = self.target)
+        };
+
+        let owner = |id: Option<u32>| id.map(|id| id.to_string()).unwrap_or_else(|| "-".into());
+        println!("  File: {path}");
+        println!(
+            "  Size: {size} ({human})",
+            size = metadata.size,
+            human = format_size(metadata.size, true),
+        );
+        println!("  Type: {kind}", kind = metadata.file_type);
+        println!(
+            "  Mode: {mode:04o} ({perms})",
+            mode = metadata.mode & 0o7777,
+            perms = format_mode(&metadata),
+        );
+        println!(
+            "   Uid: {uid}  Gid: {gid}",
+            uid = owner(metadata.uid),
+            gid = owner(metadata.gid),
+        );
+        if let Some(target) = metadata.link_target.as_ref() {
+            println!("  Link: {target}");
+        }
+        if !metadata.xattrs.is_empty() {
+            println!(
+                "Xattrs: {names}",
+                names = metadata
+                    .xattrs
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+        println!("Access: {atime}", atime = metadata.atime);
+        println!("Modify: {mtime}", mtime = metadata.mtime);
+        println!("Change: {ctime}", ctime = metadata.ctime);
+        Ok(())
+    }
+}
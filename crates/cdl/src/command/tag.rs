@@ -0,0 +1,62 @@
+use anyhow::Result;
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::GlobalPath;
+use clap::{Parser, Subcommand};
+use tracing::{info, instrument};
+
+/// Manage the named versions of the dataset
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct TagArgs {
+    #[command(subcommand)]
+    pub command: TagCommand,
+}
+
+#[derive(Clone, Debug, PartialEq, Subcommand)]
+pub enum TagCommand {
+    /// Tag the given version, or the selected one of the target (e.g. `s3://lake#v12`)
+    Create {
+        target: GlobalPath,
+        name: String,
+
+        /// The version to tag.
+        #[arg(long)]
+        version: Option<u64>,
+    },
+    /// List the tags with their versions
+    List { target: GlobalPath },
+    /// Delete the tag, leaving its version to be cleaned up
+    Delete { target: GlobalPath, name: String },
+}
+
+impl TagArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        match self.command {
+            TagCommand::Create {
+                target,
+                name,
+                version,
+            } => {
+                let fs = target.open(catalog).await?;
+                let tag = fs.create_tag(&name, version).await?;
+                info!("Tagged v{version} as #{name}", version = tag.version);
+                Ok(())
+            }
+            TagCommand::List { target } => {
+                let fs = target.open(catalog).await?;
+                println!("TAG\tVERSION");
+                for tag in fs.tags().await? {
+                    println!("{name}\tv{version}", name = tag.name, version = tag.version);
+                }
+                Ok(())
+            }
+            TagCommand::Delete { target, name } => {
+                let fs = target.open(catalog).await?;
+                fs.delete_tag(&name).await?;
+                info!("Deleted #{name}");
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::{bail, Result};
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::GlobalPath;
+use clap::Parser;
+use tracing::{info, instrument};
+
+/// Verify the checksums and sizes of the dataset's files
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct VerifyArgs {
+    pub target: GlobalPath,
+}
+
+impl VerifyArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let fs = self.target.clone().open(catalog).await?;
+        let report = fs.verify().await?;
+        for issue in &report.issues {
+            println!("{issue}");
+        }
+
+        info!(
+            "Verified {files} files ({chunks} chunks, {bytes} bytes)",
+            files = report.files,
+            chunks = report.chunks,
+            bytes = report.bytes,
+        );
+        if !report.issues.is_empty() {
+            bail!(
+                "Found {count} issues in {target}",
+                count = report.issues.len(),
+                target = self.target,
+            );
+        }
+        Ok(())
+    }
+}
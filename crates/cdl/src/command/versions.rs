@@ -0,0 +1,29 @@
+use anyhow::Result;
+use cdl_catalog::DatasetCatalog;
+use cdl_fs::GlobalPath;
+use clap::Parser;
+use tracing::instrument;
+
+/// List the versions of the dataset with their commit times and row counts
+///
+#[derive(Clone, Debug, PartialEq, Parser)]
+pub struct VersionsArgs {
+    pub target: GlobalPath,
+}
+
+impl VersionsArgs {
+    #[instrument(skip_all)]
+    pub(super) async fn execute(self, catalog: DatasetCatalog) -> Result<()> {
+        let fs = self.target.open(catalog).await?;
+        println!("VERSION\tTIMESTAMP\tROWS");
+        for version in fs.versions().await? {
+            println!(
+                "v{version}\t{timestamp}\t{rows}",
+                version = version.version,
+                timestamp = version.timestamp.to_rfc3339(),
+                rows = version.rows,
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-from typing import Any
+from typing import Any, Callable
 
 import pyarrow as pa
 
@@ -7,7 +7,14 @@ class CdlFS:
     dataset_uri: str
     global_path: str
 
-    def copy_to(self, dst: str, /) -> None: ...
+    def copy_to(
+        self,
+        dst: str,
+        /,
+        sync: bool = False,
+        restart: bool = False,
+        progress: Callable[[dict[str, Any]], None] | None = None,
+    ) -> None: ...
 
     def read_dir(self, path: str = '/', /) -> pa.RecordBatch: ...
 
@@ -15,10 +22,18 @@ class CdlFS:
 
     def read_files(self, /, condition: str) -> list[bytes]: ...
 
+    def remove(self, path: str, /, recursive: bool = False) -> int: ...
+
+    def rename(self, src: str, dst: str, /) -> int: ...
+
     def sql(self, sql: str, /) -> pa.RecordBatch: ...
 
     def storage_options(self) -> dict[str, str]: ...
 
+    def tags(self, /) -> dict[str, int]: ...
+
+    def version(self, /) -> int: ...
+
 
 class Cdl:
     def __init__(self, catalog: dict[str, Any], /) -> None: ...
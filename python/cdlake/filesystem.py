@@ -1,3 +1,5 @@
+from typing import Any, Callable
+
 import lance
 import pyarrow as pa
 
@@ -23,8 +25,19 @@ class CdlFS:
     def __init__(self, impl: _CdlFSImpl) -> None:
         self._impl = impl
 
-    def copy_to(self, dst: str) -> None:
-        return self._impl.copy_to(dst)
+    def copy_to(
+        self,
+        dst: str,
+        sync: bool = False,
+        restart: bool = False,
+        progress: Callable[[dict[str, Any]], None] | None = None,
+    ) -> None:
+        return self._impl.copy_to(
+            dst,
+            sync=sync,
+            restart=restart,
+            progress=progress,
+        )
 
     def read_dir(
         self,
@@ -35,6 +48,20 @@ class CdlFS:
     def read_dir_all(self) -> pa.RecordBatch:
         return self._impl.read_dir_all()
 
+    def remove(
+        self,
+        path: str,
+        recursive: bool = False,
+    ) -> int:
+        return self._impl.remove(path, recursive=recursive)
+
+    def rename(
+        self,
+        src: str,
+        dst: str,
+    ) -> int:
+        return self._impl.rename(src, dst)
+
     def sql(
         self,
         sql: str,
@@ -55,10 +82,17 @@ class CdlFS:
         df: pl.DataFrame = pl.from_arrow(self.sql(sql))  # type: ignore
         return df
 
+    def tags(self) -> dict[str, int]:
+        return self._impl.tags()
+
+    def version(self) -> int:
+        return self._impl.version()
+
     def to_lance_dataset(self, **kwargs) -> lance.LanceDataset:
         return lance.LanceDataset(
             storage_options=self._impl.storage_options(),
             uri=self._impl.dataset_uri,
+            version=self._impl.version(),
             **kwargs,
         )
 
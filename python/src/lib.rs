@@ -1,16 +1,20 @@
-use std::{collections::HashMap, future::Future, sync::OnceLock};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, OnceLock},
+};
 
 use anyhow::{anyhow, Context, Error, Result};
 use arrow::{array::RecordBatch, compute::concat_batches, pyarrow::PyArrowType};
 use cdl_catalog::DatasetCatalog;
-use cdl_fs::GlobalPath;
-use clap::Parser;
+use cdl_fs::{CopyOptions, GlobalPath, Progress, ProgressState};
+use clap::{CommandFactory, FromArgMatches};
 use datafusion::execution::SendableRecordBatchStream;
 use futures::{stream, TryFutureExt, TryStreamExt};
 use pyo3::{
     pyclass, pymethods, pymodule,
     types::{PyAnyMethods, PyDict, PyDictMethods, PyModule, PyStringMethods},
-    Bound, PyResult,
+    Bound, PyObject, PyResult, Python,
 };
 use tokio::runtime::Runtime;
 use tracing::debug;
@@ -29,11 +33,27 @@ impl Cdl {
     ))]
     fn new<'py>(catalog: Bound<'py, PyDict>) -> PyResult<Self> {
         let catalog = {
-            let mut merged =
-                DatasetCatalog::try_parse_from::<[_; 0], &str>([]).map_err(Error::from)?;
+            let matches = DatasetCatalog::command()
+                .try_get_matches_from::<[_; 0], &str>([])
+                .map_err(Error::from)?;
+            let mut merged = DatasetCatalog::from_arg_matches(&matches).map_err(Error::from)?;
+
+            let mut values = Vec::default();
             for (key, value) in catalog.iter() {
-                merged.merge(key.str()?.to_str()?, value.str()?.to_str()?)?;
+                let key = key.str()?.to_str()?.to_string();
+                let value = value.str()?.to_str()?.to_string();
+                values.push((key, value));
             }
+            let values = || {
+                values
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str()))
+            };
+
+            // The given values take precedence over the profile
+            merged.merge_iter(values())?;
+            merged.load_profile(&matches)?;
+            merged.merge_iter(values())?;
             merged
         };
 
@@ -71,10 +91,40 @@ impl CdlFS {
     #[pyo3(signature = (
         dst,
         /,
+        sync = false,
+        restart = false,
+        progress = None,
     ))]
-    fn copy_to(&self, dst: String) -> PyResult<()> {
+    fn copy_to(
+        &self,
+        py: Python<'_>,
+        dst: String,
+        sync: bool,
+        restart: bool,
+        progress: Option<PyObject>,
+    ) -> PyResult<()> {
         let dst: GlobalPath = dst.parse()?;
-        wrap_tokio(self.0.copy_to(&dst)).map_err(Into::into)
+        let progress = match progress {
+            Some(callback) => Progress::new(Arc::new(move |state: &ProgressState| {
+                Python::with_gil(|py| {
+                    let result =
+                        progress_to_dict(py, state).and_then(|state| callback.call1(py, (state,)));
+                    if let Err(error) = result {
+                        error.print(py);
+                    }
+                })
+            })),
+            None => Progress::default(),
+        };
+        let options = CopyOptions {
+            progress,
+            restart,
+            sync,
+        };
+
+        // Release the GIL so that the progress callback can be called
+        py.allow_threads(|| wrap_tokio(self.0.copy_to_with(&dst, options)))
+            .map_err(Into::into)
     }
 
     #[pyo3(signature = (
@@ -128,6 +178,24 @@ impl CdlFS {
         Err(anyhow!("").into())
     }
 
+    #[pyo3(signature = (
+        path,
+        /,
+        recursive = false,
+    ))]
+    fn remove(&self, path: &str, recursive: bool) -> PyResult<usize> {
+        wrap_tokio(self.0.remove(path, recursive)).map_err(Into::into)
+    }
+
+    #[pyo3(signature = (
+        src,
+        dst,
+        /,
+    ))]
+    fn rename(&self, src: &str, dst: &str) -> PyResult<usize> {
+        wrap_tokio(self.0.rename(src, dst)).map_err(Into::into)
+    }
+
     #[pyo3(signature = (
         sql,
         /,
@@ -142,14 +210,47 @@ impl CdlFS {
         .map_err(Into::into)
     }
 
+    #[pyo3(signature = (
+        /,
+    ))]
+    fn version(&self) -> PyResult<u64> {
+        wrap_tokio(self.0.version()).map_err(Into::into)
+    }
+
+    #[pyo3(signature = (
+        /,
+    ))]
+    fn tags(&self) -> PyResult<HashMap<String, u64>> {
+        wrap_tokio(self.0.tags())
+            .map(|tags| {
+                tags.into_iter()
+                    .map(|tag| (tag.name, tag.version))
+                    .collect()
+            })
+            .map_err(Into::into)
+    }
+
     #[pyo3(signature = (
         /,
     ))]
     fn storage_options(&self) -> PyResult<HashMap<String, String>> {
-        self.0.catalog().storage_options(true).map_err(Into::into)
+        wrap_tokio(self.0.catalog().storage_options_with_credentials()).map_err(Into::into)
     }
 }
 
+fn progress_to_dict<'py>(py: Python<'py>, state: &ProgressState) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("files", state.files)?;
+    dict.set_item("bytes", state.bytes)?;
+    dict.set_item("fragments", state.fragments)?;
+    dict.set_item("total_files", state.total_files)?;
+    dict.set_item("total_bytes", state.total_bytes)?;
+    dict.set_item("elapsed", state.elapsed.as_secs_f64())?;
+    dict.set_item("throughput", state.throughput())?;
+    dict.set_item("eta", state.eta().map(|eta| eta.as_secs_f64()))?;
+    Ok(dict)
+}
+
 async fn collect_batches(
     stream: SendableRecordBatchStream,
     kind: &'static str,